@@ -1,4 +1,5 @@
 use std::path::PathBuf;
+use serun::bus::{Bus, NesBus};
 use serun::cpu;
 use serun::cartridge;
 use eframe::egui;
@@ -12,16 +13,22 @@ use std::cmp;
 const DEBUG_ADDRS: usize = 11;
 const MEM_LEN: usize = 0x10000;
 
-pub fn run_debugger(path: PathBuf) -> eframe::Result {
+pub fn run_debugger(path: PathBuf, bios: Option<PathBuf>) -> eframe::Result {
     let (tx, rx) = mpsc::channel();
 
     thread::spawn(move || {
-        let mut cpu = cpu::CPU::default();
-        let cart = cartridge::Cartidge::from_path(path).unwrap();
-        cpu.load_program(cart.prg_rom);
+        let cart = match bios {
+            Some(bios) => cartridge::Cartidge::from_fds_path(path, bios),
+            None => cartridge::Cartidge::from_path(path),
+        }.unwrap();
+        for correction in &cart.header_corrections {
+            println!("Header fixed by game database: {correction}");
+        }
+        let mut cpu = cpu::CPU::new(NesBus::new(cart));
+        cpu.reset();
         // TODO: Find better way to run cpu and send info across threads
         loop {
-            cpu.execute_instruction();
+            let result = cpu.execute_instruction();
             let pc = cpu.pc as usize;
             let min_addr = cmp::min(
                 MEM_LEN - DEBUG_ADDRS,
@@ -31,8 +38,16 @@ pub fn run_debugger(path: PathBuf) -> eframe::Result {
                 MEM_LEN,
                 min_addr + DEBUG_ADDRS,
             );
-            let memory = cpu.memory.raw_memory[min_addr..max_addr].to_vec();
-            tx.send(CpuSnapshot::from_cpu(&cpu, memory)).unwrap();
+            let memory = (min_addr..max_addr).map(|addr| cpu.bus.peek(addr as u16)).collect();
+            let mut snapshot = CpuSnapshot::from_cpu(&cpu, memory);
+            // Stop stepping once the CPU errors out, but keep the window open
+            // so the error can be looked at
+            if let Err(error) = result {
+                snapshot.error = Some(error.to_string());
+                tx.send(snapshot).unwrap();
+                break;
+            }
+            tx.send(snapshot).unwrap();
             thread::sleep(std::time::Duration::from_millis(1));
         }
     });
@@ -58,11 +73,12 @@ struct CpuSnapshot {
     stack_pointer: u8,
     pc: u16,
     status: u8,
-    memory: Vec<u8>
+    memory: Vec<u8>,
+    error: Option<String>
 }
 
 impl CpuSnapshot {
-    fn from_cpu(data: &cpu::CPU, memory: Vec<u8>) -> CpuSnapshot {
+    fn from_cpu<B: Bus>(data: &cpu::CPU<B>, memory: Vec<u8>) -> CpuSnapshot {
         Self {
             register_a: data.register_a,
             register_x: data.register_x,
@@ -70,7 +86,8 @@ impl CpuSnapshot {
             stack_pointer: data.stack_pointer,
             pc: data.pc,
             status: data.status,
-            memory
+            memory,
+            error: None
         }
     }
 }
@@ -110,6 +127,10 @@ impl eframe::App for CpuReceiver {
                     });
                 });
 
+                if let Some(error) = &self.state.error {
+                    ui.label(RichText::new(error).color(Color32::RED));
+                }
+
                 ui.add_space(10.0);
                 ui.separator();
                 ui.add_space(10.0);
@@ -18,34 +18,43 @@ enum Commands {
     Test {
         /// The test suite to run.
         #[arg(short, long, value_enum)]
-        test_suite: AvailableTests
+        test_suite: AvailableTests,
+        /// Directory containing the test files, for suites that read them from one
+        #[arg(short, long, value_name = "DIR")]
+        path: Option<PathBuf>,
     },
-    /// Runs the debugger given a path to a .nes file
+    /// Runs the debugger given a path to a .nes, .unf or .fds file
     Run {
-        /// Path to .nes file
+        /// Path to .nes, .unf or .fds file
         #[arg(short, long, value_name = "FILE")]
         path: PathBuf,
+        /// Path to the FDS BIOS, needed to run .fds files
+        #[arg(short, long, value_name = "FILE")]
+        bios: Option<PathBuf>,
     },
 }
 
 #[derive(Clone, ValueEnum)]
 enum AvailableTests {
-    Cpu
+    Cpu,
+    Nestest
 }
 
 fn main() {
     let cli = Cli::parse();
 
     match &cli.command {
-        Some(Commands::Run { path }) => {
+        Some(Commands::Run { path, bios }) => {
             // TODO display err message if debugger errors out
-            let _ = debugger::run_debugger(path.to_owned());
+            let _ = debugger::run_debugger(path.to_owned(), bios.to_owned());
         },
-        Some(Commands::Test { test_suite }) => {
-            match test_suite {
-                AvailableTests::Cpu => {
-                    suites::cpu::run_tests();
-                }
+        Some(Commands::Test { test_suite, path }) => {
+            let passed = match test_suite {
+                AvailableTests::Cpu => suites::cpu::run_tests(path.as_deref()),
+                AvailableTests::Nestest => suites::nestest::run_tests(),
+            };
+            if !passed {
+                std::process::exit(1);
             }
         },
         None => {}
@@ -1 +1,2 @@
-pub mod cpu;
\ No newline at end of file
+pub mod cpu;
+pub mod nestest;
\ No newline at end of file
@@ -1,7 +1,19 @@
 use serde::{Deserialize, Serialize};
-use std::fs::File;
+use std::fs::{self, File};
 use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use serun::bus::{Bus, BusAccess, BusAccessKind, RecordingBus};
 use serun::cpu;
+use serun::memory::Memory;
+use serun::opcodes::{CPU_OPCODES, Opcode};
+
+// Where the per-opcode JSON files from SingleStepTests (nes6502) are expected
+// to be, named after the opcode they test (00.json through ff.json)
+const DEFAULT_TESTS_DIR: &str = "./tests/json";
+
+// The bus records every access so they can be checked against the "cycles"
+// of each scenario
+type TestCpu = cpu::CPU<RecordingBus<Memory>>;
 
 #[derive(Serialize, Deserialize, Debug)]
 struct CpuState {
@@ -23,37 +35,198 @@ struct TestScenario {
     cycles: Vec<(u16, u8, String)>
 }
 
-// TODO improve how this is parsing files (account more for errors)
-fn get_test_scenarios(path: &str) -> Vec<TestScenario> {
-    let file = File::open(path).unwrap();
+// Results for all scenarios in a single test file
+struct OpcodeResults {
+    name: String,
+    passed: usize,
+    failed: usize,
+    // Name of the first failing scenario and what didn't match
+    first_failure: Option<(String, String)>
+}
+
+fn get_test_files(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries = fs::read_dir(dir).map_err(|error| format!("Failed to read {}: {error}", dir.display()))?;
+    let mut files: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension().is_some_and(|extension| extension == "json"))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+fn get_test_scenarios(path: &Path) -> Result<Vec<TestScenario>, String> {
+    let file = File::open(path).map_err(|error| format!("Failed to open {}: {error}", path.display()))?;
     let reader = BufReader::new(file);
-    let serialized: Vec<TestScenario> = serde_json::from_reader(reader).unwrap();
-    serialized
+    serde_json::from_reader(reader).map_err(|error| format!("Failed to parse {}: {error}", path.display()))
+}
+
+fn get_file_name(path: &Path) -> String {
+    path.file_stem().map_or_else(String::new, |stem| stem.to_string_lossy().into_owned())
 }
 
-fn load_test_scenario(cpu: &mut cpu::CPU, scenario: &TestScenario) {
+// Opcode a test file covers, going by its name
+fn get_file_opcode(path: &Path) -> Option<u8> {
+    u8::from_str_radix(&get_file_name(path), 16).ok()
+}
+
+fn load_test_scenario(cpu: &mut TestCpu, scenario: &TestScenario) {
+    // Clears out anything left over from the previous scenario, like a pending
+    // interrupt or a jammed CPU
+    cpu.reset();
     cpu.pc = scenario.initial.pc;
+    cpu.stack_pointer = scenario.initial.s;
     cpu.register_a = scenario.initial.a;
-    cpu.register_a = scenario.initial.x;
-    cpu.status = scenario.initial.p;
+    cpu.register_x = scenario.initial.x;
     cpu.register_y = scenario.initial.y;
+    cpu.status = scenario.initial.p;
 
     for (addr, val) in &scenario.initial.ram {
-        cpu.memory.write(*addr, *val);
+        cpu.bus.inner.write(*addr, *val);
     }
+    cpu.bus.accesses.clear();
 }
 
-fn verify_test_results(cpu: &cpu::CPU, scenario: &TestScenario) {
-    // TODO: Implement
+// Zeroes out all memory the scenario could have touched, so the next one
+// starts from a clean slate
+fn unload_test_scenario(cpu: &mut TestCpu, scenario: &TestScenario) {
+    let accessed = cpu.bus.accesses.iter().map(|access| access.addr);
+    let listed = scenario.initial.ram.iter().chain(&scenario.r#final.ram).map(|(addr, _)| *addr);
+    for addr in listed.chain(accessed).collect::<Vec<_>>() {
+        cpu.bus.inner.write(addr, 0);
+    }
 }
 
-pub fn run_tests() {
-    let mut cpu = cpu::CPU::default();
-    let scenarios = get_test_scenarios("./tests/json/00.json");
+fn compare_field(field: &str, expected: u16, actual: u16) -> Result<(), String> {
+    if expected != actual {
+        return Err(format!("{field} expected {expected:02X}, got {actual:02X}"));
+    }
+    Ok(())
+}
 
-    for scenario in scenarios {
-        load_test_scenario(&mut cpu, &scenario);
-        verify_test_results(&cpu, &scenario);
-        cpu.memory.raw_memory.clear();
+// Checks the CPU against the final state of the scenario, reporting the first
+// field that doesn't match
+fn verify_test_results(cpu: &TestCpu, scenario: &TestScenario) -> Result<(), String> {
+    let expected = &scenario.r#final;
+    compare_field("pc", expected.pc, cpu.pc)?;
+    compare_field("s", expected.s as u16, cpu.stack_pointer as u16)?;
+    compare_field("a", expected.a as u16, cpu.register_a as u16)?;
+    compare_field("x", expected.x as u16, cpu.register_x as u16)?;
+    compare_field("y", expected.y as u16, cpu.register_y as u16)?;
+    compare_field("p", expected.p as u16, cpu.status as u16)?;
+    for (addr, val) in &expected.ram {
+        compare_field(&format!("ram[{addr:04X}]"), *val as u16, cpu.bus.peek(*addr) as u16)?;
     }
-}
\ No newline at end of file
+    verify_bus_accesses(&cpu.bus.accesses, &scenario.cycles)
+}
+
+fn format_bus_access(addr: u16, data: u8, kind: &str) -> String {
+    format!("{kind} {data:02X} at {addr:04X}")
+}
+
+// Checks that the CPU made the same bus accesses as the real one, cycle by
+// cycle, including all the dummy reads and writes
+fn verify_bus_accesses(accesses: &[BusAccess], expected: &[(u16, u8, String)]) -> Result<(), String> {
+    for (cycle, (access, (addr, data, kind))) in accesses.iter().zip(expected).enumerate() {
+        let actual_kind = match access.kind {
+            BusAccessKind::Read => "read",
+            BusAccessKind::Write => "write",
+        };
+        if access.addr != *addr || access.data != *data || actual_kind != kind {
+            return Err(format!(
+                "cycle {cycle} expected {}, got {}",
+                format_bus_access(*addr, *data, kind),
+                format_bus_access(access.addr, access.data, actual_kind)
+            ));
+        }
+    }
+    if accesses.len() != expected.len() {
+        return Err(format!("expected {} bus accesses, got {}", expected.len(), accesses.len()));
+    }
+    Ok(())
+}
+
+fn run_test_scenario(cpu: &mut TestCpu, scenario: &TestScenario) -> Result<(), String> {
+    load_test_scenario(cpu, scenario);
+    let result = cpu.execute_instruction()
+        .map_err(|error| format!("CPU error: {error}"))
+        .and_then(|_| verify_test_results(cpu, scenario));
+    unload_test_scenario(cpu, scenario);
+    result
+}
+
+fn run_test_file(cpu: &mut TestCpu, path: &Path) -> Result<OpcodeResults, String> {
+    let scenarios = get_test_scenarios(path)?;
+    let mut results = OpcodeResults {
+        name: get_file_name(path),
+        passed: 0,
+        failed: 0,
+        first_failure: None
+    };
+
+    for scenario in &scenarios {
+        match run_test_scenario(cpu, scenario) {
+            Ok(()) => results.passed += 1,
+            Err(mismatch) => {
+                results.failed += 1;
+                results.first_failure.get_or_insert((scenario.name.clone(), mismatch));
+            }
+        }
+    }
+    Ok(results)
+}
+
+// Runs every test file in `dir` (or the default test directory), returning
+// whether they all passed
+pub fn run_tests(dir: Option<&Path>) -> bool {
+    let dir = dir.unwrap_or(Path::new(DEFAULT_TESTS_DIR));
+    let files = match get_test_files(dir) {
+        Ok(files) if files.is_empty() => {
+            println!("No test files found in {}", dir.display());
+            return false;
+        }
+        Ok(files) => files,
+        Err(error) => {
+            println!("{error}");
+            return false;
+        }
+    };
+
+    let mut cpu = TestCpu::default();
+    let mut total_passed = 0;
+    let mut total_failed = 0;
+    let mut failed_files = 0;
+    for path in &files {
+        // JAM locks up the CPU rather than executing, so there's nothing to check
+        let is_jam = get_file_opcode(path)
+            .and_then(|opcode| CPU_OPCODES.get(&opcode))
+            .is_some_and(|instruction| matches!(instruction.opcode, Opcode::JAM));
+        if is_jam {
+            println!("{}: skipped (JAM)", get_file_name(path));
+            continue;
+        }
+
+        match run_test_file(&mut cpu, path) {
+            Ok(results) => {
+                total_passed += results.passed;
+                total_failed += results.failed;
+                match &results.first_failure {
+                    None => println!("{}: {} passed", results.name, results.passed),
+                    Some((scenario, mismatch)) => {
+                        failed_files += 1;
+                        println!(
+                            "{}: {} passed, {} failed (first failure \"{scenario}\": {mismatch})",
+                            results.name, results.passed, results.failed
+                        );
+                    }
+                }
+            }
+            Err(error) => {
+                failed_files += 1;
+                println!("{error}");
+            }
+        }
+    }
+
+    println!("{total_passed} passed, {total_failed} failed, {failed_files} of {} files had failures", files.len());
+    failed_files == 0
+}
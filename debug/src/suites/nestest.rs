@@ -0,0 +1,85 @@
+use std::cell::RefCell;
+use std::fs;
+use std::io;
+use std::rc::Rc;
+use serun::bus::{Bus, NesBus};
+use serun::cartridge::Cartidge;
+use serun::cpu;
+use serun::cpu::trace::{self, TraceRecord};
+
+const ROM_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/nestest.nes");
+// Nintendulator's trace of nestest, which has to be put next to the ROM. Without
+// it only the result codes get checked.
+const LOG_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/nestest.log");
+// Instructions in the reference log, which end with nestest returning to
+// where automation mode would have called it from
+const LOG_LINES: usize = 8991;
+// Starting at $C000 runs nestest in automation mode, which goes through every
+// test without needing a PPU or controller input
+const AUTOMATION_START: u16 = 0xC000;
+// nestest leaves an error code for the official and unofficial opcode tests here
+const RESULT_ADDRS: [u16; 2] = [0x0002, 0x0003];
+
+pub fn run_tests() -> bool {
+    let cartridge = match Cartidge::from_path(ROM_PATH.into()) {
+        Ok(cartridge) => cartridge,
+        Err(error) => {
+            println!("Failed to load {ROM_PATH}: {error}");
+            return false;
+        }
+    };
+    let reference_log = match fs::read_to_string(LOG_PATH) {
+        Ok(log) => Some(log),
+        Err(error) if error.kind() == io::ErrorKind::NotFound => {
+            println!("No reference log at {LOG_PATH}, only checking the result codes");
+            None
+        }
+        Err(error) => {
+            println!("Failed to read reference log {LOG_PATH}: {error}");
+            return false;
+        }
+    };
+    let expected_lines: Vec<&str> = reference_log.as_deref().map(|log| log.lines().collect()).unwrap_or_default();
+    let line_count = if reference_log.is_some() { expected_lines.len() } else { LOG_LINES };
+
+    let mut cpu = cpu::CPU::new(NesBus::new(cartridge));
+    cpu.reset();
+    cpu.pc = AUTOMATION_START;
+
+    let lines = Rc::new(RefCell::new(Vec::new()));
+    let trace_lines = Rc::clone(&lines);
+    cpu.set_tracer(Box::new(move |record: &TraceRecord, bus: &dyn Bus| {
+        trace_lines.borrow_mut().push(trace::format_nestest(record, bus));
+    }));
+
+    while lines.borrow().len() < line_count {
+        if let Err(error) = cpu.execute_instruction() {
+            println!("CPU error after {} instructions: {error}", lines.borrow().len());
+            break;
+        }
+    }
+
+    let lines = lines.borrow();
+    for (line_number, (actual, expected)) in lines.iter().zip(&expected_lines).enumerate() {
+        if actual != expected {
+            println!("Trace diverged from {LOG_PATH} at line {}", line_number + 1);
+            if line_number > 0 {
+                println!("  previous: {}", expected_lines[line_number - 1]);
+            }
+            println!("  expected: {expected}");
+            println!("  actual:   {actual}");
+            return false;
+        }
+    }
+    if lines.len() < line_count {
+        println!("Trace stopped after {} of {line_count} lines", lines.len());
+        return false;
+    }
+
+    let results = RESULT_ADDRS.map(|addr| cpu.bus.peek(addr));
+    if reference_log.is_some() {
+        println!("All {line_count} lines match {LOG_PATH}");
+    }
+    println!("Result codes: official {:02X}, unofficial {:02X}", results[0], results[1]);
+    results == [0, 0]
+}
@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::Path;
+use std::{env, fs};
+
+// The NES 2.0 XML database, vendored along with its license. Without it the
+// database is empty and headers are taken at their word.
+const DATABASE_PATH: &str = "data/nes20db.xml";
+// A few test ROMs in the same format, for the tests to check the generated
+// code against
+const TEST_DATABASE_PATH: &str = "data/test-roms.xml";
+
+// Generates the game database, and the one the tests use, from XML files in
+// the format of the NES 2.0 XML database. Each <game> looks like:
+//   <game>
+//     <!-- Folder\Name.nes -->
+//     <prgrom .../> <chrrom .../>
+//     <rom size="..." crc32="..." sha1="..."/>
+//     <prgram size="..."/> <prgnvram .../> <chrram .../> <chrnvram .../>
+//     <pcb mapper="..." submapper="..." mirroring="H|V|4|1" battery="..."/>
+//     <console type="..." region="..."/>
+//   </game>
+// The RAM elements are left out when there isn't any. The results are
+// phf_map!s that cartridge/database.rs includes.
+fn main() {
+    // Rather than the files themselves, so that adding the database later
+    // gets noticed
+    println!("cargo::rerun-if-changed=data");
+    let out_dir = env::var("OUT_DIR").unwrap();
+
+    let xml = fs::read_to_string(DATABASE_PATH).unwrap_or_else(|e| {
+        println!("cargo::warning=Can't read {DATABASE_PATH} ({e}), headers won't get corrected");
+        String::new()
+    });
+    fs::write(Path::new(&out_dir).join("games.rs"), games(&xml)).unwrap();
+
+    let xml = fs::read_to_string(TEST_DATABASE_PATH).unwrap_or_else(|e| panic!("Can't read {TEST_DATABASE_PATH}: {e}"));
+    fs::write(Path::new(&out_dir).join("test_games.rs"), games(&xml)).unwrap();
+}
+
+// The phf_map! of every game in a database
+fn games(xml: &str) -> String {
+    let mut crcs = HashSet::new();
+    let mut games = String::from("phf_map! {\n");
+    for game in xml.split("<game>").skip(1) {
+        let game = game.split("</game>").next().unwrap_or_default();
+        // Dumps listed twice are the same game, the first one is kept
+        if let Some((crc32, entry)) = game_entry(game)
+            && crcs.insert(crc32) {
+            games.push_str(&entry);
+        }
+    }
+    games.push('}');
+    games
+}
+
+// The value of `name` on the first `element` in `game`
+fn attribute<'a>(game: &'a str, element: &str, name: &str) -> Option<&'a str> {
+    let start = game.find(&format!("<{element} "))?;
+    let tag = &game[start..];
+    let tag = &tag[..tag.find('>')?];
+    let value = &tag[tag.find(&format!(" {name}=\""))? + name.len() + 3..];
+    Some(&value[..value.find('"')?])
+}
+
+fn size(game: &str, element: &str) -> usize {
+    attribute(game, element, "size").and_then(|size| size.parse().ok()).unwrap_or(0)
+}
+
+// The CRC-32 of a <game> and the phf_map! entry for it, or None for games
+// missing what the emulator needs to know
+fn game_entry(game: &str) -> Option<(u32, String)> {
+    // The comment is the path of the dump, in its folder of the database
+    let comment = &game[game.find("<!--")? + 4..game.find("-->")?];
+    let file = comment.trim().rsplit('\\').next()?;
+    let name = file.strip_suffix(".nes").unwrap_or(file);
+
+    let crc32 = u32::from_str_radix(attribute(game, "rom", "crc32")?, 16).ok()?;
+    let sha1 = attribute(game, "rom", "sha1")?;
+    if sha1.len() != 40 {
+        return None;
+    }
+    let sha1: Vec<_> = (0..40).step_by(2).map(|i| u8::from_str_radix(&sha1[i..i + 2], 16).map(|byte| format!("0x{byte:02X}"))).collect::<Result<_, _>>().ok()?;
+    let mapper: u16 = attribute(game, "pcb", "mapper")?.parse().ok()?;
+    let submapper: u8 = attribute(game, "pcb", "submapper").and_then(|submapper| submapper.parse().ok()).unwrap_or(0);
+    let mirroring = match attribute(game, "pcb", "mirroring") {
+        Some("H") => "Some(Mirroring::Horizontal)",
+        Some("V") => "Some(Mirroring::Vertical)",
+        Some("4") => "Some(Mirroring::FourScreen)",
+        // "1" is for mapper controlled mirroring, where the header's is as
+        // good as any
+        _ => "None",
+    };
+    let tv_system = match attribute(game, "console", "region") {
+        Some("1") => "Pal",
+        Some("2") => "MultiRegion",
+        Some("3") => "Dendy",
+        _ => "Ntsc",
+    };
+
+    let mut entry = String::new();
+    writeln!(entry, "    0x{crc32:08X}u32 => GameEntry {{").unwrap();
+    writeln!(entry, "        name: {name:?},").unwrap();
+    writeln!(entry, "        sha1: [{}],", sha1.join(", ")).unwrap();
+    writeln!(entry, "        mapper: {mapper},").unwrap();
+    writeln!(entry, "        submapper: {submapper},").unwrap();
+    writeln!(entry, "        mirroring: {mirroring},").unwrap();
+    writeln!(entry, "        prg_ram_size: {},", size(game, "prgram")).unwrap();
+    writeln!(entry, "        prg_nvram_size: {},", size(game, "prgnvram")).unwrap();
+    writeln!(entry, "        chr_ram_size: {},", size(game, "chrram")).unwrap();
+    writeln!(entry, "        chr_nvram_size: {},", size(game, "chrnvram")).unwrap();
+    writeln!(entry, "        tv_system: TvSystem::{tv_system},").unwrap();
+    writeln!(entry, "    }},").unwrap();
+    Some((crc32, entry))
+}
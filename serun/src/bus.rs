@@ -0,0 +1,212 @@
+use crate::cartridge::Cartidge;
+
+// Anything the CPU can be attached to. This is implemented by the flat 64 KiB
+// `Memory` used in unit tests, and by anything else that wants to sit on the
+// CPU's address space (the NES memory map, test harnesses, etc).
+pub trait Bus {
+    fn read(&mut self, addr: u16) -> u8;
+
+    fn write(&mut self, addr: u16, data: u8);
+
+    // Reads a value without triggering any side effects that a real read would
+    // have (e.g. clearing a register on read). Used by debuggers and tracers.
+    fn peek(&self, addr: u16) -> u8;
+
+    // Whether something on the bus (like a mapper's scanline counter) is
+    // pulling the CPU's IRQ line low
+    fn irq(&self) -> bool {
+        false
+    }
+
+    fn read_u16(&mut self, pos: u16) -> u16 {
+        let lo = self.read(pos) as u16;
+        let hi = self.read(pos.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    fn peek_u16(&self, pos: u16) -> u16 {
+        let lo = self.peek(pos) as u16;
+        let hi = self.peek(pos.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+}
+
+const RAM_SIZE: usize = 0x0800;
+const RAM_END: u16 = 0x1FFF;
+const PPU_REGISTERS_START: u16 = 0x2000;
+const PPU_REGISTERS_END: u16 = 0x3FFF;
+const PPU_REGISTER_COUNT: usize = 8;
+const APU_IO_REGISTERS_START: u16 = 0x4000;
+const APU_IO_REGISTERS_END: u16 = 0x4017;
+const APU_IO_REGISTER_COUNT: usize = 0x18;
+const APU_TEST_REGISTERS_START: u16 = 0x4018;
+const APU_TEST_REGISTERS_END: u16 = 0x401F;
+
+// The CPU memory map of the NES:
+//   $0000-$07FF  2 KiB internal RAM, mirrored up to $1FFF
+//   $2000-$2007  PPU registers, mirrored every 8 bytes up to $3FFF
+//   $4000-$4017  APU and I/O registers
+//   $4018-$401F  APU test registers (disabled on retail units)
+//   $4020-$FFFF  Cartridge space, handled by the cartridge's mapper
+pub struct NesBus {
+    ram: [u8; RAM_SIZE],
+    // TODO: Hand these off to the PPU and APU once they exist. For now they
+    // simply latch whatever was last written to them.
+    ppu_registers: [u8; PPU_REGISTER_COUNT],
+    apu_io_registers: [u8; APU_IO_REGISTER_COUNT],
+    cartridge: Option<Cartidge>,
+    // Value last seen on the data bus, returned when reading unmapped addresses
+    open_bus: u8,
+}
+
+impl Default for NesBus {
+    fn default() -> Self {
+        NesBus {
+            ram: [0; RAM_SIZE],
+            ppu_registers: [0; PPU_REGISTER_COUNT],
+            apu_io_registers: [0; APU_IO_REGISTER_COUNT],
+            cartridge: None,
+            open_bus: 0,
+        }
+    }
+}
+
+impl NesBus {
+    pub fn new(cartridge: Cartidge) -> Self {
+        NesBus {
+            cartridge: Some(cartridge),
+            ..Default::default()
+        }
+    }
+
+    pub fn insert_cartridge(&mut self, cartridge: Cartidge) {
+        self.cartridge = Some(cartridge);
+    }
+
+    pub fn remove_cartridge(&mut self) -> Option<Cartidge> {
+        self.cartridge.take()
+    }
+
+    pub fn cartridge(&self) -> Option<&Cartidge> {
+        self.cartridge.as_ref()
+    }
+
+    fn ppu_register_index(addr: u16) -> usize {
+        (addr as usize - PPU_REGISTERS_START as usize) % PPU_REGISTER_COUNT
+    }
+
+    fn apu_io_register_index(addr: u16) -> usize {
+        addr as usize - APU_IO_REGISTERS_START as usize
+    }
+
+    // The CPU makes exactly one bus access every cycle, so this is where the
+    // cartridge finds out about the passing of time
+    fn clock_cartridge(&mut self) {
+        if let Some(cartridge) = &mut self.cartridge {
+            cartridge.cpu_clock();
+        }
+    }
+}
+
+impl Bus for NesBus {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.clock_cartridge();
+        let data = match addr {
+            0..=RAM_END => self.ram[addr as usize % RAM_SIZE],
+            PPU_REGISTERS_START..=PPU_REGISTERS_END => self.ppu_registers[Self::ppu_register_index(addr)],
+            APU_IO_REGISTERS_START..=APU_IO_REGISTERS_END => self.apu_io_registers[Self::apu_io_register_index(addr)],
+            APU_TEST_REGISTERS_START..=APU_TEST_REGISTERS_END => self.open_bus,
+            _ => match &mut self.cartridge {
+                Some(cartridge) => cartridge.cpu_read(addr).unwrap_or(self.open_bus),
+                None => self.open_bus,
+            },
+        };
+        self.open_bus = data;
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.clock_cartridge();
+        self.open_bus = data;
+        match addr {
+            0..=RAM_END => self.ram[addr as usize % RAM_SIZE] = data,
+            PPU_REGISTERS_START..=PPU_REGISTERS_END => self.ppu_registers[Self::ppu_register_index(addr)] = data,
+            APU_IO_REGISTERS_START..=APU_IO_REGISTERS_END => self.apu_io_registers[Self::apu_io_register_index(addr)] = data,
+            APU_TEST_REGISTERS_START..=APU_TEST_REGISTERS_END => {},
+            _ => {
+                if let Some(cartridge) = &mut self.cartridge {
+                    cartridge.cpu_write(addr, data);
+                }
+            }
+        }
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0..=RAM_END => self.ram[addr as usize % RAM_SIZE],
+            PPU_REGISTERS_START..=PPU_REGISTERS_END => self.ppu_registers[Self::ppu_register_index(addr)],
+            APU_IO_REGISTERS_START..=APU_IO_REGISTERS_END => self.apu_io_registers[Self::apu_io_register_index(addr)],
+            APU_TEST_REGISTERS_START..=APU_TEST_REGISTERS_END => self.open_bus,
+            _ => match &self.cartridge {
+                Some(cartridge) => cartridge.cpu_peek(addr).unwrap_or(self.open_bus),
+                None => self.open_bus,
+            },
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.cartridge.as_ref().is_some_and(|cartridge| cartridge.irq())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BusAccessKind {
+    Read,
+    Write,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BusAccess {
+    pub addr: u16,
+    pub data: u8,
+    pub kind: BusAccessKind,
+}
+
+// Wraps another bus and records every read and write that goes through it, in
+// order. The CPU makes exactly one bus access per cycle, so this doubles as a
+// per-cycle log of what it did. Peeks aren't recorded.
+#[derive(Default)]
+pub struct RecordingBus<B: Bus> {
+    pub inner: B,
+    pub accesses: Vec<BusAccess>,
+}
+
+impl<B: Bus> RecordingBus<B> {
+    pub fn new(inner: B) -> Self {
+        RecordingBus {
+            inner,
+            accesses: Vec::new(),
+        }
+    }
+}
+
+impl<B: Bus> Bus for RecordingBus<B> {
+    fn read(&mut self, addr: u16) -> u8 {
+        let data = self.inner.read(addr);
+        self.accesses.push(BusAccess { addr, data, kind: BusAccessKind::Read });
+        data
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.inner.write(addr, data);
+        self.accesses.push(BusAccess { addr, data, kind: BusAccessKind::Write });
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        self.inner.peek(addr)
+    }
+
+    fn irq(&self) -> bool {
+        self.inner.irq()
+    }
+}
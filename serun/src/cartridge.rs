@@ -1,59 +1,366 @@
-use std::{fmt, fs, path::PathBuf};
+use std::{fmt, fs, io, path::{Path, PathBuf}};
+use database::{GameEntry, HeaderCorrection};
+use hash::{Crc32, Sha1};
+use header::{CartridgeHeader, Mirroring, HEADER_SIZE};
+use mapper::Mapper;
+use mapper::fds::Fds;
+use patch::PatchError;
+
+pub mod database;
+pub mod fds;
+pub mod hash;
+pub mod header;
+pub mod mapper;
+pub mod patch;
+pub mod unif;
 
 const NES_HEADER_PREFIX: [u8; 4] = [0x4E, 0x45, 0x53, 0x1A];
-const PRG_ROM_UNIT_SIZE: usize = 16384;
-const CHR_ROM_UNIT_SIZE: usize = 8192;
+const TRAINER_SIZE: usize = 0x200;
+// Patches next to a .nes file with the same name get applied automatically,
+// the first one found in this order
+const PATCH_EXTENSIONS: [&str; 3] = ["ips", "ups", "bps"];
+// How often battery backed RAM gets written out while the game runs, about
+// every 5 seconds at the NTSC CPU clock
+const SAVE_FLUSH_INTERVAL: u64 = 5 * 1_789_773;
 
-#[derive(Debug)]
 pub struct Cartidge {
-    pub prg_rom: Vec<u8>,
-    pub chr_rom: Vec<u8>
+    // With whatever the game database corrected already applied
+    pub header: CartridgeHeader,
+    // Hashes of PRG ROM followed by CHR ROM, which identify the dump
+    pub crc32: u32,
+    pub sha1: [u8; 20],
+    // What the game database knows about this dump, if it knows it at all
+    pub database_entry: Option<&'static GameEntry>,
+    // Header fields that were wrong according to the database
+    pub header_corrections: Vec<HeaderCorrection>,
+    mapper: Box<dyn Mapper>,
+    // Where battery backed PRG RAM, or what was written to disk, is kept
+    // between sessions. Only set for cartridges with a battery and disk
+    // images that were loaded from a file.
+    save_path: Option<PathBuf>,
+    // Set when the save data may have changed since it was last saved
+    save_dirty: bool,
+    cycles_since_flush: u64,
+}
+
+impl fmt::Debug for Cartidge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cartidge").field("header", &self.header).finish_non_exhaustive()
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 pub enum CartidgeError {
     MissingHeaderPrefix,
+    // The file ends before the header does
+    TooShort,
+    // The file ends before the end of the PRG or CHR ROM the header promises.
+    // Sizes are in bytes.
+    TruncatedPrgRom { expected: usize, actual: usize },
+    TruncatedChrRom { expected: usize, actual: usize },
+    UnsupportedMapper(u16),
+    ZeroPrgBanks,
+    // A UNIF chunk runs past the end of the file
+    TruncatedUnifChunk,
+    // A UNIF file without a MAPR chunk, which leaves no way to pick a mapper
+    MissingBoardName,
+    UnsupportedBoard(String),
+    // A disk image was loaded like a cartridge, without the BIOS to run it
+    MissingFdsBios,
+    FdsBiosSize(usize),
+    TruncatedDiskImage { expected: usize, actual: usize },
+    NoSuchDiskSide { side: usize, count: usize },
+    Patch(PatchError),
     Io(String)
 }
 
 impl fmt::Display for CartidgeError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Self::MissingHeaderPrefix => write!(f, "File is missing NES header prefix, is this a .nes file?"),
+            Self::MissingHeaderPrefix => write!(f, "File is missing NES header prefix, is this a .nes or .unf file?"),
+            Self::TooShort => write!(f, "File is too short to hold a NES header"),
+            Self::TruncatedPrgRom { expected, actual } => write!(f, "PRG ROM is truncated, expected {expected} bytes but found {actual}"),
+            Self::TruncatedChrRom { expected, actual } => write!(f, "CHR ROM is truncated, expected {expected} bytes but found {actual}"),
+            Self::UnsupportedMapper(mapper) => write!(f, "Mapper {mapper} is not supported"),
+            Self::ZeroPrgBanks => write!(f, "Header says there is no PRG ROM"),
+            Self::TruncatedUnifChunk => write!(f, "UNIF chunk is truncated"),
+            Self::MissingBoardName => write!(f, "UNIF file does not name its board"),
+            Self::UnsupportedBoard(board) => write!(f, "Board {board} is not supported"),
+            Self::MissingFdsBios => write!(f, "Famicom Disk System images need the FDS BIOS to run"),
+            Self::FdsBiosSize(size) => write!(f, "FDS BIOS should be {} bytes but is {size}", fds::BIOS_SIZE),
+            Self::TruncatedDiskImage { expected, actual } => write!(f, "Disk image is truncated, expected {expected} bytes but found {actual}"),
+            Self::NoSuchDiskSide { side, count } => write!(f, "Disk side {side} does not exist, there are {count}"),
+            Self::Patch(error) => write!(f, "Failed to apply patch: {error}"),
             Self::Io(msg) => write!(f, "Error while attempting to parse .nes file: {msg}")
         }
     }
 }
 
+// Slices `size` bytes starting at `start` out of `data`, or says how many
+// bytes were actually left
+fn read_rom(data: &[u8], start: usize, size: usize) -> Result<&[u8], usize> {
+    let available = data.len().saturating_sub(start);
+    if size > available {
+        return Err(available);
+    }
+    Ok(&data[start..start + size])
+}
+
+// CRC-32 and SHA-1 of `roms` one after another
+fn hash(roms: &[&[u8]]) -> (u32, [u8; 20]) {
+    let mut crc32 = Crc32::default();
+    let mut sha1 = Sha1::default();
+    for rom in roms {
+        crc32.update(rom);
+        sha1.update(rom);
+    }
+    (crc32.finish(), sha1.finish())
+}
+
 impl Cartidge {
-    fn new(prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+    // Identifies the ROM and fixes up the header before picking a mapper for
+    // it, since bad headers are common
+    fn new(mut header: CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Result<Self, CartidgeError> {
+        let (crc32, sha1) = hash(&[&prg_rom, &chr_rom]);
+        let database_entry = database::lookup(crc32, &sha1);
+        let header_corrections = match database_entry {
+            Some(entry) => database::correct_header(entry, &mut header),
+            None => Vec::new(),
+        };
+
+        let mapper = mapper::new(&header, prg_rom, chr_rom)?;
+        let mut cartridge = Self::with_mapper(header, crc32, sha1, mapper);
+        cartridge.database_entry = database_entry;
+        cartridge.header_corrections = header_corrections;
+        Ok(cartridge)
+    }
+
+    fn with_mapper(header: CartridgeHeader, crc32: u32, sha1: [u8; 20], mapper: Box<dyn Mapper>) -> Self {
         Cartidge {
-            prg_rom,
-            chr_rom
+            header,
+            crc32,
+            sha1,
+            database_entry: None,
+            header_corrections: Vec::new(),
+            mapper,
+            save_path: None,
+            save_dirty: false,
+            cycles_since_flush: 0,
+        }
+    }
+
+    // CPU side accesses to cartridge space ($4020-$FFFF). Returns `None` when
+    // nothing on the cartridge drives the data bus at that address.
+    pub fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        self.mapper.cpu_read(addr)
+    }
+
+    pub fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        self.mapper.cpu_peek(addr)
+    }
+
+    pub fn cpu_write(&mut self, addr: u16, data: u8) {
+        if self.mapper.writes_save_data(addr) {
+            self.save_dirty = true;
+        }
+        self.mapper.cpu_write(addr, data);
+    }
+
+    // PPU side accesses to the pattern tables ($0000-$1FFF)
+    pub fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.mapper.ppu_read(addr)
+    }
+
+    pub fn ppu_peek(&self, addr: u16) -> u8 {
+        self.mapper.ppu_peek(addr)
+    }
+
+    pub fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.mapper.ppu_write(addr, data);
+    }
+
+    pub fn mirroring(&self) -> Mirroring {
+        self.mapper.mirroring()
+    }
+
+    pub fn cpu_clock(&mut self) {
+        self.mapper.cpu_clock();
+        if self.mapper.writing_save_data() {
+            self.save_dirty = true;
+        }
+        self.cycles_since_flush += 1;
+        if self.cycles_since_flush >= SAVE_FLUSH_INTERVAL {
+            // Failing now isn't the end of the world, it gets another go next
+            // time and on drop
+            let _ = self.flush_save();
+        }
+    }
+
+    pub fn irq(&self) -> bool {
+        self.mapper.irq()
+    }
+
+    pub fn expansion_audio(&self) -> f32 {
+        self.mapper.expansion_audio()
+    }
+
+    pub fn disk_side_count(&self) -> usize {
+        self.mapper.disk_side_count()
+    }
+
+    // The side of the disk in the FDS drive, if there is one
+    pub fn disk_side(&self) -> Option<usize> {
+        self.mapper.disk_side()
+    }
+
+    // Flips the disk in the FDS drive over, or swaps it for another. The
+    // drive is empty for a moment first, like when swapping by hand, so that
+    // the BIOS notices.
+    pub fn insert_disk(&mut self, side: usize) -> Result<(), CartidgeError> {
+        let count = self.disk_side_count();
+        if side >= count {
+            return Err(CartidgeError::NoSuchDiskSide { side, count });
         }
+        self.mapper.insert_disk(Some(side));
+        Ok(())
     }
 
+    pub fn eject_disk(&mut self) {
+        self.mapper.insert_disk(None);
+    }
+
+    // Loads a cartridge from a .nes or UNIF file, patched by a .ips, .ups or
+    // .bps file of the same name if there is one. Battery backed RAM is kept
+    // in a .sav file next to it, and loaded from there if it exists.
     pub fn from_path(path: PathBuf) -> Result<Cartidge, CartidgeError> {
-        let bytes = fs::read(path);
-        match bytes {
-            Ok(data) => Self::from_bytes(data),
-            Err(error) => Err(CartidgeError::Io(error.to_string()))
+        let patch_path = PATCH_EXTENSIONS.iter().map(|extension| path.with_extension(extension)).find(|patch_path| patch_path.is_file());
+        Self::load(path, patch_path)
+    }
+
+    // Like `from_path`, with the patch to apply given explicitly
+    pub fn from_path_with_patch(path: PathBuf, patch_path: PathBuf) -> Result<Cartidge, CartidgeError> {
+        Self::load(path, Some(patch_path))
+    }
+
+    fn load(path: PathBuf, patch_path: Option<PathBuf>) -> Result<Cartidge, CartidgeError> {
+        let read = |path: &Path| fs::read(path).map_err(|error| CartidgeError::Io(error.to_string()));
+        let data = read(&path)?;
+        let mut cartridge = match patch_path {
+            Some(patch_path) => Self::from_bytes_with_patch(&data, &read(&patch_path)?)?,
+            None => Self::from_bytes(&data)?,
+        };
+        if cartridge.header.battery {
+            cartridge.load_save(path.with_extension("sav"))?;
         }
+        Ok(cartridge)
+    }
+
+    // Loads a Famicom Disk System image from a .fds file, to be run by the BIOS
+    // in the file at `bios_path`. The image itself is left alone, what games
+    // write to disk is kept in a .sav file next to it instead and loaded from
+    // there if it exists.
+    pub fn from_fds_path(path: PathBuf, bios_path: PathBuf) -> Result<Cartidge, CartidgeError> {
+        let read = |path: &Path| fs::read(path).map_err(|error| CartidgeError::Io(error.to_string()));
+        let mut cartridge = Self::from_fds_bytes(&read(&path)?, &read(&bios_path)?)?;
+        cartridge.load_save(path.with_extension("sav"))?;
+        Ok(cartridge)
     }
 
-    fn from_bytes(data: Vec<u8>) -> Result<Cartidge, CartidgeError> {
-        if data[..4] != NES_HEADER_PREFIX {
+    // Loads the contents of a .fds file, with the first side inserted in the
+    // drive. What games write to disk is lost unless `load_save` is called.
+    // Disk images are hashed over their sides without the fwNES header, but
+    // not looked up, since the game database only covers cartridges.
+    pub fn from_fds_bytes(data: &[u8], bios: &[u8]) -> Result<Cartidge, CartidgeError> {
+        if bios.len() != fds::BIOS_SIZE {
+            return Err(CartidgeError::FdsBiosSize(bios.len()));
+        }
+        let sides = fds::sides(data)?;
+        let (crc32, sha1) = hash(&sides);
+        let header = fds::header();
+        let disks = sides.iter().map(|side| fds::with_gaps(side)).collect();
+        let mapper = Box::new(Fds::new(&header, bios.to_vec(), disks));
+        Ok(Self::with_mapper(header, crc32, sha1, mapper))
+    }
+
+    pub fn save_path(&self) -> Option<&Path> {
+        self.save_path.as_deref()
+    }
+
+    // Starts keeping the save data in the file at `path`, loading what's
+    // already in there. A missing file is fine, the game starts without a
+    // save.
+    pub fn load_save(&mut self, path: PathBuf) -> Result<(), CartidgeError> {
+        match fs::read(&path) {
+            Ok(data) => {
+                let save_data = self.mapper.save_data_mut();
+                let len = data.len().min(save_data.len());
+                save_data[..len].copy_from_slice(&data[..len]);
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {}
+            Err(error) => return Err(CartidgeError::Io(error.to_string())),
+        }
+        self.save_path = Some(path);
+        self.save_dirty = false;
+        Ok(())
+    }
+
+    // Writes the save data out to the save file, if there is one and
+    // anything changed since the last time
+    pub fn flush_save(&mut self) -> Result<(), CartidgeError> {
+        self.cycles_since_flush = 0;
+        let Some(path) = &self.save_path else {
+            return Ok(());
+        };
+        if !self.save_dirty {
+            return Ok(());
+        }
+        fs::write(path, self.mapper.save_data()).map_err(|error| CartidgeError::Io(error.to_string()))?;
+        self.save_dirty = false;
+        Ok(())
+    }
+
+    // Applies an IPS, UPS or BPS patch to the contents of a .nes or UNIF file
+    // before loading it
+    pub fn from_bytes_with_patch(data: &[u8], patch: &[u8]) -> Result<Cartidge, CartidgeError> {
+        let patched = patch::apply(patch, data).map_err(CartidgeError::Patch)?;
+        Self::from_bytes(&patched)
+    }
+
+    // Loads a cartridge from the contents of a .nes or UNIF file. Anything that
+    // doesn't add up is reported as an error, no matter how malformed the file
+    // is.
+    pub fn from_bytes(data: &[u8]) -> Result<Cartidge, CartidgeError> {
+        if fds::is_disk_image(data) {
+            return Err(CartidgeError::MissingFdsBios);
+        }
+        if data.starts_with(unif::UNIF_MAGIC) {
+            let (header, prg_rom, chr_rom) = unif::parse(data)?;
+            return Self::new(header, prg_rom, chr_rom);
+        }
+        if data.len() < HEADER_SIZE {
+            return Err(CartidgeError::TooShort);
+        }
+        if !data.starts_with(&NES_HEADER_PREFIX) {
             return Err(CartidgeError::MissingHeaderPrefix);
         }
-        let prg_rom_size = (data[4] as usize) * PRG_ROM_UNIT_SIZE;
-        let chr_rom_size = (data[5] as usize) * CHR_ROM_UNIT_SIZE;
-        let skip_trainer = data[6] & 0b100 == 0;
-        let prg_start = if skip_trainer { 0x10 } else { 0x10 + 0x200 };
-        let prg_end = prg_start + prg_rom_size - 1;
+        let header = CartridgeHeader::parse(data);
+        if header.prg_rom_size == 0 {
+            return Err(CartidgeError::ZeroPrgBanks);
+        }
+
+        let prg_start = if header.trainer { HEADER_SIZE + TRAINER_SIZE } else { HEADER_SIZE };
+        let prg_rom = read_rom(data, prg_start, header.prg_rom_size)
+            .map_err(|actual| CartidgeError::TruncatedPrgRom { expected: header.prg_rom_size, actual })?;
+        let chr_rom = read_rom(data, prg_start + header.prg_rom_size, header.chr_rom_size)
+            .map_err(|actual| CartidgeError::TruncatedChrRom { expected: header.chr_rom_size, actual })?;
 
-        Ok(Self::new(
-            data[prg_start..prg_end].to_vec(),
-            data[prg_end..(prg_end + chr_rom_size)].to_vec()
-        ))
+        Self::new(header, prg_rom.to_vec(), chr_rom.to_vec())
     }
-}
\ No newline at end of file
+}
+
+impl Drop for Cartidge {
+    fn drop(&mut self) {
+        // Nowhere left to report an error to
+        let _ = self.flush_save();
+    }
+}
@@ -0,0 +1,106 @@
+use std::fmt;
+use phf::phf_map;
+use crate::cartridge::header::{CartridgeHeader, Mirroring, TvSystem};
+
+// What's known about a dump, in the same terms as a NES 2.0 header
+#[derive(Debug, PartialEq)]
+pub struct GameEntry {
+    pub name: &'static str,
+    // Guards against CRC-32 collisions
+    pub sha1: [u8; 20],
+    pub mapper: u16,
+    pub submapper: u8,
+    // None when the mapper controls mirroring
+    pub mirroring: Option<Mirroring>,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub tv_system: TvSystem,
+}
+
+// Known dumps, keyed by the CRC-32 of their PRG ROM followed by CHR ROM, the
+// same as the NES 2.0 XML database identifies them. build.rs generates the map
+// from the database in data/.
+static GAMES: phf::Map<u32, GameEntry> = include!(concat!(env!("OUT_DIR"), "/games.rs"));
+
+// The test ROMs in data/test-roms.xml, generated the same way
+#[cfg(test)]
+pub static TEST_GAMES: phf::Map<u32, GameEntry> = include!(concat!(env!("OUT_DIR"), "/test_games.rs"));
+
+pub fn lookup(crc32: u32, sha1: &[u8; 20]) -> Option<&'static GameEntry> {
+    lookup_in(&GAMES, crc32, sha1)
+}
+
+pub fn lookup_in<'a>(games: &'a phf::Map<u32, GameEntry>, crc32: u32, sha1: &[u8; 20]) -> Option<&'a GameEntry> {
+    games.get(&crc32).filter(|entry| entry.sha1 == *sha1)
+}
+
+// A header field the database disagreed with, and what it was changed to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeaderCorrection {
+    Mapper { header: u16, database: u16 },
+    Submapper { header: u8, database: u8 },
+    Mirroring { header: Mirroring, database: Mirroring },
+    PrgRamSize { header: usize, database: usize },
+    PrgNvramSize { header: usize, database: usize },
+    ChrRamSize { header: usize, database: usize },
+    ChrNvramSize { header: usize, database: usize },
+    TvSystem { header: TvSystem, database: TvSystem },
+}
+
+impl fmt::Display for HeaderCorrection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mapper { header, database } => write!(f, "Mapper {header} corrected to {database}"),
+            Self::Submapper { header, database } => write!(f, "Submapper {header} corrected to {database}"),
+            Self::Mirroring { header, database } => write!(f, "{header:?} mirroring corrected to {database:?}"),
+            Self::PrgRamSize { header, database } => write!(f, "PRG RAM size {header} corrected to {database}"),
+            Self::PrgNvramSize { header, database } => write!(f, "PRG NVRAM size {header} corrected to {database}"),
+            Self::ChrRamSize { header, database } => write!(f, "CHR RAM size {header} corrected to {database}"),
+            Self::ChrNvramSize { header, database } => write!(f, "CHR NVRAM size {header} corrected to {database}"),
+            Self::TvSystem { header, database } => write!(f, "TV system {header:?} corrected to {database:?}"),
+        }
+    }
+}
+
+// Overwrites whatever `header` gets wrong about the game with what the
+// database says, and returns what had to change
+pub fn correct_header(entry: &GameEntry, header: &mut CartridgeHeader) -> Vec<HeaderCorrection> {
+    let mut corrections = Vec::new();
+    if header.mapper != entry.mapper {
+        corrections.push(HeaderCorrection::Mapper { header: header.mapper, database: entry.mapper });
+        header.mapper = entry.mapper;
+    }
+    if header.submapper != entry.submapper {
+        corrections.push(HeaderCorrection::Submapper { header: header.submapper, database: entry.submapper });
+        header.submapper = entry.submapper;
+    }
+    if let Some(mirroring) = entry.mirroring
+        && header.mirroring != mirroring {
+        corrections.push(HeaderCorrection::Mirroring { header: header.mirroring, database: mirroring });
+        header.mirroring = mirroring;
+    }
+    if header.prg_ram_size != entry.prg_ram_size {
+        corrections.push(HeaderCorrection::PrgRamSize { header: header.prg_ram_size, database: entry.prg_ram_size });
+        header.prg_ram_size = entry.prg_ram_size;
+    }
+    if header.prg_nvram_size != entry.prg_nvram_size {
+        corrections.push(HeaderCorrection::PrgNvramSize { header: header.prg_nvram_size, database: entry.prg_nvram_size });
+        header.prg_nvram_size = entry.prg_nvram_size;
+    }
+    if header.chr_ram_size != entry.chr_ram_size {
+        corrections.push(HeaderCorrection::ChrRamSize { header: header.chr_ram_size, database: entry.chr_ram_size });
+        header.chr_ram_size = entry.chr_ram_size;
+    }
+    if header.chr_nvram_size != entry.chr_nvram_size {
+        corrections.push(HeaderCorrection::ChrNvramSize { header: header.chr_nvram_size, database: entry.chr_nvram_size });
+        header.chr_nvram_size = entry.chr_nvram_size;
+    }
+    if header.tv_system != entry.tv_system {
+        corrections.push(HeaderCorrection::TvSystem { header: header.tv_system, database: entry.tv_system });
+        header.tv_system = entry.tv_system;
+    }
+    header.battery = header.prg_nvram_size > 0 || header.chr_nvram_size > 0;
+    corrections
+}
@@ -0,0 +1,117 @@
+use crate::cartridge::CartidgeError;
+use crate::cartridge::header::{CartridgeHeader, ConsoleType, HeaderFormat, Mirroring, TvSystem};
+
+// .fds files hold every side of a disk one after another, optionally behind a
+// 16 byte fwNES header of "FDS", an MS-DOS end of file, the number of sides
+// and padding. Each side is 65500 bytes of blocks:
+//   1  Disk info, 56 bytes starting with "*NINTENDO-HVC*"
+//   2  Number of files, 2 bytes
+//   3  File header, 16 bytes with the size of the file at 13-14
+//   4  File data, as many bytes as the header before it says
+// followed by zeroes up to the end of the side. The gaps and CRCs that
+// surround each block on a real disk are left out.
+pub const FWNES_MAGIC: &[u8] = b"FDS\x1A";
+pub const DISK_INFO_MAGIC: &[u8] = b"\x01*NINTENDO-HVC*";
+pub const BIOS_SIZE: usize = 0x2000;
+const FWNES_HEADER_SIZE: usize = 16;
+const SIDE_SIZE: usize = 65500;
+const PRG_RAM_SIZE: usize = 0x8000;
+const CHR_RAM_SIZE: usize = 0x2000;
+// The iNES mapper number set aside for the FDS
+const MAPPER: u16 = 20;
+
+const DISK_INFO_BLOCK: u8 = 1;
+const FILE_COUNT_BLOCK: u8 = 2;
+const FILE_HEADER_BLOCK: u8 = 3;
+const FILE_DATA_BLOCK: u8 = 4;
+const DISK_INFO_BLOCK_SIZE: usize = 56;
+const FILE_COUNT_BLOCK_SIZE: usize = 2;
+const FILE_HEADER_BLOCK_SIZE: usize = 16;
+const FILE_HEADER_SIZE_OFFSET: usize = 13;
+// Blank disk the head passes over before the first block and between blocks,
+// in bytes
+const LEAD_IN_SIZE: usize = 28300 / 8;
+const GAP_SIZE: usize = 976 / 8;
+// The first bit after a gap is set, marking where the block starts
+pub const GAP_END: u8 = 0x80;
+// Images don't keep the CRCs, the drive makes out they always match
+const CRC_SIZE: usize = 2;
+
+pub fn is_disk_image(data: &[u8]) -> bool {
+    data.starts_with(FWNES_MAGIC) || data.starts_with(DISK_INFO_MAGIC)
+}
+
+// Splits the contents of a .fds file into its sides
+pub fn sides(data: &[u8]) -> Result<Vec<&[u8]>, CartidgeError> {
+    let (count, data) = if data.starts_with(FWNES_MAGIC) {
+        let body = data.get(FWNES_HEADER_SIZE..).ok_or(CartidgeError::TooShort)?;
+        // Some images leave the count at 0
+        let count = match data[4] as usize {
+            0 => body.len().div_ceil(SIDE_SIZE),
+            count => count,
+        };
+        (count, body)
+    } else {
+        (data.len().div_ceil(SIDE_SIZE), data)
+    };
+    let expected = count.max(1) * SIDE_SIZE;
+    if data.len() < expected {
+        return Err(CartidgeError::TruncatedDiskImage { expected, actual: data.len() });
+    }
+    Ok(data.chunks(SIDE_SIZE).take(count).collect())
+}
+
+// Lays a side out the way the drive sees it, with gaps between blocks and
+// room for the BIOS to write more files after the last one
+pub fn with_gaps(side: &[u8]) -> Vec<u8> {
+    let mut disk = vec![0; LEAD_IN_SIZE];
+    let mut pos = 0;
+    let mut file_size = 0;
+    while let Some(&block) = side.get(pos) {
+        let len = match block {
+            DISK_INFO_BLOCK => DISK_INFO_BLOCK_SIZE,
+            FILE_COUNT_BLOCK => FILE_COUNT_BLOCK_SIZE,
+            FILE_HEADER_BLOCK => {
+                if let Some(size) = side.get(pos + FILE_HEADER_SIZE_OFFSET..pos + FILE_HEADER_SIZE_OFFSET + 2) {
+                    file_size = u16::from_le_bytes([size[0], size[1]]) as usize;
+                }
+                FILE_HEADER_BLOCK_SIZE
+            }
+            FILE_DATA_BLOCK => 1 + file_size,
+            // Zeroes from here on, or garbage that's no use to anyone
+            _ => break,
+        };
+        let end = (pos + len).min(side.len());
+        disk.push(GAP_END);
+        disk.extend_from_slice(&side[pos..end]);
+        disk.extend_from_slice(&[0; CRC_SIZE]);
+        disk.extend_from_slice(&[0; GAP_SIZE]);
+        pos = end;
+    }
+    disk.resize(disk.len() + side.len() - pos, 0);
+    disk
+}
+
+// What a .nes header would say about the RAM adapter, which plugs into the
+// Famicom like a cartridge does
+pub fn header() -> CartridgeHeader {
+    CartridgeHeader {
+        format: HeaderFormat::Fds,
+        mapper: MAPPER,
+        submapper: 0,
+        mirroring: Mirroring::Horizontal,
+        battery: false,
+        trainer: false,
+        prg_rom_size: BIOS_SIZE,
+        chr_rom_size: 0,
+        prg_ram_size: PRG_RAM_SIZE,
+        prg_nvram_size: 0,
+        chr_ram_size: CHR_RAM_SIZE,
+        chr_nvram_size: 0,
+        tv_system: TvSystem::Ntsc,
+        console_type: ConsoleType::Nes,
+        vs_system: None,
+        misc_rom_count: 0,
+        expansion_device: 0,
+    }
+}
@@ -0,0 +1,327 @@
+// The 16 byte header at the start of every .nes file. The first 8 bytes are
+// shared by every version of the format:
+//   0-3   "NES" followed by an MS-DOS end of file
+//   4     PRG ROM size in 16 KiB units
+//   5     CHR ROM size in 8 KiB units (0 means the board uses CHR RAM)
+//   6     Flags 6: mirroring, battery, trainer, four-screen and mapper low nibble
+//   7     Flags 7: console type, NES 2.0 identifier and mapper high nibble
+//
+// iNES 1.0 uses the rest like this:
+//   8     PRG RAM size in 8 KiB units (0 means 8 KiB for compatibility)
+//   9     Flags 9: TV system
+//   10-15 Unused padding
+//
+// NES 2.0 uses all of it:
+//   8     Mapper bits 8-11 and submapper
+//   9     PRG and CHR ROM size high nibbles
+//   10    PRG RAM and PRG NVRAM shift counts
+//   11    CHR RAM and CHR NVRAM shift counts
+//   12    CPU/PPU timing
+//   13    Vs. System type, or extended console type
+//   14    Number of miscellaneous ROMs
+//   15    Default expansion device
+pub const HEADER_SIZE: usize = 16;
+pub const PRG_ROM_UNIT_SIZE: usize = 16384;
+pub const CHR_ROM_UNIT_SIZE: usize = 8192;
+pub const PRG_RAM_UNIT_SIZE: usize = 8192;
+pub const CHR_RAM_DEFAULT_SIZE: usize = 8192;
+
+const FLAGS_6_VERTICAL_MIRRORING: u8 = 0b0000_0001;
+const FLAGS_6_BATTERY: u8 = 0b0000_0010;
+const FLAGS_6_TRAINER: u8 = 0b0000_0100;
+const FLAGS_6_FOUR_SCREEN: u8 = 0b0000_1000;
+const FLAGS_7_CONSOLE_TYPE: u8 = 0b0000_0011;
+const FLAGS_7_FORMAT: u8 = 0b0000_1100;
+const FLAGS_7_NES_2_0: u8 = 0b0000_1000;
+const FLAGS_9_PAL: u8 = 0b0000_0001;
+// A ROM size high nibble of $F means the low byte holds an exponent and
+// multiplier instead of a number of banks
+const ROM_SIZE_EXPONENT_NOTATION: usize = 0xF;
+// RAM sizes are given as shift counts of 64 bytes, 0 meaning no RAM at all
+const RAM_SHIFT_BASE: usize = 64;
+
+// Which version of the header format the file uses. The extra fields of newer
+// versions are left at their defaults for older ones.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeaderFormat {
+    // Bytes 7-15 may hold garbage (like "DiskDude!") and are ignored
+    ArchaicINes,
+    INes,
+    Nes20,
+    // Not a .nes header at all, but filled in from the chunks of a UNIF file
+    Unif,
+    // Made up for a disk image, which has nothing like it
+    Fds,
+}
+
+// How the PPU's nametables are laid out, which decides the direction the
+// screen can scroll in without glitches
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mirroring {
+    Horizontal,
+    Vertical,
+    // The cartridge brings its own VRAM for all four nametables
+    FourScreen,
+    // All four nametables show the same one. Only ever selected by mappers.
+    SingleScreenLower,
+    SingleScreenUpper,
+}
+
+// CPU/PPU timing the game was made for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TvSystem {
+    Ntsc,
+    Pal,
+    // Works on both, regional differences are handled by the game itself
+    MultiRegion,
+    // Famiclones like the Dendy, which run PAL video with NTSC like timing
+    Dendy,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConsoleType {
+    Nes,
+    VsSystem,
+    PlayChoice10,
+    // One of the NES 2.0 extended console types (famiclones and the like),
+    // going by the number in byte 13
+    Extended(u8),
+}
+
+// The Vs. System arcade boards came with different PPUs, most of them with
+// their own scrambled palette
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VsPpu {
+    Rp2c03b,
+    Rp2c03g,
+    Rp2c04_0001,
+    Rp2c04_0002,
+    Rp2c04_0003,
+    Rp2c04_0004,
+    Rc2c03b,
+    Rc2c03c,
+    Rc2c05_01,
+    Rc2c05_02,
+    Rc2c05_03,
+    Rc2c05_04,
+    Rc2c05_05,
+    Unknown(u8),
+}
+
+// Copy protection and wiring differences between Vs. System games
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum VsHardware {
+    Unisystem,
+    UnisystemRbiBaseball,
+    UnisystemTkoBoxing,
+    UnisystemSuperXevious,
+    UnisystemIceClimberJapan,
+    DualSystem,
+    DualSystemRaidOnBungelingBay,
+    Unknown(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct VsSystemType {
+    pub ppu: VsPpu,
+    pub hardware: VsHardware,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CartridgeHeader {
+    pub format: HeaderFormat,
+    pub mapper: u16,
+    // Tells apart boards sharing a mapper number. Only NES 2.0 has these, 0
+    // otherwise.
+    pub submapper: u8,
+    pub mirroring: Mirroring,
+    // Some of the cartridge's RAM is battery backed and should be kept between
+    // runs
+    pub battery: bool,
+    // A 512 byte trainer sits between the header and PRG ROM
+    pub trainer: bool,
+    // Sizes in bytes. Older formats can't tell volatile and battery backed RAM
+    // apart, so all of their PRG RAM counts as NVRAM when `battery` is set.
+    pub prg_rom_size: usize,
+    pub chr_rom_size: usize,
+    pub prg_ram_size: usize,
+    pub prg_nvram_size: usize,
+    pub chr_ram_size: usize,
+    pub chr_nvram_size: usize,
+    pub tv_system: TvSystem,
+    pub console_type: ConsoleType,
+    // Only set for Vs. System games with a NES 2.0 header
+    pub vs_system: Option<VsSystemType>,
+    pub misc_rom_count: u8,
+    // Input device the game expects to be plugged in, numbered as in the
+    // NES 2.0 spec (1 being standard controllers). 0 when unspecified.
+    pub expansion_device: u8,
+}
+
+impl HeaderFormat {
+    fn detect(header: &[u8]) -> HeaderFormat {
+        match header[7] & FLAGS_7_FORMAT {
+            FLAGS_7_NES_2_0 => HeaderFormat::Nes20,
+            0 if header[12..HEADER_SIZE].iter().all(|byte| *byte == 0) => HeaderFormat::INes,
+            _ => HeaderFormat::ArchaicINes,
+        }
+    }
+}
+
+impl VsPpu {
+    fn from_id(id: u8) -> VsPpu {
+        match id {
+            0x0 => VsPpu::Rp2c03b,
+            0x1 => VsPpu::Rp2c03g,
+            0x2 => VsPpu::Rp2c04_0001,
+            0x3 => VsPpu::Rp2c04_0002,
+            0x4 => VsPpu::Rp2c04_0003,
+            0x5 => VsPpu::Rp2c04_0004,
+            0x6 => VsPpu::Rc2c03b,
+            0x7 => VsPpu::Rc2c03c,
+            0x8 => VsPpu::Rc2c05_01,
+            0x9 => VsPpu::Rc2c05_02,
+            0xA => VsPpu::Rc2c05_03,
+            0xB => VsPpu::Rc2c05_04,
+            0xC => VsPpu::Rc2c05_05,
+            _ => VsPpu::Unknown(id),
+        }
+    }
+}
+
+impl VsHardware {
+    fn from_id(id: u8) -> VsHardware {
+        match id {
+            0x0 => VsHardware::Unisystem,
+            0x1 => VsHardware::UnisystemRbiBaseball,
+            0x2 => VsHardware::UnisystemTkoBoxing,
+            0x3 => VsHardware::UnisystemSuperXevious,
+            0x4 => VsHardware::UnisystemIceClimberJapan,
+            0x5 => VsHardware::DualSystem,
+            0x6 => VsHardware::DualSystemRaidOnBungelingBay,
+            _ => VsHardware::Unknown(id),
+        }
+    }
+}
+
+// NES 2.0 ROM size from the low byte in the header and the high nibble from
+// byte 9. With a high nibble of $F the low byte is EEEEEEMM, meaning a size of
+// 2^E * (MM * 2 + 1) bytes.
+fn nes_2_0_rom_size(lsb: u8, msb: u8, unit_size: usize) -> usize {
+    let msb = msb as usize & 0x0F;
+    if msb == ROM_SIZE_EXPONENT_NOTATION {
+        let exponent = (lsb >> 2) as u32;
+        let multiplier = (lsb & 0b11) as usize * 2 + 1;
+        1usize.checked_shl(exponent).map_or(usize::MAX, |size| size.saturating_mul(multiplier))
+    } else {
+        ((msb << 8) | lsb as usize) * unit_size
+    }
+}
+
+fn nes_2_0_ram_size(shift_count: u8) -> usize {
+    match shift_count & 0x0F {
+        0 => 0,
+        shift_count => RAM_SHIFT_BASE << shift_count,
+    }
+}
+
+impl CartridgeHeader {
+    // `header` has to be at least `HEADER_SIZE` bytes long. The caller is
+    // expected to have checked the "NES\x1A" prefix already.
+    pub fn parse(header: &[u8]) -> CartridgeHeader {
+        let flags_6 = header[6];
+        let format = HeaderFormat::detect(header);
+        // Archaic headers only have the first 7 bytes worth trusting
+        let flags_7 = match format {
+            HeaderFormat::ArchaicINes => 0,
+            _ => header[7],
+        };
+
+        let mirroring = if flags_6 & FLAGS_6_FOUR_SCREEN != 0 {
+            Mirroring::FourScreen
+        } else if flags_6 & FLAGS_6_VERTICAL_MIRRORING != 0 {
+            Mirroring::Vertical
+        } else {
+            Mirroring::Horizontal
+        };
+        let battery = flags_6 & FLAGS_6_BATTERY != 0;
+
+        let mut parsed = CartridgeHeader {
+            format,
+            mapper: ((flags_7 & 0xF0) | (flags_6 >> 4)) as u16,
+            submapper: 0,
+            mirroring,
+            battery,
+            trainer: flags_6 & FLAGS_6_TRAINER != 0,
+            prg_rom_size: header[4] as usize * PRG_ROM_UNIT_SIZE,
+            chr_rom_size: header[5] as usize * CHR_ROM_UNIT_SIZE,
+            prg_ram_size: 0,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            tv_system: TvSystem::Ntsc,
+            console_type: match flags_7 & FLAGS_7_CONSOLE_TYPE {
+                0 => ConsoleType::Nes,
+                1 => ConsoleType::VsSystem,
+                2 => ConsoleType::PlayChoice10,
+                _ => ConsoleType::Extended(0),
+            },
+            vs_system: None,
+            misc_rom_count: 0,
+            expansion_device: 0,
+        };
+
+        match format {
+            HeaderFormat::Nes20 => parsed.parse_nes_2_0(header),
+            HeaderFormat::INes => parsed.parse_ines(header[8], header[9]),
+            HeaderFormat::ArchaicINes => parsed.parse_ines(0, 0),
+            // Never detected from a .nes header
+            HeaderFormat::Unif | HeaderFormat::Fds => unreachable!(),
+        }
+        parsed
+    }
+
+    fn parse_ines(&mut self, prg_ram_banks: u8, flags_9: u8) {
+        let prg_ram_size = prg_ram_banks.max(1) as usize * PRG_RAM_UNIT_SIZE;
+        if self.battery {
+            self.prg_nvram_size = prg_ram_size;
+        } else {
+            self.prg_ram_size = prg_ram_size;
+        }
+        if self.chr_rom_size == 0 {
+            self.chr_ram_size = CHR_RAM_DEFAULT_SIZE;
+        }
+        if flags_9 & FLAGS_9_PAL != 0 {
+            self.tv_system = TvSystem::Pal;
+        }
+    }
+
+    fn parse_nes_2_0(&mut self, header: &[u8]) {
+        self.mapper |= ((header[8] & 0x0F) as u16) << 8;
+        self.submapper = header[8] >> 4;
+        self.prg_rom_size = nes_2_0_rom_size(header[4], header[9], PRG_ROM_UNIT_SIZE);
+        self.chr_rom_size = nes_2_0_rom_size(header[5], header[9] >> 4, CHR_ROM_UNIT_SIZE);
+        self.prg_ram_size = nes_2_0_ram_size(header[10]);
+        self.prg_nvram_size = nes_2_0_ram_size(header[10] >> 4);
+        self.chr_ram_size = nes_2_0_ram_size(header[11]);
+        self.chr_nvram_size = nes_2_0_ram_size(header[11] >> 4);
+        self.tv_system = match header[12] & 0b11 {
+            0 => TvSystem::Ntsc,
+            1 => TvSystem::Pal,
+            2 => TvSystem::MultiRegion,
+            _ => TvSystem::Dendy,
+        };
+        match self.console_type {
+            ConsoleType::VsSystem => {
+                self.vs_system = Some(VsSystemType {
+                    ppu: VsPpu::from_id(header[13] & 0x0F),
+                    hardware: VsHardware::from_id(header[13] >> 4),
+                });
+            }
+            ConsoleType::Extended(_) => self.console_type = ConsoleType::Extended(header[13] & 0x0F),
+            _ => {}
+        }
+        self.misc_rom_count = header[14] & 0b11;
+        self.expansion_device = header[15] & 0b0011_1111;
+    }
+}
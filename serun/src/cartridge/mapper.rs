@@ -0,0 +1,171 @@
+use crate::cartridge::CartidgeError;
+use crate::cartridge::header::{CartridgeHeader, HeaderFormat, Mirroring};
+use axrom::Axrom;
+use cnrom::Cnrom;
+use gxrom::Gxrom;
+use mmc1::Mmc1;
+use mmc2::{Mmc2, Mmc2Chip};
+use mmc3::Mmc3;
+use nrom::Nrom;
+use uxrom::Uxrom;
+use vrc4::Vrc4;
+use vrc6::Vrc6;
+use vrc7::Vrc7;
+
+pub mod axrom;
+pub mod cnrom;
+pub mod fds;
+pub mod gxrom;
+pub mod mmc1;
+pub mod mmc2;
+pub mod mmc3;
+pub mod nrom;
+pub mod uxrom;
+mod vrc;
+pub mod vrc4;
+pub mod vrc6;
+pub mod vrc7;
+
+pub const PRG_RAM_START: u16 = 0x6000;
+pub const PRG_ROM_START: u16 = 0x8000;
+
+// The circuitry on the cartridge board that decides what the CPU and PPU see
+// when they access cartridge space, usually switching between banks of a ROM
+// too large to fit in the address space at once.
+pub trait Mapper {
+    // CPU side accesses to $4020-$FFFF. Returns `None` when nothing on the
+    // cartridge drives the data bus at that address.
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        self.cpu_peek(addr)
+    }
+
+    // Like `cpu_read`, without side effects
+    fn cpu_peek(&self, addr: u16) -> Option<u8>;
+
+    fn cpu_write(&mut self, addr: u16, data: u8);
+
+    // PPU side accesses to the pattern tables at $0000-$1FFF. Every pattern
+    // fetch the PPU makes goes through here, so mappers that react to what the
+    // PPU is drawing watch these.
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.ppu_peek(addr)
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8;
+
+    fn ppu_write(&mut self, addr: u16, data: u8);
+
+    // Current nametable layout. Fixed by the board's wiring on simple boards,
+    // mapper controlled on others.
+    fn mirroring(&self) -> Mirroring;
+
+    // Called once every CPU cycle, before the bus access made on it, for
+    // mappers that need to keep track of time
+    fn cpu_clock(&mut self) {}
+
+    // Whether the mapper is asserting the CPU's IRQ line
+    fn irq(&self) -> bool {
+        false
+    }
+
+    // Current output of the sound channels on the cartridge, for the APU to
+    // mix into its own. Goes from 0.0 for silence to 1.0 with every channel at
+    // its loudest.
+    fn expansion_audio(&self) -> f32 {
+        0.0
+    }
+
+    // All of the PRG RAM on the board, whether it's mapped in right now or
+    // not. Used to load and save battery backed RAM.
+    fn prg_ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut []
+    }
+
+    // What's kept in the save file between sessions: battery backed PRG RAM
+    // on cartridges, the disks on the Famicom Disk System
+    fn save_data(&self) -> &[u8] {
+        self.prg_ram()
+    }
+
+    fn save_data_mut(&mut self) -> &mut [u8] {
+        self.prg_ram_mut()
+    }
+
+    // Whether a CPU write to `addr` may change the save data
+    fn writes_save_data(&self, addr: u16) -> bool {
+        (PRG_RAM_START..PRG_ROM_START).contains(&addr)
+    }
+
+    // Whether the save data may change on this CPU cycle without the CPU
+    // writing to it, like when the FDS drive writes to disk
+    fn writing_save_data(&self) -> bool {
+        false
+    }
+
+    // The disk drive of the Famicom Disk System. Sides are numbered from 0 in
+    // the order of the disk image, and `None` means the drive is empty.
+    fn disk_side_count(&self) -> usize {
+        0
+    }
+
+    fn disk_side(&self) -> Option<usize> {
+        None
+    }
+
+    fn insert_disk(&mut self, _side: Option<usize>) {}
+}
+
+// Picks the mapper for the board the header describes
+pub fn new(header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Result<Box<dyn Mapper>, CartidgeError> {
+    match header.mapper {
+        0 => Ok(Box::new(Nrom::new(header, prg_rom, chr_rom))),
+        1 => Ok(Box::new(Mmc1::new(header, prg_rom, chr_rom))),
+        2 => Ok(Box::new(Uxrom::new(header, prg_rom, chr_rom))),
+        3 => Ok(Box::new(Cnrom::new(header, prg_rom, chr_rom))),
+        4 => Ok(Box::new(Mmc3::new(header, prg_rom, chr_rom))),
+        7 => Ok(Box::new(Axrom::new(header, prg_rom, chr_rom))),
+        9 => Ok(Box::new(Mmc2::new(Mmc2Chip::Mmc2, header, prg_rom, chr_rom))),
+        10 => Ok(Box::new(Mmc2::new(Mmc2Chip::Mmc4, header, prg_rom, chr_rom))),
+        // 20 is the FDS, which comes on disks rather than in .nes files
+        21 | 22 | 23 | 25 => Ok(Box::new(Vrc4::new(header, prg_rom, chr_rom))),
+        24 | 26 => Ok(Box::new(Vrc6::new(header, prg_rom, chr_rom))),
+        66 => Ok(Box::new(Gxrom::new(header, prg_rom, chr_rom))),
+        85 => Ok(Box::new(Vrc7::new(header, prg_rom, chr_rom))),
+        mapper => Err(CartidgeError::UnsupportedMapper(mapper)),
+    }
+}
+
+// RAM on the PPU side takes the place of CHR ROM on boards that don't have any
+pub(crate) fn chr_memory(header: &CartridgeHeader, chr_rom: Vec<u8>) -> (Vec<u8>, bool) {
+    if chr_rom.is_empty() {
+        (vec![0; header.chr_ram_size + header.chr_nvram_size], true)
+    } else {
+        (chr_rom, false)
+    }
+}
+
+// Whether writes to the board's registers also enable the PRG ROM, so that the
+// CPU and the ROM drive the data bus at the same time and the register latches
+// their bitwise AND. NES 2.0 headers say so with submapper 1 (no conflicts) or
+// 2 (conflicts), otherwise it depends on what the usual board for the mapper
+// does.
+pub(crate) fn has_bus_conflicts(header: &CartridgeHeader, board_default: bool) -> bool {
+    match (header.format, header.submapper) {
+        (HeaderFormat::Nes20, 1) => false,
+        (HeaderFormat::Nes20, 2) => true,
+        _ => board_default,
+    }
+}
+
+// Index into a memory of `len` bytes mirrored across a larger address range
+pub(crate) fn mirrored(len: usize, offset: usize) -> Option<usize> {
+    if len == 0 {
+        None
+    } else {
+        Some(offset % len)
+    }
+}
@@ -0,0 +1,65 @@
+use crate::cartridge::header::{CartridgeHeader, Mirroring};
+use crate::cartridge::mapper::{chr_memory, has_bus_conflicts, mirrored, Mapper, PRG_ROM_START};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const PRG_BANK_MASK: u8 = 0b0000_0111;
+const SINGLE_SCREEN_UPPER: u8 = 0b0001_0000;
+
+// Mapper 7, AxROM. Writes to $8000-$FFFF pick a 32 KiB PRG bank with bits
+// 0-2 and which nametable fills the whole screen with bit 4. CHR is 8 KiB of
+// unbanked RAM. Only AMROM has bus conflicts, ANROM and AOROM were designed to
+// avoid them.
+pub struct Axrom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    bus_conflicts: bool,
+    bank: u8,
+}
+
+impl Axrom {
+    pub fn new(header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let (chr, chr_is_ram) = chr_memory(header, chr_rom);
+        Axrom {
+            prg_rom,
+            chr,
+            chr_is_ram,
+            bus_conflicts: has_bus_conflicts(header, false),
+            bank: 0,
+        }
+    }
+}
+
+impl Mapper for Axrom {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        if addr < PRG_ROM_START {
+            return None;
+        }
+        let bank = (self.bank & PRG_BANK_MASK) as usize;
+        mirrored(self.prg_rom.len(), bank * PRG_BANK_SIZE + (addr - PRG_ROM_START) as usize).map(|index| self.prg_rom[index])
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= PRG_ROM_START {
+            self.bank = if self.bus_conflicts { data & self.cpu_peek(addr).unwrap_or(0xFF) } else { data };
+        }
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8 {
+        mirrored(self.chr.len(), addr as usize).map_or(0, |index| self.chr[index])
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && let Some(index) = mirrored(self.chr.len(), addr as usize) {
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.bank & SINGLE_SCREEN_UPPER != 0 {
+            Mirroring::SingleScreenUpper
+        } else {
+            Mirroring::SingleScreenLower
+        }
+    }
+}
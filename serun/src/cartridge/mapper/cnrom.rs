@@ -0,0 +1,62 @@
+use crate::cartridge::header::{CartridgeHeader, Mirroring};
+use crate::cartridge::mapper::{chr_memory, has_bus_conflicts, mirrored, Mapper, PRG_ROM_START};
+
+const CHR_BANK_SIZE: usize = 0x2000;
+
+// Mapper 3, CNROM. PRG ROM is laid out like NROM, writes to $8000-$FFFF pick
+// the 8 KiB CHR ROM bank. Mirroring is soldered.
+pub struct Cnrom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    chr_bank: u8,
+}
+
+impl Cnrom {
+    pub fn new(header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let (chr, chr_is_ram) = chr_memory(header, chr_rom);
+        Cnrom {
+            prg_rom,
+            chr,
+            chr_is_ram,
+            mirroring: header.mirroring,
+            bus_conflicts: has_bus_conflicts(header, true),
+            chr_bank: 0,
+        }
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        mirrored(self.chr.len(), self.chr_bank as usize * CHR_BANK_SIZE + addr as usize)
+    }
+}
+
+impl Mapper for Cnrom {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        if addr < PRG_ROM_START {
+            return None;
+        }
+        mirrored(self.prg_rom.len(), (addr - PRG_ROM_START) as usize).map(|index| self.prg_rom[index])
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= PRG_ROM_START {
+            self.chr_bank = if self.bus_conflicts { data & self.cpu_peek(addr).unwrap_or(0xFF) } else { data };
+        }
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8 {
+        self.chr_index(addr).map_or(0, |index| self.chr[index])
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && let Some(index) = self.chr_index(addr) {
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
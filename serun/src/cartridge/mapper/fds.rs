@@ -0,0 +1,629 @@
+use std::ops::Range;
+use crate::cartridge::fds::GAP_END;
+use crate::cartridge::header::{CartridgeHeader, Mirroring};
+use crate::cartridge::mapper::{Mapper, PRG_RAM_START};
+
+const BIOS_START: u16 = 0xE000;
+
+const IO_ENABLE_DISK: u8 = 0b01;
+const IO_ENABLE_SOUND: u8 = 0b10;
+const TIMER_REPEAT: u8 = 0b01;
+const TIMER_ENABLE: u8 = 0b10;
+const CONTROL_MOTOR_ON: u8 = 0b0000_0001;
+const CONTROL_TRANSFER_RESET: u8 = 0b0000_0010;
+const CONTROL_READ_MODE: u8 = 0b0000_0100;
+const CONTROL_HORIZONTAL_MIRRORING: u8 = 0b0000_1000;
+const CONTROL_CRC: u8 = 0b0001_0000;
+const CONTROL_TRANSFER_START: u8 = 0b0100_0000;
+const CONTROL_TRANSFER_IRQ: u8 = 0b1000_0000;
+const DISK_STATUS_TIMER_IRQ: u8 = 0b0000_0001;
+const DISK_STATUS_TRANSFERRED: u8 = 0b0000_0010;
+const DISK_STATUS_END_OF_HEAD: u8 = 0b0100_0000;
+const DRIVE_STATUS_NO_DISK: u8 = 0b001;
+const DRIVE_STATUS_NOT_READY: u8 = 0b010;
+const DRIVE_STATUS_WRITE_PROTECTED: u8 = 0b100;
+// Bit 7 of $4033 reads set while the batteries in the drive are good
+const EXTERNAL_BATTERY_GOOD: u8 = 0b1000_0000;
+
+// CPU cycles the head takes to get back to the start of the disk, and to move
+// on by a byte (the drive reads about 12 KB a second)
+const REWIND_CYCLES: u32 = 50000;
+const BYTE_CYCLES: u32 = 150;
+// How long the drive stays empty when swapping disks, so that the BIOS
+// notices. About half a second.
+const DISK_SWAP_CYCLES: u32 = 900_000;
+
+// The Famicom Disk System's RAM adapter, which takes the place of a cartridge
+// and connects the disk drive. Memory:
+//   $6000-$DFFF  32 KiB of PRG RAM, where games get loaded from disk
+//   $E000-$FFFF  8 KiB BIOS ROM
+// and 8 KiB of CHR RAM. Registers:
+//   $4020-$4022  Timer IRQ reload value and control
+//   $4023        Disk and sound register enable
+//   $4024        Byte to write to disk
+//   $4025        Drive control, and mirroring
+//   $4030        Disk status, reading acknowledges both IRQs
+//   $4031        Byte read from disk
+//   $4032        Drive status
+//   $4033        Drive battery status
+//   $4040-$408A  Wavetable sound channel, see `FdsAudio`
+// Disks are made up of bytes rather than bits, in the layout of
+// `fds::with_gaps`. The drive always reports CRCs as matching, since images
+// don't have them. What games write to disk is kept as the save data, every
+// side one after another.
+pub struct Fds {
+    bios: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr_ram: Vec<u8>,
+    disk: Vec<u8>,
+    // Where each side is in `disk`
+    sides: Vec<Range<usize>>,
+    disk_side: Option<usize>,
+    // While counting down, the drive is empty as far as the BIOS can tell
+    swap_delay: u32,
+    io_enable: u8,
+    control: u8,
+    timer_reload: u16,
+    timer_counter: u16,
+    timer_control: u8,
+    timer_irq: bool,
+    disk_irq: bool,
+    // Set once a whole byte has been read or written, until the CPU picks it
+    // up or hands over the next one
+    transferred: bool,
+    read_data: u8,
+    write_data: u8,
+    position: usize,
+    // Cycles until the head gets to the byte at `position`
+    delay: u32,
+    end_of_head: bool,
+    scanning: bool,
+    // Whether reading got past the gap before the current block
+    gap_ended: bool,
+    audio: FdsAudio,
+}
+
+impl Fds {
+    // `disks` holds every side of every disk, as laid out by `fds::with_gaps`
+    pub fn new(header: &CartridgeHeader, bios: Vec<u8>, disks: Vec<Vec<u8>>) -> Self {
+        let mut sides = Vec::new();
+        let mut start = 0;
+        for side in &disks {
+            sides.push(start..start + side.len());
+            start += side.len();
+        }
+        Fds {
+            bios,
+            prg_ram: vec![0; header.prg_ram_size],
+            chr_ram: vec![0; header.chr_ram_size],
+            disk: disks.concat(),
+            disk_side: if sides.is_empty() { None } else { Some(0) },
+            sides,
+            swap_delay: 0,
+            io_enable: 0,
+            control: 0,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_control: 0,
+            timer_irq: false,
+            disk_irq: false,
+            transferred: false,
+            read_data: 0,
+            write_data: 0,
+            position: 0,
+            delay: 0,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            audio: FdsAudio::default(),
+        }
+    }
+
+    fn disk_inserted(&self) -> bool {
+        self.disk_side.is_some() && self.swap_delay == 0
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        let disk_enabled = self.io_enable & IO_ENABLE_DISK != 0;
+        let sound_enabled = self.io_enable & IO_ENABLE_SOUND != 0;
+        match addr {
+            0x4023 => {
+                self.io_enable = data;
+                if data & IO_ENABLE_DISK == 0 {
+                    self.timer_control &= !TIMER_ENABLE;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            _ if !disk_enabled && addr < 0x4040 => {}
+            0x4020 => self.timer_reload = (self.timer_reload & 0xFF00) | data as u16,
+            0x4021 => self.timer_reload = (self.timer_reload & 0x00FF) | ((data as u16) << 8),
+            0x4022 => {
+                self.timer_control = data & (TIMER_REPEAT | TIMER_ENABLE);
+                if self.timer_control & TIMER_ENABLE != 0 {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4024 => {
+                self.write_data = data;
+                self.transferred = false;
+                self.disk_irq = false;
+            }
+            0x4025 => {
+                self.control = data;
+                self.disk_irq = false;
+            }
+            0x4040..=0x408A if sound_enabled => self.audio.write(addr, data),
+            _ => {}
+        }
+    }
+
+    // What reading a register would return, before the side effects of
+    // reading it
+    fn peek_register(&self, addr: u16) -> Option<u8> {
+        if self.io_enable & IO_ENABLE_DISK == 0 && addr < 0x4040 {
+            return None;
+        }
+        match addr {
+            0x4030 => {
+                let mut status = 0;
+                if self.timer_irq {
+                    status |= DISK_STATUS_TIMER_IRQ;
+                }
+                if self.transferred {
+                    status |= DISK_STATUS_TRANSFERRED;
+                }
+                if self.end_of_head {
+                    status |= DISK_STATUS_END_OF_HEAD;
+                }
+                Some(status)
+            }
+            0x4031 => Some(self.read_data),
+            0x4032 => {
+                let inserted = self.disk_inserted();
+                let mut status = 0;
+                if !inserted {
+                    status |= DRIVE_STATUS_NO_DISK | DRIVE_STATUS_WRITE_PROTECTED;
+                }
+                if !inserted || !self.scanning {
+                    status |= DRIVE_STATUS_NOT_READY;
+                }
+                Some(status)
+            }
+            0x4033 => Some(EXTERNAL_BATTERY_GOOD),
+            0x4040..=0x4092 if self.io_enable & IO_ENABLE_SOUND != 0 => self.audio.peek(addr),
+            _ => None,
+        }
+    }
+
+    fn clock_timer(&mut self) {
+        if self.timer_control & TIMER_ENABLE == 0 {
+            return;
+        }
+        if self.timer_counter == 0 {
+            self.timer_irq = true;
+            self.timer_counter = self.timer_reload;
+            if self.timer_control & TIMER_REPEAT == 0 {
+                self.timer_control &= !TIMER_ENABLE;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    // Moves the disk along under the head, transferring a byte each time it
+    // gets to the next one
+    fn clock_drive(&mut self) {
+        if self.swap_delay > 0 {
+            self.swap_delay -= 1;
+            return;
+        }
+        let Some(side) = self.disk_side.filter(|_| self.control & CONTROL_MOTOR_ON != 0) else {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        };
+        if self.control & CONTROL_TRANSFER_RESET != 0 && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.end_of_head = false;
+            self.position = 0;
+            self.delay = REWIND_CYCLES;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let transfer_start = self.control & CONTROL_TRANSFER_START != 0;
+        let disk = &mut self.disk[self.sides[side].clone()];
+        if self.control & CONTROL_READ_MODE != 0 {
+            let data = disk[self.position];
+            if !transfer_start {
+                self.gap_ended = false;
+            } else if !self.gap_ended && data == GAP_END {
+                // The byte with the end of the gap in it is the first one
+                // handed over, but without an IRQ
+                self.gap_ended = true;
+                self.transferred = true;
+                self.read_data = data;
+            } else if self.gap_ended {
+                self.transferred = true;
+                self.read_data = data;
+                self.disk_irq |= self.control & CONTROL_TRANSFER_IRQ != 0;
+            }
+        } else {
+            // Writes the CRC on its own while the CRC bit is set
+            let data = if self.control & CONTROL_CRC == 0 {
+                self.transferred = true;
+                self.disk_irq |= self.control & CONTROL_TRANSFER_IRQ != 0;
+                self.write_data
+            } else {
+                0
+            };
+            disk[self.position] = if transfer_start { data } else { 0 };
+            self.gap_ended = false;
+        }
+
+        self.position += 1;
+        if self.position >= disk.len() {
+            self.control &= !CONTROL_MOTOR_ON;
+            self.end_of_head = true;
+        } else {
+            self.delay = BYTE_CYCLES;
+        }
+    }
+}
+
+impl Mapper for Fds {
+    fn cpu_read(&mut self, addr: u16) -> Option<u8> {
+        let data = self.cpu_peek(addr);
+        if data.is_some() {
+            match addr {
+                0x4030 => {
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                    self.transferred = false;
+                }
+                0x4031 => {
+                    self.disk_irq = false;
+                    self.transferred = false;
+                }
+                _ => {}
+            }
+        }
+        data
+    }
+
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            BIOS_START..=u16::MAX => self.bios.get((addr - BIOS_START) as usize).copied(),
+            PRG_RAM_START..BIOS_START => self.prg_ram.get((addr - PRG_RAM_START) as usize).copied(),
+            _ => self.peek_register(addr),
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            BIOS_START..=u16::MAX => {}
+            PRG_RAM_START..BIOS_START => {
+                if let Some(byte) = self.prg_ram.get_mut((addr - PRG_RAM_START) as usize) {
+                    *byte = data;
+                }
+            }
+            _ => self.write_register(addr, data),
+        }
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8 {
+        self.chr_ram.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if let Some(byte) = self.chr_ram.get_mut(addr as usize) {
+            *byte = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        if self.control & CONTROL_HORIZONTAL_MIRRORING != 0 { Mirroring::Horizontal } else { Mirroring::Vertical }
+    }
+
+    fn cpu_clock(&mut self) {
+        self.clock_timer();
+        self.clock_drive();
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+
+    fn expansion_audio(&self) -> f32 {
+        self.audio.output()
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+
+    fn save_data(&self) -> &[u8] {
+        &self.disk
+    }
+
+    fn save_data_mut(&mut self) -> &mut [u8] {
+        &mut self.disk
+    }
+
+    // PRG RAM is lost when the power goes, only the disk keeps anything
+    fn writes_save_data(&self, _addr: u16) -> bool {
+        false
+    }
+
+    fn writing_save_data(&self) -> bool {
+        self.disk_inserted() && self.control & (CONTROL_MOTOR_ON | CONTROL_READ_MODE) == CONTROL_MOTOR_ON
+    }
+
+    fn disk_side_count(&self) -> usize {
+        self.sides.len()
+    }
+
+    fn disk_side(&self) -> Option<usize> {
+        self.disk_side
+    }
+
+    fn insert_disk(&mut self, side: Option<usize>) {
+        self.disk_side = side.filter(|&side| side < self.sides.len());
+        self.swap_delay = if self.disk_side.is_some() { DISK_SWAP_CYCLES } else { 0 };
+        self.end_of_head = true;
+        self.scanning = false;
+    }
+}
+
+const ENVELOPE_DISABLE: u8 = 0b1000_0000;
+const ENVELOPE_INCREASE: u8 = 0b0100_0000;
+const ENVELOPE_SPEED: u8 = 0b0011_1111;
+const WAVE_HALT: u8 = 0b1000_0000;
+const WAVE_ENVELOPES_HALT: u8 = 0b0100_0000;
+const MODULATION_HALT: u8 = 0b1000_0000;
+const MASTER_WAVE_WRITE: u8 = 0b1000_0000;
+const MASTER_VOLUME: u8 = 0b0000_0011;
+// Gains above 32 are as loud as 32
+const MAX_GAIN: u8 = 32;
+// The wave table at its peak of 63 and the gain at its maximum
+const MAX_OUTPUT: f32 = 63.0 * 32.0;
+// Master volumes of 2/2, 2/3, 2/4 and 2/5
+const MASTER_VOLUMES: [f32; 4] = [1.0, 2.0 / 3.0, 0.5, 0.4];
+// Added to the modulation counter for each value in the modulation table, with
+// 4 resetting it instead
+const MODULATION_STEPS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+const MODULATION_RESET: u8 = 4;
+const WAVE_LENGTH: usize = 64;
+// The wave accumulator holds 16 bits of fraction below the position in the
+// wave table
+const WAVE_FRACTION_BITS: u32 = 16;
+
+// The FDS's single sound channel, which steps through a 64 entry table of 6
+// bit samples. Its pitch can be bent by a modulation unit, which steps through
+// a table of its own. Registers:
+//   $4040-$407F  Wave table, writable while $4089 allows it
+//   $4080        Volume envelope
+//   $4082-$4083  Wave frequency, halt and envelope halt
+//   $4084        Modulation envelope
+//   $4085        Modulation counter
+//   $4086-$4087  Modulation frequency and halt
+//   $4088        Appends to the modulation table while modulation is halted
+//   $4089        Wave table write enable and master volume
+//   $408A        Envelope speed, shared by both envelopes
+//   $4090, $4092 Volume and modulation gain (read only)
+struct FdsAudio {
+    wave_table: [u8; WAVE_LENGTH],
+    wave_frequency: u16,
+    wave_control: u8,
+    wave_accumulator: u32,
+    volume: FdsEnvelope,
+    // The volume gain only takes effect at the start of the wave
+    output_gain: u8,
+    modulation: FdsEnvelope,
+    modulation_table: [u8; WAVE_LENGTH],
+    modulation_position: usize,
+    // 7-bit signed
+    modulation_counter: i8,
+    modulation_frequency: u16,
+    modulation_control: u8,
+    modulation_accumulator: u16,
+    master: u8,
+    envelope_speed: u8,
+    // Held while the wave table is being written
+    output: f32,
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        FdsAudio {
+            wave_table: [0; WAVE_LENGTH],
+            wave_frequency: 0,
+            wave_control: WAVE_HALT,
+            wave_accumulator: 0,
+            volume: FdsEnvelope::default(),
+            output_gain: 0,
+            modulation: FdsEnvelope::default(),
+            modulation_table: [0; WAVE_LENGTH],
+            modulation_position: 0,
+            modulation_counter: 0,
+            modulation_frequency: 0,
+            modulation_control: MODULATION_HALT,
+            modulation_accumulator: 0,
+            master: 0,
+            // What the BIOS sets it to
+            envelope_speed: 0xE8,
+            output: 0.0,
+        }
+    }
+}
+
+impl FdsAudio {
+    fn write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0x4040..=0x407F if self.master & MASTER_WAVE_WRITE != 0 => self.wave_table[(addr - 0x4040) as usize] = data & 0x3F,
+            0x4080 => self.volume.write(data),
+            0x4082 => self.wave_frequency = (self.wave_frequency & 0x0F00) | data as u16,
+            0x4083 => {
+                self.wave_frequency = (self.wave_frequency & 0x00FF) | (((data & 0x0F) as u16) << 8);
+                self.wave_control = data;
+                if data & WAVE_HALT != 0 {
+                    self.wave_accumulator = 0;
+                }
+            }
+            0x4084 => self.modulation.write(data),
+            // Sign extends the low 7 bits
+            0x4085 => self.modulation_counter = ((data << 1) as i8) >> 1,
+            0x4086 => self.modulation_frequency = (self.modulation_frequency & 0x0F00) | data as u16,
+            0x4087 => {
+                self.modulation_frequency = (self.modulation_frequency & 0x00FF) | (((data & 0x0F) as u16) << 8);
+                self.modulation_control = data;
+                if data & MODULATION_HALT != 0 {
+                    self.modulation_accumulator = 0;
+                }
+            }
+            // Each write fills two entries of the table
+            0x4088 if self.modulation_control & MODULATION_HALT != 0 => {
+                for _ in 0..2 {
+                    self.modulation_table[self.modulation_position] = data & 0b111;
+                    self.modulation_position = (self.modulation_position + 1) % WAVE_LENGTH;
+                }
+            }
+            0x4089 => self.master = data,
+            0x408A => self.envelope_speed = data,
+            _ => {}
+        }
+    }
+
+    fn peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            0x4040..=0x407F => Some(self.wave_table[(addr - 0x4040) as usize]),
+            0x4090 => Some(self.volume.gain),
+            0x4092 => Some(self.modulation.gain),
+            _ => None,
+        }
+    }
+
+    fn wave_position(&self) -> usize {
+        (self.wave_accumulator >> WAVE_FRACTION_BITS) as usize % WAVE_LENGTH
+    }
+
+    // The wave frequency, bent by the modulation counter and gain. Rounds the
+    // way the hardware does, as worked out on the NESdev wiki.
+    fn modulated_frequency(&self) -> u32 {
+        let frequency = self.wave_frequency as i32;
+        let counter = self.modulation_counter as i32;
+        let mut bend = counter * self.modulation.gain as i32;
+        let remainder = bend & 0x0F;
+        bend >>= 4;
+        if remainder > 0 && bend & 0x80 == 0 {
+            bend += if counter < 0 { -1 } else { 2 };
+        }
+        if bend >= 192 {
+            bend -= 256;
+        } else if bend < -64 {
+            bend += 256;
+        }
+        bend *= frequency;
+        let remainder = bend & 0x3F;
+        bend >>= 6;
+        if remainder >= 32 {
+            bend += 1;
+        }
+        (frequency + bend).max(0) as u32
+    }
+
+    fn clock(&mut self) {
+        let halted = self.wave_control & WAVE_HALT != 0;
+        if !halted && self.wave_control & WAVE_ENVELOPES_HALT == 0 {
+            self.volume.clock(self.envelope_speed);
+            self.modulation.clock(self.envelope_speed);
+        }
+
+        if self.modulation_control & MODULATION_HALT == 0 && self.modulation_frequency > 0 {
+            let (accumulator, overflow) = self.modulation_accumulator.overflowing_add(self.modulation_frequency);
+            self.modulation_accumulator = accumulator;
+            if overflow {
+                let step = self.modulation_table[self.modulation_position];
+                self.modulation_counter = if step == MODULATION_RESET {
+                    0
+                } else {
+                    let counter = self.modulation_counter.wrapping_add(MODULATION_STEPS[step as usize]);
+                    (counter << 1) >> 1
+                };
+                self.modulation_position = (self.modulation_position + 1) % WAVE_LENGTH;
+            }
+        }
+
+        if self.master & MASTER_WAVE_WRITE != 0 {
+            return;
+        }
+        if !halted {
+            self.wave_accumulator = self.wave_accumulator.wrapping_add(self.modulated_frequency());
+        }
+        let position = self.wave_position();
+        if position == 0 {
+            self.output_gain = self.volume.gain.min(MAX_GAIN);
+        }
+        let level = self.wave_table[position] as f32 * self.output_gain as f32;
+        self.output = level / MAX_OUTPUT * MASTER_VOLUMES[(self.master & MASTER_VOLUME) as usize];
+    }
+
+    fn output(&self) -> f32 {
+        self.output
+    }
+}
+
+// Fades the volume or modulation gain in or out, or holds it at whatever was
+// last written
+#[derive(Default)]
+struct FdsEnvelope {
+    control: u8,
+    gain: u8,
+    timer: u32,
+}
+
+impl FdsEnvelope {
+    fn write(&mut self, data: u8) {
+        self.control = data;
+        self.timer = 0;
+        if data & ENVELOPE_DISABLE != 0 {
+            self.gain = data & ENVELOPE_SPEED;
+        }
+    }
+
+    // Steps the gain once every 8 * master speed * (speed + 1) CPU cycles. A
+    // master speed of 0 stops both envelopes.
+    fn clock(&mut self, master_speed: u8) {
+        if self.control & ENVELOPE_DISABLE != 0 || master_speed == 0 {
+            return;
+        }
+        self.timer += 1;
+        let period = 8 * master_speed as u32 * ((self.control & ENVELOPE_SPEED) as u32 + 1);
+        if self.timer < period {
+            return;
+        }
+        self.timer = 0;
+        if self.control & ENVELOPE_INCREASE != 0 {
+            if self.gain < MAX_GAIN {
+                self.gain += 1;
+            }
+        } else if self.gain > 0 {
+            self.gain -= 1;
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use crate::cartridge::header::{CartridgeHeader, Mirroring};
+use crate::cartridge::mapper::{chr_memory, has_bus_conflicts, mirrored, Mapper, PRG_ROM_START};
+
+const PRG_BANK_SIZE: usize = 0x8000;
+const CHR_BANK_SIZE: usize = 0x2000;
+
+// Mapper 66, GNROM and MHROM. Writes to $8000-$FFFF pick a 32 KiB PRG bank
+// with bits 4-5 and an 8 KiB CHR bank with bits 0-1. Mirroring is soldered.
+pub struct Gxrom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    bank: u8,
+}
+
+impl Gxrom {
+    pub fn new(header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let (chr, chr_is_ram) = chr_memory(header, chr_rom);
+        Gxrom {
+            prg_rom,
+            chr,
+            chr_is_ram,
+            mirroring: header.mirroring,
+            bus_conflicts: has_bus_conflicts(header, true),
+            bank: 0,
+        }
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        let bank = (self.bank & 0b11) as usize;
+        mirrored(self.chr.len(), bank * CHR_BANK_SIZE + addr as usize)
+    }
+}
+
+impl Mapper for Gxrom {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        if addr < PRG_ROM_START {
+            return None;
+        }
+        let bank = ((self.bank >> 4) & 0b11) as usize;
+        mirrored(self.prg_rom.len(), bank * PRG_BANK_SIZE + (addr - PRG_ROM_START) as usize).map(|index| self.prg_rom[index])
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= PRG_ROM_START {
+            self.bank = if self.bus_conflicts { data & self.cpu_peek(addr).unwrap_or(0xFF) } else { data };
+        }
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8 {
+        self.chr_index(addr).map_or(0, |index| self.chr[index])
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && let Some(index) = self.chr_index(addr) {
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
@@ -0,0 +1,207 @@
+use crate::cartridge::header::{CartridgeHeader, Mirroring};
+use crate::cartridge::mapper::{chr_memory, mirrored, Mapper, PRG_RAM_START, PRG_ROM_START};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+const CHR_BANK_SIZE: usize = 0x1000;
+const PRG_RAM_BANK_SIZE: usize = 0x2000;
+// SUROM and SXROM split their 512 KiB of PRG ROM into two halves, each banked
+// like a regular 256 KiB board
+const PRG_OUTER_BANK_SIZE: usize = 0x40000;
+const SOROM_PRG_RAM_SIZE: usize = 0x4000;
+const SXROM_PRG_RAM_SIZE: usize = 0x8000;
+
+const SHIFT_RESET: u8 = 0b1000_0000;
+const SHIFT_REGISTER_BITS: u8 = 5;
+const CONTROL_MIRRORING: u8 = 0b0_0011;
+const CONTROL_PRG_MODE: u8 = 0b0_1100;
+const CONTROL_CHR_4K: u8 = 0b1_0000;
+// Fixes the last PRG bank at $C000, the mode the MMC1 powers up in
+const CONTROL_PRG_FIX_LAST: u8 = 0b0_1100;
+const PRG_BANK_RAM_DISABLE: u8 = 0b1_0000;
+// Board specific uses of the CHR bank lines, see `Mmc1`
+const CHR_BANK_PRG_OUTER: u8 = 0b1_0000;
+const CHR_BANK_SNROM_RAM_DISABLE: u8 = 0b1_0000;
+
+// Mapper 1, the MMC1 found on Nintendo's SxROM boards. Registers are written
+// one bit at a time through a serial port at $8000-$FFFF: five writes fill
+// the shift register, and the fifth one copies it into the register picked by
+// bits 13-14 of its address:
+//   $8000-$9FFF  Control: mirroring, PRG banking mode and CHR banking mode
+//   $A000-$BFFF  CHR bank for $0000 (or all 8 KiB)
+//   $C000-$DFFF  CHR bank for $1000
+//   $E000-$FFFF  PRG bank and PRG RAM enable
+//
+// Larger boards reuse the upper CHR bank lines, which they don't need with
+// only 8 KiB of CHR:
+//   SNROM  bit 4 disables PRG RAM
+//   SOROM  bit 3 selects the 8 KiB bank of PRG RAM
+//   SUROM  bit 4 selects the 256 KiB half of PRG ROM
+//   SXROM  bit 4 like SUROM, bits 2-3 select the 8 KiB bank of PRG RAM
+// These go by the CHR bank for $0000, like most emulators do. Real boards use
+// whichever register the PPU last fetched through in 4 KiB mode.
+pub struct Mmc1 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    shift_register: u8,
+    shift_count: u8,
+    control: u8,
+    chr_bank_0: u8,
+    chr_bank_1: u8,
+    prg_bank: u8,
+    // The MMC1 ignores a write on the cycle right after another one, which
+    // read-modify-write instructions run into with their dummy write
+    wrote_last_cycle: bool,
+    wrote_this_cycle: bool,
+}
+
+impl Mmc1 {
+    pub fn new(header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let (chr, chr_is_ram) = chr_memory(header, chr_rom);
+        Mmc1 {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size + header.prg_nvram_size],
+            chr,
+            chr_is_ram,
+            shift_register: 0,
+            shift_count: 0,
+            control: CONTROL_PRG_FIX_LAST,
+            chr_bank_0: 0,
+            chr_bank_1: 0,
+            prg_bank: 0,
+            wrote_last_cycle: false,
+            wrote_this_cycle: false,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        if data & SHIFT_RESET != 0 {
+            self.shift_register = 0;
+            self.shift_count = 0;
+            self.control |= CONTROL_PRG_FIX_LAST;
+            return;
+        }
+
+        self.shift_register |= (data & 1) << self.shift_count;
+        self.shift_count += 1;
+        if self.shift_count < SHIFT_REGISTER_BITS {
+            return;
+        }
+
+        let value = self.shift_register;
+        self.shift_register = 0;
+        self.shift_count = 0;
+        match addr {
+            0x8000..=0x9FFF => self.control = value,
+            0xA000..=0xBFFF => self.chr_bank_0 = value,
+            0xC000..=0xDFFF => self.chr_bank_1 = value,
+            _ => self.prg_bank = value,
+        }
+    }
+
+    fn prg_rom_index(&self, addr: u16) -> Option<usize> {
+        let bank = (self.prg_bank & 0x0F) as usize;
+        let bank = match ((self.control & CONTROL_PRG_MODE) >> 2, addr) {
+            // 32 KiB at a time, ignoring the low bit of the bank number
+            (0 | 1, 0x8000..=0xBFFF) => bank & !1,
+            (0 | 1, _) => bank | 1,
+            (2, 0x8000..=0xBFFF) => 0,
+            (2, _) => bank,
+            (_, 0x8000..=0xBFFF) => bank,
+            (_, _) => 0x0F,
+        };
+        let outer_bank = if self.prg_rom.len() > PRG_OUTER_BANK_SIZE && self.chr_bank_0 & CHR_BANK_PRG_OUTER != 0 {
+            PRG_OUTER_BANK_SIZE
+        } else {
+            0
+        };
+        let offset = (addr - PRG_ROM_START) as usize % PRG_BANK_SIZE;
+        mirrored(self.prg_rom.len(), outer_bank + bank * PRG_BANK_SIZE + offset)
+    }
+
+    fn prg_ram_index(&self, addr: u16) -> Option<usize> {
+        if self.prg_bank & PRG_BANK_RAM_DISABLE != 0 {
+            return None;
+        }
+        let bank = match self.prg_ram.len() {
+            SXROM_PRG_RAM_SIZE => (self.chr_bank_0 >> 2) & 0b11,
+            SOROM_PRG_RAM_SIZE => (self.chr_bank_0 >> 3) & 1,
+            _ if self.chr_is_ram && self.chr_bank_0 & CHR_BANK_SNROM_RAM_DISABLE != 0 => return None,
+            _ => 0,
+        };
+        let offset = (addr - PRG_RAM_START) as usize;
+        mirrored(self.prg_ram.len(), bank as usize * PRG_RAM_BANK_SIZE + offset)
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        let bank = if self.control & CONTROL_CHR_4K == 0 {
+            // 8 KiB at a time, ignoring the low bit of the bank number
+            (self.chr_bank_0 & !1) as usize + (addr as usize / CHR_BANK_SIZE)
+        } else if addr < CHR_BANK_SIZE as u16 {
+            self.chr_bank_0 as usize
+        } else {
+            self.chr_bank_1 as usize
+        };
+        mirrored(self.chr.len(), bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE)
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START..=u16::MAX => self.prg_rom_index(addr).map(|index| self.prg_rom[index]),
+            PRG_RAM_START..PRG_ROM_START => self.prg_ram_index(addr).map(|index| self.prg_ram[index]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            PRG_ROM_START..=u16::MAX => {
+                if !self.wrote_last_cycle {
+                    self.write_register(addr, data);
+                }
+                self.wrote_this_cycle = true;
+            }
+            PRG_RAM_START..PRG_ROM_START => {
+                if let Some(index) = self.prg_ram_index(addr) {
+                    self.prg_ram[index] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8 {
+        self.chr_index(addr).map_or(0, |index| self.chr[index])
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && let Some(index) = self.chr_index(addr) {
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & CONTROL_MIRRORING {
+            0 => Mirroring::SingleScreenLower,
+            1 => Mirroring::SingleScreenUpper,
+            2 => Mirroring::Vertical,
+            _ => Mirroring::Horizontal,
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        self.wrote_last_cycle = self.wrote_this_cycle;
+        self.wrote_this_cycle = false;
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+}
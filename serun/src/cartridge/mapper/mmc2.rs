@@ -0,0 +1,154 @@
+use crate::cartridge::header::{CartridgeHeader, Mirroring};
+use crate::cartridge::mapper::{chr_memory, mirrored, Mapper, PRG_RAM_START, PRG_ROM_START};
+
+const CHR_BANK_SIZE: usize = 0x1000;
+const MMC2_PRG_BANK_SIZE: usize = 0x2000;
+const MMC4_PRG_BANK_SIZE: usize = 0x4000;
+
+// The two chips only differ in how they bank PRG ROM and in which fetches
+// flip the latch for $0000-$0FFF
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mmc2Chip {
+    // Mapper 9, PxROM (Punch-Out!!). An 8 KiB PRG bank at $8000 and the last
+    // three fixed after it.
+    Mmc2,
+    // Mapper 10, FxROM (the Fire Emblem games). A 16 KiB PRG bank at $8000,
+    // the last one fixed at $C000, and 8 KiB of PRG RAM at $6000.
+    Mmc4,
+}
+
+// Which of its two CHR banks each half of the pattern tables shows is picked
+// by a latch, which the PPU flips by fetching tile $FD or $FE from that half.
+// Games use this to switch CHR banks partway through the frame without the
+// CPU's help. Registers:
+//   $A000-$AFFF  PRG bank
+//   $B000-$BFFF  CHR bank for $0000-$0FFF when the latch reads $FD
+//   $C000-$CFFF  CHR bank for $0000-$0FFF when the latch reads $FE
+//   $D000-$DFFF  CHR bank for $1000-$1FFF when the latch reads $FD
+//   $E000-$EFFF  CHR bank for $1000-$1FFF when the latch reads $FE
+//   $F000-$FFFF  Mirroring
+pub struct Mmc2 {
+    chip: Mmc2Chip,
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    prg_bank: u8,
+    // Indexed by pattern table, then by latch
+    chr_banks: [[u8; 2]; 2],
+    // false for $FD, true for $FE
+    latches: [bool; 2],
+    mirroring: Mirroring,
+}
+
+impl Mmc2 {
+    pub fn new(chip: Mmc2Chip, header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let (chr, chr_is_ram) = chr_memory(header, chr_rom);
+        Mmc2 {
+            chip,
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size + header.prg_nvram_size],
+            chr,
+            chr_is_ram,
+            prg_bank: 0,
+            chr_banks: [[0; 2]; 2],
+            latches: [true; 2],
+            mirroring: header.mirroring,
+        }
+    }
+
+    fn prg_rom_index(&self, addr: u16) -> Option<usize> {
+        let bank_size = match self.chip {
+            Mmc2Chip::Mmc2 => MMC2_PRG_BANK_SIZE,
+            Mmc2Chip::Mmc4 => MMC4_PRG_BANK_SIZE,
+        };
+        let offset = (addr - PRG_ROM_START) as usize;
+        let index = if offset < bank_size {
+            (self.prg_bank & 0x0F) as usize * bank_size + offset
+        } else {
+            // Everything after the switchable bank is fixed to the end of
+            // PRG ROM
+            (self.prg_rom.len() + offset).wrapping_sub(0x8000)
+        };
+        mirrored(self.prg_rom.len(), index)
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        let table = (addr as usize / CHR_BANK_SIZE) & 1;
+        let bank = self.chr_banks[table][self.latches[table] as usize] & 0x1F;
+        mirrored(self.chr.len(), bank as usize * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE)
+    }
+
+    // The latch flips once the PPU fetches the high bit plane of the tile
+    // ($xFD8-$xFDF or $xFE8-$xFEF), so the trigger tile itself is still drawn
+    // from the old bank. The MMC2 only reacts to the first byte of the plane
+    // in the first pattern table.
+    fn update_latch(&mut self, addr: u16) {
+        let tile_addr = if self.chip == Mmc2Chip::Mmc2 && addr < 0x1000 { addr } else { addr & !0b111 };
+        let latch = match tile_addr & 0x0FFF {
+            0x0FD8 => Some(false),
+            0x0FE8 => Some(true),
+            _ => None,
+        };
+        if let Some(latch) = latch {
+            self.latches[(addr as usize / CHR_BANK_SIZE) & 1] = latch;
+        }
+    }
+}
+
+impl Mapper for Mmc2 {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START..=u16::MAX => self.prg_rom_index(addr).map(|index| self.prg_rom[index]),
+            PRG_RAM_START..PRG_ROM_START => mirrored(self.prg_ram.len(), (addr - PRG_RAM_START) as usize).map(|index| self.prg_ram[index]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            0xA000..=0xAFFF => self.prg_bank = data,
+            0xB000..=0xBFFF => self.chr_banks[0][0] = data,
+            0xC000..=0xCFFF => self.chr_banks[0][1] = data,
+            0xD000..=0xDFFF => self.chr_banks[1][0] = data,
+            0xE000..=0xEFFF => self.chr_banks[1][1] = data,
+            0xF000..=0xFFFF => {
+                self.mirroring = if data & 1 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            }
+            PRG_RAM_START..PRG_ROM_START => {
+                if let Some(index) = mirrored(self.prg_ram.len(), (addr - PRG_RAM_START) as usize) {
+                    self.prg_ram[index] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        let data = self.ppu_peek(addr);
+        self.update_latch(addr);
+        data
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8 {
+        self.chr_index(addr).map_or(0, |index| self.chr[index])
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && let Some(index) = self.chr_index(addr) {
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+}
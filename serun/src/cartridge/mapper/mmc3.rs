@@ -0,0 +1,248 @@
+use crate::cartridge::header::{CartridgeHeader, HeaderFormat, Mirroring};
+use crate::cartridge::mapper::{chr_memory, mirrored, Mapper, PRG_RAM_START, PRG_ROM_START};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const PPU_A12: u16 = 0x1000;
+// How long A12 has to stay low before the next rise clocks the IRQ counter,
+// in CPU cycles. This filters out the rises between the sprite pattern fetches
+// of a scanline, so that only the one per scanline counts.
+const A12_LOW_FILTER_CYCLES: u8 = 3;
+// NES 2.0 submapper for boards with an MMC3A
+const SUBMAPPER_MMC3A: u8 = 4;
+
+const BANK_SELECT_REGISTER: u8 = 0b0000_0111;
+const BANK_SELECT_PRG_SWAP: u8 = 0b0100_0000;
+const BANK_SELECT_CHR_INVERT: u8 = 0b1000_0000;
+const PRG_RAM_WRITE_PROTECT: u8 = 0b0100_0000;
+const PRG_RAM_ENABLE: u8 = 0b1000_0000;
+
+// Which MMC3 the board has. They only differ in how the IRQ counter behaves
+// once it has been reloaded with 0.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mmc3Revision {
+    // MMC3B and MMC3C made by Sharp fire an IRQ on every clock that leaves the
+    // counter at 0, so a latch of 0 fires one every scanline
+    Sharp,
+    // MMC3A and the MMC3B made by NEC only fire one when the counter gets to
+    // 0 by counting down or by a reload requested through $C001
+    Nec,
+}
+
+// Mapper 4, the MMC3 found on Nintendo's TxROM boards. Registers are picked
+// by bits 13-14 and bit 0 of the address written to:
+//   $8000  Bank select: which bank register $8001 writes, the PRG and CHR
+//          banking modes
+//   $8001  Bank data
+//   $A000  Mirroring, unless the board is wired for four screens
+//   $A001  PRG RAM enable and write protect
+//   $C000  IRQ counter reload value
+//   $C001  Reloads the IRQ counter on its next clock
+//   $E000  Disables and acknowledges the IRQ
+//   $E001  Enables the IRQ
+//
+// The IRQ counter is clocked by rises of the PPU's A12 address line, which
+// happen once every scanline when backgrounds and sprites use different
+// pattern tables.
+pub struct Mmc3 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    revision: Mmc3Revision,
+    four_screen: bool,
+    bank_select: u8,
+    // R0-R5 pick CHR banks, R6-R7 pick PRG banks
+    bank_registers: [u8; 8],
+    mirroring: Mirroring,
+    prg_ram_protect: u8,
+    irq_latch: u8,
+    irq_counter: u8,
+    irq_reload: bool,
+    irq_enabled: bool,
+    irq_pending: bool,
+    a12_high: bool,
+    a12_low_cycles: u8,
+}
+
+impl Mmc3 {
+    pub fn new(header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let revision = if header.format == HeaderFormat::Nes20 && header.submapper == SUBMAPPER_MMC3A {
+            Mmc3Revision::Nec
+        } else {
+            Mmc3Revision::Sharp
+        };
+        Self::with_revision(header, prg_rom, chr_rom, revision)
+    }
+
+    // For picking the revision when the header doesn't say which one the
+    // board has, which it usually doesn't
+    pub fn with_revision(header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>, revision: Mmc3Revision) -> Self {
+        let (chr, chr_is_ram) = chr_memory(header, chr_rom);
+        Mmc3 {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size + header.prg_nvram_size],
+            chr,
+            chr_is_ram,
+            revision,
+            four_screen: header.mirroring == Mirroring::FourScreen,
+            bank_select: 0,
+            bank_registers: [0, 2, 4, 5, 6, 7, 0, 1],
+            mirroring: header.mirroring,
+            // Some games use PRG RAM without ever enabling it
+            prg_ram_protect: PRG_RAM_ENABLE,
+            irq_latch: 0,
+            irq_counter: 0,
+            irq_reload: false,
+            irq_enabled: false,
+            irq_pending: false,
+            a12_high: false,
+            a12_low_cycles: 0,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match (addr & 0xE001, self.four_screen) {
+            (0x8000, _) => self.bank_select = data,
+            (0x8001, _) => self.bank_registers[(self.bank_select & BANK_SELECT_REGISTER) as usize] = data,
+            (0xA000, false) => {
+                self.mirroring = if data & 1 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            }
+            (0xA000, true) => {}
+            (0xA001, _) => self.prg_ram_protect = data,
+            (0xC000, _) => self.irq_latch = data,
+            (0xC001, _) => {
+                self.irq_counter = 0;
+                self.irq_reload = true;
+            }
+            (0xE000, _) => {
+                self.irq_enabled = false;
+                self.irq_pending = false;
+            }
+            _ => self.irq_enabled = true,
+        }
+    }
+
+    fn prg_rom_index(&self, addr: u16) -> Option<usize> {
+        let second_last = (self.prg_rom.len() / PRG_BANK_SIZE).wrapping_sub(2);
+        let swapped = self.bank_select & BANK_SELECT_PRG_SWAP != 0;
+        let bank = match (addr, swapped) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => (self.bank_registers[6] & 0x3F) as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            (0xA000..=0xBFFF, _) => (self.bank_registers[7] & 0x3F) as usize,
+            _ => second_last.wrapping_add(1),
+        };
+        let offset = addr as usize % PRG_BANK_SIZE;
+        mirrored(self.prg_rom.len(), bank.wrapping_mul(PRG_BANK_SIZE).wrapping_add(offset))
+    }
+
+    fn prg_ram_index(&self, addr: u16) -> Option<usize> {
+        if self.prg_ram_protect & PRG_RAM_ENABLE == 0 {
+            return None;
+        }
+        mirrored(self.prg_ram.len(), (addr - PRG_RAM_START) as usize)
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        // Inverting swaps the 2 KiB banks over to $1000-$1FFF and the 1 KiB
+        // banks over to $0000-$0FFF
+        let addr = if self.bank_select & BANK_SELECT_CHR_INVERT != 0 { addr ^ PPU_A12 } else { addr };
+        let bank = match addr {
+            0x0000..=0x07FF => (self.bank_registers[0] & !1) as usize + (addr as usize / CHR_BANK_SIZE) % 2,
+            0x0800..=0x0FFF => (self.bank_registers[1] & !1) as usize + (addr as usize / CHR_BANK_SIZE) % 2,
+            _ => self.bank_registers[2 + (addr as usize - 0x1000) / CHR_BANK_SIZE] as usize,
+        };
+        mirrored(self.chr.len(), bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE)
+    }
+
+    // Keeps track of the PPU's A12 line through the addresses of its pattern
+    // table accesses
+    fn watch_a12(&mut self, addr: u16) {
+        let a12_high = addr & PPU_A12 != 0;
+        if a12_high && !self.a12_high && self.a12_low_cycles >= A12_LOW_FILTER_CYCLES {
+            self.clock_irq_counter();
+        }
+        if !a12_high && self.a12_high {
+            self.a12_low_cycles = 0;
+        }
+        self.a12_high = a12_high;
+    }
+
+    fn clock_irq_counter(&mut self) {
+        let counter = self.irq_counter;
+        if counter == 0 || self.irq_reload {
+            self.irq_counter = self.irq_latch;
+        } else {
+            self.irq_counter -= 1;
+        }
+        let fires = match self.revision {
+            Mmc3Revision::Sharp => self.irq_counter == 0,
+            Mmc3Revision::Nec => self.irq_counter == 0 && (counter != 0 || self.irq_reload),
+        };
+        if fires && self.irq_enabled {
+            self.irq_pending = true;
+        }
+        self.irq_reload = false;
+    }
+}
+
+impl Mapper for Mmc3 {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START..=u16::MAX => self.prg_rom_index(addr).map(|index| self.prg_rom[index]),
+            PRG_RAM_START..PRG_ROM_START => self.prg_ram_index(addr).map(|index| self.prg_ram[index]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            PRG_ROM_START..=u16::MAX => self.write_register(addr, data),
+            PRG_RAM_START..PRG_ROM_START => {
+                if self.prg_ram_protect & PRG_RAM_WRITE_PROTECT == 0
+                    && let Some(index) = self.prg_ram_index(addr) {
+                    self.prg_ram[index] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_read(&mut self, addr: u16) -> u8 {
+        self.watch_a12(addr);
+        self.ppu_peek(addr)
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8 {
+        self.chr_index(addr).map_or(0, |index| self.chr[index])
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        self.watch_a12(addr);
+        if self.chr_is_ram && let Some(index) = self.chr_index(addr) {
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn cpu_clock(&mut self) {
+        if !self.a12_high {
+            self.a12_low_cycles = self.a12_low_cycles.saturating_add(1);
+        }
+    }
+
+    fn irq(&self) -> bool {
+        self.irq_pending
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+}
@@ -0,0 +1,65 @@
+use crate::cartridge::header::{CartridgeHeader, Mirroring};
+use crate::cartridge::mapper::{chr_memory, mirrored, Mapper, PRG_RAM_START, PRG_ROM_START};
+
+// Mapper 0, boards without any bank switching. NROM-128 has 16 KiB of PRG ROM
+// mirrored into both halves of $8000-$FFFF, NROM-256 fills it with 32 KiB.
+// A few boards (like Family BASIC) have PRG RAM at $6000-$7FFF.
+pub struct Nrom {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+}
+
+impl Nrom {
+    pub fn new(header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let (chr, chr_is_ram) = chr_memory(header, chr_rom);
+        Nrom {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size + header.prg_nvram_size],
+            chr,
+            chr_is_ram,
+            mirroring: header.mirroring,
+        }
+    }
+}
+
+impl Mapper for Nrom {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START..=u16::MAX => mirrored(self.prg_rom.len(), (addr - PRG_ROM_START) as usize).map(|index| self.prg_rom[index]),
+            PRG_RAM_START..PRG_ROM_START => mirrored(self.prg_ram.len(), (addr - PRG_RAM_START) as usize).map(|index| self.prg_ram[index]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if (PRG_RAM_START..PRG_ROM_START).contains(&addr)
+            && let Some(index) = mirrored(self.prg_ram.len(), (addr - PRG_RAM_START) as usize) {
+            self.prg_ram[index] = data;
+        }
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8 {
+        mirrored(self.chr.len(), addr as usize).map_or(0, |index| self.chr[index])
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && let Some(index) = mirrored(self.chr.len(), addr as usize) {
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+}
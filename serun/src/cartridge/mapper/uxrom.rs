@@ -0,0 +1,65 @@
+use crate::cartridge::header::{CartridgeHeader, Mirroring};
+use crate::cartridge::mapper::{chr_memory, has_bus_conflicts, mirrored, Mapper, PRG_ROM_START};
+
+const PRG_BANK_SIZE: usize = 0x4000;
+
+// Mapper 2, UNROM and UOROM. A 16 KiB PRG bank picked by writes to
+// $8000-$FFFF is mapped at $8000, the last bank is fixed at $C000. CHR is 8 KiB
+// of unbanked RAM and mirroring is soldered.
+pub struct Uxrom {
+    prg_rom: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    mirroring: Mirroring,
+    bus_conflicts: bool,
+    prg_bank: u8,
+}
+
+impl Uxrom {
+    pub fn new(header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let (chr, chr_is_ram) = chr_memory(header, chr_rom);
+        Uxrom {
+            prg_rom,
+            chr,
+            chr_is_ram,
+            mirroring: header.mirroring,
+            bus_conflicts: has_bus_conflicts(header, true),
+            prg_bank: 0,
+        }
+    }
+}
+
+impl Mapper for Uxrom {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        if addr < PRG_ROM_START {
+            return None;
+        }
+        let offset = (addr - PRG_ROM_START) as usize % PRG_BANK_SIZE;
+        let index = match addr {
+            0x8000..=0xBFFF => self.prg_bank as usize * PRG_BANK_SIZE + offset,
+            // The last bank, wrapping around for ROMs smaller than one
+            _ => (self.prg_rom.len() + offset).wrapping_sub(PRG_BANK_SIZE),
+        };
+        mirrored(self.prg_rom.len(), index).map(|index| self.prg_rom[index])
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        if addr >= PRG_ROM_START {
+            self.prg_bank = if self.bus_conflicts { data & self.cpu_peek(addr).unwrap_or(0xFF) } else { data };
+        }
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8 {
+        mirrored(self.chr.len(), addr as usize).map_or(0, |index| self.chr[index])
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && let Some(index) = mirrored(self.chr.len(), addr as usize) {
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+}
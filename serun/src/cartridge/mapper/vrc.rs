@@ -0,0 +1,95 @@
+// Pieces shared by Konami's VRC mappers
+
+// The VRCs pick their registers by two address lines, which each board wires
+// up to different CPU address lines. `lines` holds the CPU lines going to the
+// chip's A0 and A1 as masks. Boards whose wiring isn't known can OR several
+// lines together, since games only ever set the ones their board uses.
+// Returns the address with A0 and A1 moved down to bits 0 and 1.
+pub(crate) fn register_address(addr: u16, lines: [u16; 2]) -> u16 {
+    let a0 = (addr & lines[0] != 0) as u16;
+    let a1 = (addr & lines[1] != 0) as u16;
+    (addr & 0xF000) | (a1 << 1) | a0
+}
+
+// The prescaler divides the CPU clock by 113.667, about the length of a
+// scanline, by counting down 3 for each CPU cycle from 341 (PPU dots per
+// scanline)
+const PRESCALER_PERIOD: i16 = 341;
+const PRESCALER_STEP: i16 = 3;
+
+const CONTROL_ENABLE_AFTER_ACK: u8 = 0b001;
+const CONTROL_ENABLE: u8 = 0b010;
+const CONTROL_CYCLE_MODE: u8 = 0b100;
+
+// The IRQ counter of the VRC4, VRC6 and VRC7. It counts up either every CPU
+// cycle or every scanline's worth of them, and fires when it overflows, after
+// which it starts over from the latch.
+#[derive(Default)]
+pub(crate) struct VrcIrq {
+    latch: u8,
+    counter: u8,
+    prescaler: i16,
+    enabled: bool,
+    enable_after_ack: bool,
+    cycle_mode: bool,
+    pending: bool,
+}
+
+impl VrcIrq {
+    pub(crate) fn write_latch(&mut self, data: u8) {
+        self.latch = data;
+    }
+
+    // The VRC4 takes the latch 4 bits at a time
+    pub(crate) fn write_latch_low(&mut self, data: u8) {
+        self.latch = (self.latch & 0xF0) | (data & 0x0F);
+    }
+
+    pub(crate) fn write_latch_high(&mut self, data: u8) {
+        self.latch = (self.latch & 0x0F) | (data << 4);
+    }
+
+    pub(crate) fn write_control(&mut self, data: u8) {
+        self.enable_after_ack = data & CONTROL_ENABLE_AFTER_ACK != 0;
+        self.enabled = data & CONTROL_ENABLE != 0;
+        self.cycle_mode = data & CONTROL_CYCLE_MODE != 0;
+        if self.enabled {
+            self.counter = self.latch;
+            self.prescaler = PRESCALER_PERIOD;
+        }
+        self.pending = false;
+    }
+
+    pub(crate) fn acknowledge(&mut self) {
+        self.pending = false;
+        self.enabled = self.enable_after_ack;
+    }
+
+    pub(crate) fn clock(&mut self) {
+        if !self.enabled {
+            return;
+        }
+        if self.cycle_mode {
+            self.clock_counter();
+            return;
+        }
+        self.prescaler -= PRESCALER_STEP;
+        if self.prescaler <= 0 {
+            self.prescaler += PRESCALER_PERIOD;
+            self.clock_counter();
+        }
+    }
+
+    fn clock_counter(&mut self) {
+        if self.counter == 0xFF {
+            self.counter = self.latch;
+            self.pending = true;
+        } else {
+            self.counter += 1;
+        }
+    }
+
+    pub(crate) fn pending(&self) -> bool {
+        self.pending
+    }
+}
@@ -0,0 +1,195 @@
+use crate::cartridge::header::{CartridgeHeader, HeaderFormat, Mirroring};
+use crate::cartridge::mapper::vrc::{register_address, VrcIrq};
+use crate::cartridge::mapper::{chr_memory, mirrored, Mapper, PRG_RAM_START, PRG_ROM_START};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+const LATCH_END: u16 = 0x6FFF;
+const PRG_SWAP_MODE: u8 = 0b10;
+
+const A0: u16 = 1 << 0;
+const A1: u16 = 1 << 1;
+const A2: u16 = 1 << 2;
+const A3: u16 = 1 << 3;
+const A6: u16 = 1 << 6;
+const A7: u16 = 1 << 7;
+
+// Mappers 21, 22, 23 and 25, Konami's VRC2 and VRC4. The VRC4 is a VRC2 with
+// an IRQ counter, a second PRG banking mode, single screen mirroring and
+// wider CHR bank numbers. Registers, as seen by the chip's own address lines:
+//   $8000-$8003  PRG bank for $8000 (or $C000 when swapped)
+//   $9000-$9001  Mirroring
+//   $9002-$9003  PRG swap mode (VRC4)
+//   $A000-$A003  PRG bank for $A000
+//   $B000-$E003  1 KiB CHR banks, two registers each for the low and high
+//                bits of the bank number
+//   $F000-$F003  IRQ latch low and high bits, control and acknowledge (VRC4)
+//
+// Each mapper number covers boards that wire the chip's A0 and A1 to
+// different CPU address lines. NES 2.0 submappers tell them apart:
+//   21  1: VRC4a (A1, A2)  2: VRC4c (A6, A7)
+//   22  VRC2a (A1, A0), which also drops the low bit of CHR bank numbers
+//   23  1: VRC4f (A0, A1)  2: VRC4e (A2, A3)  3: VRC2b (A0, A1)
+//   25  1: VRC4b (A1, A0)  2: VRC4d (A3, A2)  3: VRC2c (A1, A0)
+// Without a submapper, the lines of every variant sharing the mapper number
+// are ORed together and the chip is assumed to be a VRC4.
+pub struct Vrc4 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    vrc2: bool,
+    lines: [u16; 2],
+    chr_shift: u8,
+    prg_banks: [u8; 2],
+    prg_swap_mode: bool,
+    chr_banks: [u16; 8],
+    mirroring: Mirroring,
+    irq: VrcIrq,
+    // Boards without PRG RAM have a single bit latch at $6000-$6FFF instead,
+    // which some games use to check for RAM
+    latch: u8,
+}
+
+impl Vrc4 {
+    pub fn new(header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let submapper = if header.format == HeaderFormat::Nes20 { header.submapper } else { 0 };
+        let (vrc2, lines) = match (header.mapper, submapper) {
+            (21, 1) => (false, [A1, A2]),
+            (21, 2) => (false, [A6, A7]),
+            (21, _) => (false, [A1 | A6, A2 | A7]),
+            (22, _) => (true, [A1, A0]),
+            (23, 1) => (false, [A0, A1]),
+            (23, 2) => (false, [A2, A3]),
+            (23, 3) => (true, [A0, A1]),
+            (23, _) => (false, [A0 | A2, A1 | A3]),
+            (25, 1) => (false, [A1, A0]),
+            (25, 2) => (false, [A3, A2]),
+            (25, 3) => (true, [A1, A0]),
+            _ => (false, [A1 | A3, A0 | A2]),
+        };
+        let (chr, chr_is_ram) = chr_memory(header, chr_rom);
+        Vrc4 {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size + header.prg_nvram_size],
+            chr,
+            chr_is_ram,
+            vrc2,
+            lines,
+            chr_shift: if header.mapper == 22 { 1 } else { 0 },
+            prg_banks: [0; 2],
+            prg_swap_mode: false,
+            chr_banks: [0; 8],
+            mirroring: header.mirroring,
+            irq: VrcIrq::default(),
+            latch: 0,
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match register_address(addr, self.lines) {
+            0x8000..=0x8003 => self.prg_banks[0] = data & 0x1F,
+            0x9000..=0x9003 if self.vrc2 => {
+                self.mirroring = if data & 1 == 0 { Mirroring::Vertical } else { Mirroring::Horizontal };
+            }
+            0x9000..=0x9001 => {
+                self.mirroring = match data & 0b11 {
+                    0 => Mirroring::Vertical,
+                    1 => Mirroring::Horizontal,
+                    2 => Mirroring::SingleScreenLower,
+                    _ => Mirroring::SingleScreenUpper,
+                };
+            }
+            0x9002..=0x9003 => self.prg_swap_mode = data & PRG_SWAP_MODE != 0,
+            0xA000..=0xA003 => self.prg_banks[1] = data & 0x1F,
+            addr @ 0xB000..=0xE003 => {
+                // $B000-$B001 is bank 0, $B002-$B003 bank 1, $C000-$C001 bank
+                // 2 and so on
+                let bank = ((addr >> 12) as usize - 0xB) * 2 + (addr as usize & 0b10) / 2;
+                let high_mask = if self.vrc2 { 0x0F } else { 0x1F };
+                self.chr_banks[bank] = if addr & 1 == 0 {
+                    (self.chr_banks[bank] & 0x1F0) | (data & 0x0F) as u16
+                } else {
+                    (self.chr_banks[bank] & 0x0F) | (((data & high_mask) as u16) << 4)
+                };
+            }
+            _ if self.vrc2 => {}
+            0xF000 => self.irq.write_latch_low(data),
+            0xF001 => self.irq.write_latch_high(data),
+            0xF002 => self.irq.write_control(data),
+            0xF003 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn prg_rom_index(&self, addr: u16) -> Option<usize> {
+        let second_last = (self.prg_rom.len() / PRG_BANK_SIZE).wrapping_sub(2);
+        let bank = match (addr, self.prg_swap_mode) {
+            (0x8000..=0x9FFF, false) | (0xC000..=0xDFFF, true) => self.prg_banks[0] as usize,
+            (0x8000..=0x9FFF, true) | (0xC000..=0xDFFF, false) => second_last,
+            (0xA000..=0xBFFF, _) => self.prg_banks[1] as usize,
+            _ => second_last.wrapping_add(1),
+        };
+        let offset = addr as usize % PRG_BANK_SIZE;
+        mirrored(self.prg_rom.len(), bank.wrapping_mul(PRG_BANK_SIZE).wrapping_add(offset))
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        let bank = (self.chr_banks[addr as usize / CHR_BANK_SIZE] >> self.chr_shift) as usize;
+        mirrored(self.chr.len(), bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE)
+    }
+}
+
+impl Mapper for Vrc4 {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START..=u16::MAX => self.prg_rom_index(addr).map(|index| self.prg_rom[index]),
+            PRG_RAM_START..=LATCH_END if self.prg_ram.is_empty() => Some(self.latch),
+            PRG_RAM_START..PRG_ROM_START => mirrored(self.prg_ram.len(), (addr - PRG_RAM_START) as usize).map(|index| self.prg_ram[index]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            PRG_ROM_START..=u16::MAX => self.write_register(addr, data),
+            PRG_RAM_START..=LATCH_END if self.prg_ram.is_empty() => self.latch = data & 1,
+            PRG_RAM_START..PRG_ROM_START => {
+                if let Some(index) = mirrored(self.prg_ram.len(), (addr - PRG_RAM_START) as usize) {
+                    self.prg_ram[index] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8 {
+        self.chr_index(addr).map_or(0, |index| self.chr[index])
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && let Some(index) = self.chr_index(addr) {
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+}
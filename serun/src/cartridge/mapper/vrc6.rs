@@ -0,0 +1,307 @@
+use crate::cartridge::header::{CartridgeHeader, Mirroring};
+use crate::cartridge::mapper::vrc::{register_address, VrcIrq};
+use crate::cartridge::mapper::{chr_memory, mirrored, Mapper, PRG_RAM_START, PRG_ROM_START};
+
+const PRG_16K_BANK_SIZE: usize = 0x4000;
+const PRG_8K_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+const BANKING_PPU_MODE: u8 = 0b0000_0011;
+const BANKING_MIRRORING: u8 = 0b0000_1100;
+const BANKING_PRG_RAM_ENABLE: u8 = 0b1000_0000;
+
+const AUDIO_HALT: u8 = 0b001;
+const AUDIO_FREQUENCY_X16: u8 = 0b010;
+const AUDIO_FREQUENCY_X256: u8 = 0b100;
+const CHANNEL_ENABLE: u8 = 0b1000_0000;
+const PULSE_IGNORE_DUTY: u8 = 0b1000_0000;
+// Both pulses at volume 15 and the sawtooth at its peak of 31
+const MAX_OUTPUT: f32 = 61.0;
+
+// Mappers 24 and 26, Konami's VRC6, as found in Akumajou Densetsu (VRC6a) and
+// Madara and Esper Dream 2 (VRC6b, with A0 and A1 swapped). Registers:
+//   $8000-$8003  16 KiB PRG bank for $8000
+//   $9000-$9003  Pulse 1, and $9003 scales all the audio frequencies
+//   $A000-$A002  Pulse 2
+//   $B000-$B002  Sawtooth
+//   $B003        PPU banking mode, mirroring and PRG RAM enable
+//   $C000-$C003  8 KiB PRG bank for $C000
+//   $D000-$E003  1 KiB CHR banks
+//   $F000-$F002  IRQ latch, control and acknowledge
+// The last 8 KiB of PRG ROM are fixed at $E000.
+//
+// The modes of $B003 that map CHR ROM in place of the console's nametable RAM
+// aren't supported, no licensed game uses them.
+pub struct Vrc6 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    lines: [u16; 2],
+    prg_16k_bank: u8,
+    prg_8k_bank: u8,
+    chr_banks: [u8; 8],
+    banking_control: u8,
+    irq: VrcIrq,
+    audio: Vrc6Audio,
+}
+
+impl Vrc6 {
+    pub fn new(header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let (chr, chr_is_ram) = chr_memory(header, chr_rom);
+        Vrc6 {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size + header.prg_nvram_size],
+            chr,
+            chr_is_ram,
+            lines: if header.mapper == 26 { [0b10, 0b01] } else { [0b01, 0b10] },
+            prg_16k_bank: 0,
+            prg_8k_bank: 0,
+            chr_banks: [0; 8],
+            banking_control: BANKING_PRG_RAM_ENABLE,
+            irq: VrcIrq::default(),
+            audio: Vrc6Audio::default(),
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        match register_address(addr, self.lines) {
+            0x8000..=0x8003 => self.prg_16k_bank = data & 0x0F,
+            0x9003 => self.audio.frequency_control = data,
+            addr @ 0x9000..=0x9002 => self.audio.pulses[0].write(addr & 0b11, data),
+            addr @ 0xA000..=0xA002 => self.audio.pulses[1].write(addr & 0b11, data),
+            addr @ 0xB000..=0xB002 => self.audio.sawtooth.write(addr & 0b11, data),
+            0xB003 => self.banking_control = data,
+            0xC000..=0xC003 => self.prg_8k_bank = data & 0x1F,
+            addr @ 0xD000..=0xE003 => {
+                let bank = ((addr >> 12) as usize - 0xD) * 4 + (addr as usize & 0b11);
+                self.chr_banks[bank] = data;
+            }
+            0xF000 => self.irq.write_latch(data),
+            0xF001 => self.irq.write_control(data),
+            0xF002 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn prg_rom_index(&self, addr: u16) -> Option<usize> {
+        let index = match addr {
+            0x8000..=0xBFFF => self.prg_16k_bank as usize * PRG_16K_BANK_SIZE + addr as usize % PRG_16K_BANK_SIZE,
+            0xC000..=0xDFFF => self.prg_8k_bank as usize * PRG_8K_BANK_SIZE + addr as usize % PRG_8K_BANK_SIZE,
+            _ => (self.prg_rom.len() + addr as usize % PRG_8K_BANK_SIZE).wrapping_sub(PRG_8K_BANK_SIZE),
+        };
+        mirrored(self.prg_rom.len(), index)
+    }
+
+    fn prg_ram_index(&self, addr: u16) -> Option<usize> {
+        if self.banking_control & BANKING_PRG_RAM_ENABLE == 0 {
+            return None;
+        }
+        mirrored(self.prg_ram.len(), (addr - PRG_RAM_START) as usize)
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        let slot = addr as usize / CHR_BANK_SIZE;
+        // 2 KiB banks take their low bit from the PPU's A10
+        let two_kib_bank = |register: usize| (self.chr_banks[register] & !1) as usize | (slot & 1);
+        let bank = match (self.banking_control & BANKING_PPU_MODE, slot) {
+            (0, _) => self.chr_banks[slot] as usize,
+            (1, _) => two_kib_bank(slot / 2),
+            (_, 0..=3) => self.chr_banks[slot] as usize,
+            (_, _) => two_kib_bank(4 + (slot - 4) / 2),
+        };
+        mirrored(self.chr.len(), bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE)
+    }
+}
+
+impl Mapper for Vrc6 {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START..=u16::MAX => self.prg_rom_index(addr).map(|index| self.prg_rom[index]),
+            PRG_RAM_START..PRG_ROM_START => self.prg_ram_index(addr).map(|index| self.prg_ram[index]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            PRG_ROM_START..=u16::MAX => self.write_register(addr, data),
+            PRG_RAM_START..PRG_ROM_START => {
+                if let Some(index) = self.prg_ram_index(addr) {
+                    self.prg_ram[index] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8 {
+        self.chr_index(addr).map_or(0, |index| self.chr[index])
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && let Some(index) = self.chr_index(addr) {
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match (self.banking_control & BANKING_MIRRORING) >> 2 {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+        self.audio.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    fn expansion_audio(&self) -> f32 {
+        self.audio.output() as f32 / MAX_OUTPUT
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+}
+
+// The VRC6's two pulse channels and sawtooth channel, all clocked by the CPU
+#[derive(Default)]
+struct Vrc6Audio {
+    pulses: [Vrc6Pulse; 2],
+    sawtooth: Vrc6Sawtooth,
+    frequency_control: u8,
+}
+
+impl Vrc6Audio {
+    fn clock(&mut self) {
+        if self.frequency_control & AUDIO_HALT != 0 {
+            return;
+        }
+        // Speeds every channel up by dropping the low bits of their periods
+        let shift = if self.frequency_control & AUDIO_FREQUENCY_X256 != 0 {
+            8
+        } else if self.frequency_control & AUDIO_FREQUENCY_X16 != 0 {
+            4
+        } else {
+            0
+        };
+        for pulse in &mut self.pulses {
+            pulse.clock(shift);
+        }
+        self.sawtooth.clock(shift);
+    }
+
+    fn output(&self) -> u8 {
+        self.pulses[0].output() + self.pulses[1].output() + self.sawtooth.output()
+    }
+}
+
+// Ticks down once every CPU cycle, and returns true each time it has gone
+// through a whole period
+fn clock_divider(divider: &mut u16, period: u16, shift: u8) -> bool {
+    if *divider == 0 {
+        *divider = period >> shift;
+        true
+    } else {
+        *divider -= 1;
+        false
+    }
+}
+
+#[derive(Default)]
+struct Vrc6Pulse {
+    // Volume, duty cycle and whether to ignore it
+    control: u8,
+    period: u16,
+    enabled: bool,
+    divider: u16,
+    duty_step: u8,
+}
+
+impl Vrc6Pulse {
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => self.control = data,
+            1 => self.period = (self.period & 0x0F00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | (((data & 0x0F) as u16) << 8);
+                self.enabled = data & CHANNEL_ENABLE != 0;
+                if !self.enabled {
+                    self.duty_step = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if self.enabled && clock_divider(&mut self.divider, self.period, shift) {
+            self.duty_step = (self.duty_step + 1) % 16;
+        }
+    }
+
+    fn output(&self) -> u8 {
+        let duty = (self.control >> 4) & 0b111;
+        // The output is high for the last duty + 1 of the 16 steps
+        let high = self.control & PULSE_IGNORE_DUTY != 0 || 15 - self.duty_step <= duty;
+        if self.enabled && high { self.control & 0x0F } else { 0 }
+    }
+}
+
+#[derive(Default)]
+struct Vrc6Sawtooth {
+    rate: u8,
+    period: u16,
+    enabled: bool,
+    divider: u16,
+    // The rate is added to the accumulator on every other clock, and the
+    // accumulator is reset on the 14th
+    step: u8,
+    accumulator: u8,
+}
+
+impl Vrc6Sawtooth {
+    fn write(&mut self, register: u16, data: u8) {
+        match register {
+            0 => self.rate = data & 0x3F,
+            1 => self.period = (self.period & 0x0F00) | data as u16,
+            _ => {
+                self.period = (self.period & 0x00FF) | (((data & 0x0F) as u16) << 8);
+                self.enabled = data & CHANNEL_ENABLE != 0;
+                if !self.enabled {
+                    self.step = 0;
+                    self.accumulator = 0;
+                }
+            }
+        }
+    }
+
+    fn clock(&mut self, shift: u8) {
+        if !self.enabled || !clock_divider(&mut self.divider, self.period, shift) {
+            return;
+        }
+        self.step += 1;
+        if self.step == 14 {
+            self.step = 0;
+            self.accumulator = 0;
+        } else if self.step.is_multiple_of(2) {
+            self.accumulator = self.accumulator.wrapping_add(self.rate);
+        }
+    }
+
+    // Only the top 5 bits of the accumulator make it to the output
+    fn output(&self) -> u8 {
+        self.accumulator >> 3
+    }
+}
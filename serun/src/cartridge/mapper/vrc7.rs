@@ -0,0 +1,160 @@
+use crate::cartridge::header::{CartridgeHeader, HeaderFormat, Mirroring};
+use crate::cartridge::mapper::vrc::{register_address, VrcIrq};
+use crate::cartridge::mapper::{chr_memory, mirrored, Mapper, PRG_RAM_START, PRG_ROM_START};
+
+const PRG_BANK_SIZE: usize = 0x2000;
+const CHR_BANK_SIZE: usize = 0x0400;
+
+const CONTROL_MIRRORING: u8 = 0b0000_0011;
+const CONTROL_PRG_RAM_ENABLE: u8 = 0b1000_0000;
+
+// Mapper 85, Konami's VRC7, as found in Lagrange Point (VRC7a) and Tiny Toon
+// Adventures 2 (VRC7b). The chip only has one register select line besides
+// A12-A15, which is A3 on the VRC7b and A4 on the VRC7a (NES 2.0 submappers 1
+// and 2). Registers, at their VRC7a addresses:
+//   $8000, $8010  8 KiB PRG banks for $8000 and $A000
+//   $9000         8 KiB PRG bank for $C000
+//   $9010, $9030  FM audio register select and data
+//   $A000-$D010   1 KiB CHR banks
+//   $E000         Mirroring, audio reset and PRG RAM enable
+//   $E010         IRQ latch
+//   $F000, $F010  IRQ control and acknowledge
+// The last 8 KiB of PRG ROM are fixed at $E000.
+//
+// The FM synthesizer isn't emulated, so VRC7 audio is silent. Writes to its
+// registers are ignored. Lagrange Point is the only game using it.
+pub struct Vrc7 {
+    prg_rom: Vec<u8>,
+    prg_ram: Vec<u8>,
+    chr: Vec<u8>,
+    chr_is_ram: bool,
+    line: u16,
+    prg_banks: [u8; 3],
+    chr_banks: [u8; 8],
+    control: u8,
+    irq: VrcIrq,
+}
+
+impl Vrc7 {
+    pub fn new(header: &CartridgeHeader, prg_rom: Vec<u8>, chr_rom: Vec<u8>) -> Self {
+        let submapper = if header.format == HeaderFormat::Nes20 { header.submapper } else { 0 };
+        let line = match submapper {
+            1 => 0x08,
+            2 => 0x10,
+            _ => 0x18,
+        };
+        let (chr, chr_is_ram) = chr_memory(header, chr_rom);
+        Vrc7 {
+            prg_rom,
+            prg_ram: vec![0; header.prg_ram_size + header.prg_nvram_size],
+            chr,
+            chr_is_ram,
+            line,
+            prg_banks: [0; 3],
+            chr_banks: [0; 8],
+            control: CONTROL_PRG_RAM_ENABLE,
+            irq: VrcIrq::default(),
+        }
+    }
+
+    fn write_register(&mut self, addr: u16, data: u8) {
+        // Only the chip's A0 is wired, so its A1 always reads 0
+        match register_address(addr, [self.line, 0]) {
+            0x8000 => self.prg_banks[0] = data & 0x3F,
+            0x8001 => self.prg_banks[1] = data & 0x3F,
+            0x9000 => self.prg_banks[2] = data & 0x3F,
+            addr @ 0xA000..=0xD001 => {
+                let bank = ((addr >> 12) as usize - 0xA) * 2 + (addr as usize & 1);
+                self.chr_banks[bank] = data;
+            }
+            0xE000 => self.control = data,
+            0xE001 => self.irq.write_latch(data),
+            0xF000 => self.irq.write_control(data),
+            0xF001 => self.irq.acknowledge(),
+            _ => {}
+        }
+    }
+
+    fn prg_rom_index(&self, addr: u16) -> Option<usize> {
+        let offset = addr as usize % PRG_BANK_SIZE;
+        let index = match addr {
+            0x8000..=0xDFFF => self.prg_banks[(addr as usize - 0x8000) / PRG_BANK_SIZE] as usize * PRG_BANK_SIZE + offset,
+            _ => (self.prg_rom.len() + offset).wrapping_sub(PRG_BANK_SIZE),
+        };
+        mirrored(self.prg_rom.len(), index)
+    }
+
+    fn prg_ram_index(&self, addr: u16) -> Option<usize> {
+        if self.control & CONTROL_PRG_RAM_ENABLE == 0 {
+            return None;
+        }
+        mirrored(self.prg_ram.len(), (addr - PRG_RAM_START) as usize)
+    }
+
+    fn chr_index(&self, addr: u16) -> Option<usize> {
+        let bank = self.chr_banks[addr as usize / CHR_BANK_SIZE] as usize;
+        mirrored(self.chr.len(), bank * CHR_BANK_SIZE + addr as usize % CHR_BANK_SIZE)
+    }
+}
+
+impl Mapper for Vrc7 {
+    fn cpu_peek(&self, addr: u16) -> Option<u8> {
+        match addr {
+            PRG_ROM_START..=u16::MAX => self.prg_rom_index(addr).map(|index| self.prg_rom[index]),
+            PRG_RAM_START..PRG_ROM_START => self.prg_ram_index(addr).map(|index| self.prg_ram[index]),
+            _ => None,
+        }
+    }
+
+    fn cpu_write(&mut self, addr: u16, data: u8) {
+        match addr {
+            PRG_ROM_START..=u16::MAX => self.write_register(addr, data),
+            PRG_RAM_START..PRG_ROM_START => {
+                if let Some(index) = self.prg_ram_index(addr) {
+                    self.prg_ram[index] = data;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn ppu_peek(&self, addr: u16) -> u8 {
+        self.chr_index(addr).map_or(0, |index| self.chr[index])
+    }
+
+    fn ppu_write(&mut self, addr: u16, data: u8) {
+        if self.chr_is_ram && let Some(index) = self.chr_index(addr) {
+            self.chr[index] = data;
+        }
+    }
+
+    fn mirroring(&self) -> Mirroring {
+        match self.control & CONTROL_MIRRORING {
+            0 => Mirroring::Vertical,
+            1 => Mirroring::Horizontal,
+            2 => Mirroring::SingleScreenLower,
+            _ => Mirroring::SingleScreenUpper,
+        }
+    }
+
+    fn cpu_clock(&mut self) {
+        self.irq.clock();
+    }
+
+    fn irq(&self) -> bool {
+        self.irq.pending()
+    }
+
+    // Silent, see `Vrc7`
+    fn expansion_audio(&self) -> f32 {
+        0.0
+    }
+
+    fn prg_ram(&self) -> &[u8] {
+        &self.prg_ram
+    }
+
+    fn prg_ram_mut(&mut self) -> &mut [u8] {
+        &mut self.prg_ram
+    }
+}
@@ -0,0 +1,287 @@
+use std::fmt;
+use crate::cartridge::hash::Crc32;
+
+// Soft patching, the way ROM hacks and translations are distributed. Patches
+// apply to the whole .nes file, header included.
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: [u8; 3] = *b"EOF";
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+// UPS and BPS patches end with the CRC-32s of the source, the target and the
+// patch itself (minus this last CRC)
+const CHECKSUMS_SIZE: usize = 12;
+// Far larger than any NES game. Keeps a bogus size in a patch from making us
+// allocate all the memory there is.
+const MAX_TARGET_SIZE: usize = 0x400_0000;
+
+#[derive(Debug, PartialEq)]
+pub enum PatchError {
+    // Not an IPS, UPS or BPS patch
+    UnknownFormat,
+    // The patch ends in the middle of a record
+    Truncated,
+    // The patch writes past the end of the file it's making
+    OutOfBounds,
+    // The patch is corrupt
+    PatchChecksum { expected: u32, actual: u32 },
+    // The patch was made for a different ROM (or a different dump of it)
+    SourceSize { expected: usize, actual: usize },
+    SourceChecksum { expected: u32, actual: u32 },
+    // Applying the patch didn't give the file it was supposed to
+    TargetChecksum { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for PatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFormat => write!(f, "Patch is not an IPS, UPS or BPS file"),
+            Self::Truncated => write!(f, "Patch file is truncated"),
+            Self::OutOfBounds => write!(f, "Patch writes outside of the patched file"),
+            Self::PatchChecksum { expected, actual } => write!(f, "Patch is corrupt, expected CRC32 {expected:08X} but found {actual:08X}"),
+            Self::SourceSize { expected, actual } => write!(f, "Patch is for a different ROM, expected {expected} bytes but found {actual}"),
+            Self::SourceChecksum { expected, actual } => write!(f, "Patch is for a different ROM, expected CRC32 {expected:08X} but found {actual:08X}"),
+            Self::TargetChecksum { expected, actual } => write!(f, "Patched ROM is wrong, expected CRC32 {expected:08X} but found {actual:08X}"),
+        }
+    }
+}
+
+// Returns `source` with `patch` applied, in whichever format it's in
+pub fn apply(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(patch, source)
+    } else if patch.starts_with(UPS_MAGIC) {
+        apply_ups(patch, source)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(patch, source)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc32 = Crc32::default();
+    crc32.update(data);
+    crc32.finish()
+}
+
+// Reads through a patch, reporting running out of bytes as `Truncated`
+struct PatchReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PatchReader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        PatchReader { data, pos }
+    }
+
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], PatchError> {
+        let bytes = self.peek(len).ok_or(PatchError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn peek(&self, len: usize) -> Option<&'a [u8]> {
+        self.data.get(self.pos..self.pos.saturating_add(len))
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16_be(&mut self) -> Result<usize, PatchError> {
+        let bytes = self.bytes(2)?;
+        Ok(((bytes[0] as usize) << 8) | bytes[1] as usize)
+    }
+
+    fn u24_be(&mut self) -> Result<usize, PatchError> {
+        let bytes = self.bytes(3)?;
+        Ok(((bytes[0] as usize) << 16) | ((bytes[1] as usize) << 8) | bytes[2] as usize)
+    }
+
+    fn u32_le(&mut self) -> Result<u32, PatchError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // The variable length numbers of UPS and BPS. Every byte holds 7 bits,
+    // least significant first, and the last one has its top bit set. Each
+    // continuation also adds one, so every number has a single encoding.
+    fn number(&mut self) -> Result<usize, PatchError> {
+        let mut number: usize = 0;
+        let mut shift: usize = 1;
+        loop {
+            let byte = self.byte()?;
+            number = ((byte & 0x7F) as usize).checked_mul(shift).and_then(|bits| number.checked_add(bits)).ok_or(PatchError::OutOfBounds)?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::OutOfBounds)?;
+            number = number.checked_add(shift).ok_or(PatchError::OutOfBounds)?;
+        }
+    }
+
+    fn at_end(&self, end: usize) -> bool {
+        self.pos >= end
+    }
+}
+
+// IPS: records of an offset and the bytes to write there, or a byte to repeat,
+// until "EOF". Some patches follow that with the size to truncate the file to.
+fn apply_ips(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut target = source.to_vec();
+    let mut reader = PatchReader::new(patch, IPS_MAGIC.len());
+    while reader.peek(IPS_EOF.len()) != Some(&IPS_EOF) {
+        let offset = reader.u24_be()?;
+        let (len, run) = match reader.u16_be()? {
+            0 => (reader.u16_be()?, Some(reader.byte()?)),
+            len => (len, None),
+        };
+        if target.len() < offset + len {
+            target.resize(offset + len, 0);
+        }
+        match run {
+            Some(byte) => target[offset..offset + len].fill(byte),
+            None => target[offset..offset + len].copy_from_slice(reader.bytes(len)?),
+        }
+    }
+    reader.bytes(IPS_EOF.len())?;
+    if let Ok(len) = reader.u24_be() {
+        target.truncate(len);
+    }
+    Ok(target)
+}
+
+// Checks the CRC-32s at the end of a UPS or BPS patch that can be checked
+// before applying it, and returns the one the result should have
+fn check_checksums(patch: &[u8], source: &[u8]) -> Result<u32, PatchError> {
+    if patch.len() < CHECKSUMS_SIZE {
+        return Err(PatchError::Truncated);
+    }
+    let mut reader = PatchReader::new(patch, patch.len() - CHECKSUMS_SIZE);
+    let source_crc32 = reader.u32_le()?;
+    let target_crc32 = reader.u32_le()?;
+    let patch_crc32 = reader.u32_le()?;
+
+    let actual = crc32(&patch[..patch.len() - 4]);
+    if actual != patch_crc32 {
+        return Err(PatchError::PatchChecksum { expected: patch_crc32, actual });
+    }
+    let actual = crc32(source);
+    if actual != source_crc32 {
+        return Err(PatchError::SourceChecksum { expected: source_crc32, actual });
+    }
+    Ok(target_crc32)
+}
+
+fn check_target(target: &[u8], expected: u32) -> Result<(), PatchError> {
+    let actual = crc32(target);
+    if actual != expected {
+        return Err(PatchError::TargetChecksum { expected, actual });
+    }
+    Ok(())
+}
+
+// UPS: the source and target sizes, then hunks of bytes to XOR into the file,
+// each at some distance past the end of the last one
+fn apply_ups(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let target_crc32 = check_checksums(patch, source)?;
+    let end = patch.len() - CHECKSUMS_SIZE;
+    let mut reader = PatchReader::new(patch, UPS_MAGIC.len());
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    if source_size != source.len() {
+        return Err(PatchError::SourceSize { expected: source_size, actual: source.len() });
+    }
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::OutOfBounds);
+    }
+
+    let mut target = source.to_vec();
+    target.resize(target_size, 0);
+    let mut pos: usize = 0;
+    while !reader.at_end(end) {
+        pos = pos.checked_add(reader.number()?).ok_or(PatchError::OutOfBounds)?;
+        // Each hunk ends with a 0, which counts as a byte left unchanged
+        loop {
+            let byte = reader.byte()?;
+            if byte == 0 {
+                pos = pos.checked_add(1).ok_or(PatchError::OutOfBounds)?;
+                break;
+            }
+            *target.get_mut(pos).ok_or(PatchError::OutOfBounds)? ^= byte;
+            pos = pos.checked_add(1).ok_or(PatchError::OutOfBounds)?;
+        }
+    }
+    check_target(&target, target_crc32)?;
+    Ok(target)
+}
+
+// BPS: the source and target sizes, some metadata, then commands that build
+// the target from start to end by copying from the source, from the patch, or
+// from what's been built so far
+fn apply_bps(patch: &[u8], source: &[u8]) -> Result<Vec<u8>, PatchError> {
+    const SOURCE_READ: usize = 0;
+    const TARGET_READ: usize = 1;
+    const SOURCE_COPY: usize = 2;
+
+    let target_crc32 = check_checksums(patch, source)?;
+    let end = patch.len() - CHECKSUMS_SIZE;
+    let mut reader = PatchReader::new(patch, BPS_MAGIC.len());
+    let source_size = reader.number()?;
+    let target_size = reader.number()?;
+    let metadata_size = reader.number()?;
+    reader.bytes(metadata_size)?;
+    if source_size != source.len() {
+        return Err(PatchError::SourceSize { expected: source_size, actual: source.len() });
+    }
+    if target_size > MAX_TARGET_SIZE {
+        return Err(PatchError::OutOfBounds);
+    }
+
+    let mut target = Vec::with_capacity(target_size);
+    // Copies move these along, by signed offsets relative to where the last
+    // copy left off
+    let mut source_pos: usize = 0;
+    let mut target_pos: usize = 0;
+    let relative = |pos: usize, reader: &mut PatchReader| -> Result<usize, PatchError> {
+        let offset = reader.number()?;
+        let distance = offset >> 1;
+        if offset & 1 == 0 { pos.checked_add(distance) } else { pos.checked_sub(distance) }.ok_or(PatchError::OutOfBounds)
+    };
+    while !reader.at_end(end) {
+        let command = reader.number()?;
+        let len = (command >> 2) + 1;
+        if target.len() + len > target_size {
+            return Err(PatchError::OutOfBounds);
+        }
+        match command & 0b11 {
+            SOURCE_READ => {
+                let out = target.len();
+                target.extend_from_slice(source.get(out..out + len).ok_or(PatchError::OutOfBounds)?);
+            }
+            TARGET_READ => target.extend_from_slice(reader.bytes(len)?),
+            SOURCE_COPY => {
+                source_pos = relative(source_pos, &mut reader)?;
+                target.extend_from_slice(source.get(source_pos..source_pos + len).ok_or(PatchError::OutOfBounds)?);
+                source_pos += len;
+            }
+            _ => {
+                target_pos = relative(target_pos, &mut reader)?;
+                // Copied a byte at a time, since the copy may overlap with
+                // what it's adding to repeat a pattern
+                for _ in 0..len {
+                    let byte = *target.get(target_pos).ok_or(PatchError::OutOfBounds)?;
+                    target.push(byte);
+                    target_pos += 1;
+                }
+            }
+        }
+    }
+    if target.len() != target_size {
+        return Err(PatchError::Truncated);
+    }
+    check_target(&target, target_crc32)?;
+    Ok(target)
+}
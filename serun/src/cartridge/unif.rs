@@ -0,0 +1,131 @@
+use crate::cartridge::CartidgeError;
+use crate::cartridge::header::{CartridgeHeader, ConsoleType, HeaderFormat, Mirroring, TvSystem, CHR_RAM_DEFAULT_SIZE};
+
+// UNIF files start with a 32 byte header, "UNIF", a revision number and
+// padding, followed by chunks of a 4 byte ID, a 32-bit little endian length
+// and that much data. The chunks that matter here:
+//   MAPR       Name of the board, as printed on it
+//   PRG0-PRGF  PRG ROM, in as many pieces as the board has chips
+//   CHR0-CHRF  CHR ROM, likewise. The board has CHR RAM without any.
+//   MIRR       Mirroring hard-wired on the board
+//   BATR       Present on boards with battery backed RAM
+//   TVCI       TV system
+// Anything else, like the game's name or dumper info, is skipped.
+pub const UNIF_MAGIC: &[u8] = b"UNIF";
+const UNIF_HEADER_SIZE: usize = 32;
+const CHUNK_HEADER_SIZE: usize = 8;
+const PRG_RAM_SIZE: usize = 0x2000;
+
+// Mapper and PRG RAM size of the boards UNIF names that there's a mapper for.
+// Manufacturer prefixes like "NES-" are stripped off first.
+fn board(name: &str) -> Option<(u16, usize)> {
+    let board = match name {
+        "NROM" | "NROM-128" | "NROM-256" | "RROM" | "RROM-128" => (0, 0),
+        "SBROM" | "SCROM" | "SEROM" | "SGROM" | "SLROM" | "SL1ROM" | "SL2ROM" | "SL3ROM" | "SLRROM" => (1, 0),
+        "SAROM" | "SKROM" | "SNROM" | "SUROM" => (1, PRG_RAM_SIZE),
+        "SOROM" => (1, 2 * PRG_RAM_SIZE),
+        "SXROM" => (1, 4 * PRG_RAM_SIZE),
+        "UNROM" | "UOROM" => (2, 0),
+        "CNROM" => (3, 0),
+        "TBROM" | "TEROM" | "TFROM" | "TGROM" | "TLROM" | "TL1ROM" | "TR1ROM" | "TVROM" => (4, 0),
+        "TKROM" | "TSROM" => (4, PRG_RAM_SIZE),
+        "AMROM" | "ANROM" | "AN1ROM" | "AOROM" => (7, 0),
+        "PNROM" | "PEEOROM" => (9, 0),
+        "FJROM" | "FKROM" => (10, PRG_RAM_SIZE),
+        "GNROM" | "MHROM" => (66, 0),
+        _ => return None,
+    };
+    Some(board)
+}
+
+// Reads the header and ROMs out of a UNIF file, in the same form as a .nes
+// file would have them. `data` has to start with "UNIF".
+pub fn parse(data: &[u8]) -> Result<(CartridgeHeader, Vec<u8>, Vec<u8>), CartidgeError> {
+    if data.len() < UNIF_HEADER_SIZE {
+        return Err(CartidgeError::TooShort);
+    }
+
+    let mut board_name = None;
+    let mut prg_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut chr_chunks: [Option<&[u8]>; 16] = [None; 16];
+    let mut mirroring = Mirroring::Horizontal;
+    let mut battery = false;
+    let mut tv_system = TvSystem::Ntsc;
+
+    let mut pos = UNIF_HEADER_SIZE;
+    while pos < data.len() {
+        let id = data.get(pos..pos + 4).ok_or(CartidgeError::TruncatedUnifChunk)?;
+        let len = data.get(pos + 4..pos + CHUNK_HEADER_SIZE).ok_or(CartidgeError::TruncatedUnifChunk)?;
+        let len = u32::from_le_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        let start = pos + CHUNK_HEADER_SIZE;
+        let chunk = data.get(start..start.saturating_add(len)).ok_or(CartidgeError::TruncatedUnifChunk)?;
+        pos = start + len;
+
+        match id {
+            b"MAPR" => {
+                // Zero terminated, though not always
+                let name = chunk.split(|&byte| byte == 0).next().unwrap_or_default();
+                board_name = Some(String::from_utf8_lossy(name).trim().to_string());
+            }
+            [b'P', b'R', b'G', number] | [b'C', b'H', b'R', number] => {
+                let Some(index) = (*number as char).to_digit(16) else {
+                    continue;
+                };
+                let chunks = if id.starts_with(b"PRG") { &mut prg_chunks } else { &mut chr_chunks };
+                chunks[index as usize] = Some(chunk);
+            }
+            b"MIRR" => {
+                mirroring = match chunk.first() {
+                    Some(1) => Mirroring::Vertical,
+                    Some(2) => Mirroring::SingleScreenLower,
+                    Some(3) => Mirroring::SingleScreenUpper,
+                    Some(4) => Mirroring::FourScreen,
+                    // 0 is horizontal, 5 leaves it up to the mapper
+                    _ => Mirroring::Horizontal,
+                };
+            }
+            b"BATR" => battery = true,
+            b"TVCI" => {
+                tv_system = match chunk.first() {
+                    Some(1) => TvSystem::Pal,
+                    Some(2) => TvSystem::MultiRegion,
+                    _ => TvSystem::Ntsc,
+                };
+            }
+            _ => {}
+        }
+    }
+
+    let board_name = board_name.ok_or(CartidgeError::MissingBoardName)?;
+    let stripped = ["NES-", "HVC-", "UNL-", "BTL-"].iter().find_map(|prefix| board_name.strip_prefix(prefix)).unwrap_or(&board_name);
+    let (mapper, prg_ram_size) = board(stripped).ok_or_else(|| CartidgeError::UnsupportedBoard(board_name.clone()))?;
+    // A battery is no use without RAM for it to keep
+    let prg_ram_size = if battery { prg_ram_size.max(PRG_RAM_SIZE) } else { prg_ram_size };
+    let prg_rom: Vec<u8> = prg_chunks.iter().flatten().flat_map(|chunk| chunk.iter()).copied().collect();
+    let chr_rom: Vec<u8> = chr_chunks.iter().flatten().flat_map(|chunk| chunk.iter()).copied().collect();
+    if prg_rom.is_empty() {
+        return Err(CartidgeError::ZeroPrgBanks);
+    }
+
+    let header = CartridgeHeader {
+        format: HeaderFormat::Unif,
+        mapper,
+        submapper: 0,
+        mirroring,
+        battery,
+        trainer: false,
+        prg_rom_size: prg_rom.len(),
+        chr_rom_size: chr_rom.len(),
+        // Like iNES, there's no telling which of the RAM the battery keeps
+        prg_ram_size: if battery { 0 } else { prg_ram_size },
+        prg_nvram_size: if battery { prg_ram_size } else { 0 },
+        chr_ram_size: if chr_rom.is_empty() { CHR_RAM_DEFAULT_SIZE } else { 0 },
+        chr_nvram_size: 0,
+        tv_system,
+        console_type: ConsoleType::Nes,
+        vs_system: None,
+        misc_rom_count: 0,
+        expansion_device: 0,
+    };
+    Ok((header, prg_rom, chr_rom))
+}
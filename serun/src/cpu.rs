@@ -1,10 +1,26 @@
+use std::fmt;
+use crate::bus::Bus;
 use crate::memory::Memory;
 use crate::opcodes::{CPU_OPCODES, AddressingMode, Instruction, Opcode};
+use cycle::CycleState;
+use trace::{TraceRecord, Tracer};
 
 pub mod instructions;
+pub mod trace;
+mod cycle;
 
 const SP_BASE_ADDR: u16 = 0x0100;
 const SP_INITIAL_ADDR: u8 = 0xFD;
+const NMI_VECTOR: u16 = 0xFFFA;
+const RESET_VECTOR: u16 = 0xFFFC;
+const IRQ_VECTOR: u16 = 0xFFFE;
+// Status register value after reset: interrupts disabled and the unused bit set
+const STATUS_INITIAL: u8 = 0b0010_0100;
+// Bits 4 and 5 of the status register don't physically exist. They only show
+// up in the copy of the status register pushed to the stack.
+const STATUS_BREAK: u8 = 0b0001_0000;
+const STATUS_UNUSED: u8 = 0b0010_0000;
+const INTERRUPT_CYCLES: u8 = 7;
 
 pub enum StatusFlag {
     C,
@@ -16,86 +32,329 @@ pub enum StatusFlag {
     N
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CpuError {
+    // The byte at `address` doesn't decode to any known instruction
+    UnknownOpcode { opcode: u8, address: u16 },
+    // A JAM opcode at `address` has locked up the CPU until it is reset
+    Jammed { address: u16 },
+    // The instruction at `address` was used with an addressing mode it can't handle
+    UnsupportedAddressingMode { mode: AddressingMode, address: u16 },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownOpcode { opcode, address } => write!(f, "Unknown opcode {opcode:02X} at {address:04X}"),
+            Self::Jammed { address } => write!(f, "CPU jammed by opcode at {address:04X}"),
+            Self::UnsupportedAddressingMode { mode, address } => write!(f, "Addressing mode {mode:?} is not supported by instruction at {address:04X}"),
+        }
+    }
+}
+
+// Number of cycles taken by a step of the CPU, or why it couldn't be taken
+pub type StepResult = Result<u8, CpuError>;
+
+// How an instruction uses the memory at its operand address. This decides
+// which dummy accesses the CPU makes while getting there.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MemoryAccess {
+    Read,
+    Write,
+    ReadModifyWrite,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Interrupt {
+    Nmi,
+    Irq,
+}
+
+impl Interrupt {
+    fn vector(self) -> u16 {
+        match self {
+            Interrupt::Nmi => NMI_VECTOR,
+            Interrupt::Irq => IRQ_VECTOR,
+        }
+    }
+}
+
 #[derive(Default)]
-pub struct CPU {
+pub struct CPU<B: Bus = Memory> {
     pub register_a: u8,
     pub register_x: u8,
     pub register_y: u8,
     pub stack_pointer: u8,
     pub pc: u16,
     pub status: u8,
-    // TODO: This won't work for testing when a Bus is implemented.
-    // Will need to fix so that this can be used with a Bus or Ram
-    pub memory: Memory,
+    // Total number of cycles the CPU has run for
+    pub cycles: u64,
+    pub bus: B,
+    // Set when the last effective address calculation crossed a page boundary
+    page_crossed: bool,
+    // Cycles spent by the current instruction on top of its base cycle count
+    extra_cycles: u8,
+    // Latched NMI edge, waiting to be serviced
+    nmi_pending: bool,
+    // Level of the IRQ input. Stays asserted until whoever raised it lets go.
+    irq_line: bool,
+    // Interrupt picked up while polling at the end of the last instruction,
+    // serviced before the next instruction is fetched
+    pending_interrupt: Option<Interrupt>,
+    // Set once a JAM opcode has locked up the CPU
+    jammed: bool,
+    // Gets a record of every instruction before it executes. Tracing is off
+    // (and costs nothing) while this is `None`.
+    tracer: Option<Box<dyn Tracer>>,
+    // Instruction `tick` is partway through, if any
+    cycle_state: Option<CycleState>,
+    // Set by `tick` before the last cycle of an instruction, which has already
+    // made the addressing accesses (and for read-modify-write instructions,
+    // the read and dummy write). The instruction only makes its final access.
+    latched_address: Option<u16>,
+    latched_data: Option<u8>,
 }
 
-impl CPU {
+impl CPU<Memory> {
+    pub fn load_program(&mut self, program: Vec<u8>) {
+        self.bus.load(program);
+        self.reset();
+    }
+}
+
+impl<B: Bus> CPU<B> {
+    pub fn new(bus: B) -> Self {
+        CPU {
+            register_a: 0,
+            register_x: 0,
+            register_y: 0,
+            stack_pointer: 0,
+            pc: 0,
+            status: 0,
+            cycles: 0,
+            bus,
+            page_crossed: false,
+            extra_cycles: 0,
+            nmi_pending: false,
+            irq_line: false,
+            pending_interrupt: None,
+            jammed: false,
+            tracer: None,
+            cycle_state: None,
+            latched_address: None,
+            latched_data: None,
+        }
+    }
+
     pub fn reset(&mut self) {
         self.register_a = 0;
         self.register_x = 0;
-        self.status = 0;
+        self.status = STATUS_INITIAL;
         self.stack_pointer = SP_INITIAL_ADDR;
-        self.pc = self.memory.read_u16(0xFFFC);
+        self.pc = self.bus.read_u16(RESET_VECTOR);
+        self.nmi_pending = false;
+        self.pending_interrupt = None;
+        self.jammed = false;
+        self.cycle_state = None;
+        self.cycles += INTERRUPT_CYCLES as u64;
     }
 
-    pub fn load_program(&mut self, program: Vec<u8>) {
-        self.memory.load(program);
-        self.reset();
+    pub fn is_jammed(&self) -> bool {
+        self.jammed
+    }
+
+    pub fn set_tracer(&mut self, tracer: Box<dyn Tracer>) {
+        self.tracer = Some(tracer);
+    }
+
+    pub fn clear_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
+
+    fn trace(&mut self, instruction: &'static Instruction) {
+        if let Some(tracer) = self.tracer.as_mut() {
+            let mut opcode_bytes = [0; 3];
+            for (offset, byte) in opcode_bytes.iter_mut().enumerate().take(instruction.bytes as usize) {
+                *byte = self.bus.peek(self.pc.wrapping_add(offset as u16));
+            }
+            let record = TraceRecord {
+                pc: self.pc,
+                opcode_bytes,
+                instruction,
+                register_a: self.register_a,
+                register_x: self.register_x,
+                register_y: self.register_y,
+                stack_pointer: self.stack_pointer,
+                status: self.status,
+                cycles: self.cycles,
+            };
+            tracer.trace(&record, &self.bus);
+        }
+    }
+
+    // Signals a falling edge on the NMI line. The NMI is serviced once the
+    // instruction currently executing has finished.
+    pub fn trigger_nmi(&mut self) {
+        self.nmi_pending = true;
+    }
+
+    // Sets the level of the IRQ line. IRQs are level triggered, so the CPU will
+    // keep taking the interrupt for as long as the line is asserted and the
+    // interrupt disable flag is clear. Devices on the bus can also assert it,
+    // see `Bus::irq`.
+    pub fn set_irq_line(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
+
+    // Interrupts are polled at the end of each instruction. `irq_disabled` is
+    // the interrupt disable flag as it was when the poll happened, which is
+    // before the last cycle of the instruction.
+    fn poll_interrupts(&mut self, irq_disabled: bool) {
+        let interrupt = self.poll_interrupt_lines(irq_disabled);
+        self.latch_interrupt(interrupt);
+    }
+
+    // Interrupt the CPU would take if it polled its interrupt lines right now
+    fn poll_interrupt_lines(&self, irq_disabled: bool) -> Option<Interrupt> {
+        if self.nmi_pending {
+            Some(Interrupt::Nmi)
+        } else if (self.irq_line || self.bus.irq()) && !irq_disabled {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        }
     }
 
-    fn get_operand_address(&mut self, mode: &AddressingMode) -> u16 {
-        match mode {
-            AddressingMode::Immediate => self.pc,
+    // Queues up an interrupt picked up by polling, to be serviced before the
+    // next instruction
+    fn latch_interrupt(&mut self, interrupt: Option<Interrupt>) {
+        if interrupt == Some(Interrupt::Nmi) {
+            self.nmi_pending = false;
+        }
+        if interrupt.is_some() {
+            self.pending_interrupt = interrupt;
+        }
+    }
+
+    fn interrupt(&mut self, interrupt: Interrupt) -> StepResult {
+        // The CPU fetches the next opcode and its following byte like it would
+        // for BRK, but throws them away and doesn't move pc
+        self.bus.read(self.pc);
+        self.bus.read(self.pc);
+        self.push_stack_u16(self.pc);
+        self.push_stack((self.status & !STATUS_BREAK) | STATUS_UNUSED);
+        self.set_status_flag(StatusFlag::I);
+        self.pc = self.bus.read_u16(interrupt.vector());
+        self.cycles += INTERRUPT_CYCLES as u64;
+        Ok(INTERRUPT_CYCLES)
+    }
+
+    fn unsupported_addressing_mode(&self, mode: &AddressingMode) -> CpuError {
+        // pc points just past the opcode while an instruction is executing
+        CpuError::UnsupportedAddressingMode { mode: *mode, address: self.pc.wrapping_sub(1) }
+    }
+
+    fn fetch_byte(&mut self) -> u8 {
+        let data = self.bus.read(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        data
+    }
+
+    fn fetch_u16(&mut self) -> u16 {
+        let lo = self.fetch_byte() as u16;
+        let hi = self.fetch_byte() as u16;
+        hi << 8 | lo
+    }
+
+    // Adds an index to a base address. The CPU adds to the low byte first and
+    // reads from the result before it has fixed up the high byte. Reads that
+    // didn't cross a page can use that value right away, anything else has to
+    // spend a cycle on a dummy read and try again.
+    fn add_index(&mut self, base: u16, index: u8, access: MemoryAccess) -> u16 {
+        let addr = base.wrapping_add(index as u16);
+        self.page_crossed = Self::is_page_crossed(base, addr);
+        if self.page_crossed || access != MemoryAccess::Read {
+            self.bus.read((base & 0xFF00) | (addr & 0x00FF));
+        }
+        addr
+    }
 
-            AddressingMode::ZeroPage => self.memory.read(self.pc) as u16,
+    // Fetches the operand bytes of the current instruction and works out the
+    // address it operates on. Every bus access the real CPU makes along the
+    // way, including dummy reads, happens here in the same order.
+    fn get_operand_address(&mut self, mode: &AddressingMode, access: MemoryAccess) -> Result<u16, CpuError> {
+        if let Some(address) = self.latched_address.take() {
+            return Ok(address);
+        }
 
-            AddressingMode::Absolute => self.memory.read_u16(self.pc),
+        let address = match mode {
+            AddressingMode::Immediate => {
+                let addr = self.pc;
+                self.pc = self.pc.wrapping_add(1);
+                addr
+            }
+
+            AddressingMode::ZeroPage => self.fetch_byte() as u16,
+
+            AddressingMode::Absolute => self.fetch_u16(),
 
             AddressingMode::ZeroPage_X => {
-                let pos = self.memory.read(self.pc);
+                let pos = self.fetch_byte();
+                self.bus.read(pos as u16);
                 pos.wrapping_add(self.register_x) as u16
             }
 
             AddressingMode::ZeroPage_Y => {
-                let pos = self.memory.read(self.pc);
+                let pos = self.fetch_byte();
+                self.bus.read(pos as u16);
                 pos.wrapping_add(self.register_y) as u16
             }
 
             AddressingMode::Absolute_X => {
-                let base = self.memory.read_u16(self.pc);
-                base.wrapping_add(self.register_x as u16)
+                let base = self.fetch_u16();
+                self.add_index(base, self.register_x, access)
             }
 
             AddressingMode::Absolute_Y => {
-                let base = self.memory.read_u16(self.pc);
-                base.wrapping_add(self.register_y as u16)
+                let base = self.fetch_u16();
+                self.add_index(base, self.register_y, access)
             }
 
+            // The 6502 never carries into the high byte of the pointer, so
+            // JMP ($xxFF) reads its high byte from $xx00
             AddressingMode::Indirect => {
-                let operand_address = self.memory.read_u16(self.pc);
-                self.memory.read_u16(operand_address)
+                let operand_address = self.fetch_u16();
+                let lo = self.bus.read(operand_address);
+                let hi = self.bus.read((operand_address & 0xFF00) | (operand_address.wrapping_add(1) & 0x00FF));
+                (hi as u16) << 8 | (lo as u16)
             }
 
             AddressingMode::Indirect_X => {
-                let base = self.memory.read(self.pc);
+                let base = self.fetch_byte();
+                self.bus.read(base as u16);
                 let ptr = base.wrapping_add(self.register_x);
-                let lo = self.memory.read(ptr as u16);
-                let hi = self.memory.read(ptr.wrapping_add(1) as u16);
+                let lo = self.bus.read(ptr as u16);
+                let hi = self.bus.read(ptr.wrapping_add(1) as u16);
                 (hi as u16) << 8 | (lo as u16)
             }
 
             AddressingMode::Indirect_Y => {
-                let base = self.memory.read(self.pc);
-                let lo = self.memory.read(base as u16);
-                let hi = self.memory.read(base.wrapping_add(1) as u16);
+                let base = self.fetch_byte();
+                let lo = self.bus.read(base as u16);
+                let hi = self.bus.read(base.wrapping_add(1) as u16);
                 let deref_base = (hi as u16) << 8 | (lo as u16);
-                deref_base.wrapping_add(self.register_y as u16)
+                self.add_index(deref_base, self.register_y, access)
             }
 
             _ => {
-                panic!("addressing mode {mode:?} is not supported");
+                return Err(self.unsupported_addressing_mode(mode));
             }
-        }
+        };
+        Ok(address)
+    }
+
+    fn is_page_crossed(a: u16, b: u16) -> bool {
+        a & 0xFF00 != b & 0xFF00
     }
 
     fn update_zero_and_negative_flags(&mut self, result: u8) {
@@ -112,13 +371,13 @@ impl CPU {
         }
     }
 
-    fn get_operand(&mut self, instruction: &Instruction) -> u8 {
-        let operand_address = self.get_operand_address(&instruction.addressing_mode);
-        self.memory.read(operand_address)
+    fn get_operand(&mut self, instruction: &Instruction) -> Result<u8, CpuError> {
+        let operand_address = self.get_operand_address(&instruction.addressing_mode, MemoryAccess::Read)?;
+        Ok(self.bus.read(operand_address))
     }
 
     pub fn push_stack(&mut self, value: u8) {
-        self.memory.write(SP_BASE_ADDR + u16::from(self.stack_pointer), value);
+        self.bus.write(SP_BASE_ADDR + u16::from(self.stack_pointer), value);
         self.stack_pointer = self.stack_pointer.wrapping_sub(1);
     }
 
@@ -129,9 +388,15 @@ impl CPU {
         self.push_stack(lo);
     }
 
+    // Instructions that pull from the stack spend a cycle reading from the
+    // current stack pointer before incrementing it
+    fn dummy_stack_read(&mut self) {
+        self.bus.read(SP_BASE_ADDR + u16::from(self.stack_pointer));
+    }
+
     pub fn pop_stack(&mut self) -> u8 {
         self.stack_pointer = self.stack_pointer.wrapping_add(1);
-        self.memory.read(SP_BASE_ADDR + u16::from(self.stack_pointer))
+        self.bus.read(SP_BASE_ADDR + u16::from(self.stack_pointer))
     }
 
     pub fn pop_stack_u16(&mut self) -> u16 {
@@ -176,78 +441,153 @@ impl CPU {
         };
     }
 
-    pub fn execute_instruction(&mut self) {
-        let instruction_hex = self.memory.read(self.pc);
-        println!("Instruction hex: {}", instruction_hex);
-        let instruction = CPU_OPCODES.get(&instruction_hex).unwrap_or_else(|| panic!("Failed to retrieve opcode!"));
-        self.pc += 1;
-        println!("Found instruction {:?}, pc: {}", instruction, self.pc);
+    // Executes a single instruction and returns the number of cycles it took.
+    // If an interrupt was raised during the previous instruction, the interrupt
+    // sequence is run instead. An instruction `tick` has already started gets
+    // finished off cycle by cycle.
+    pub fn execute_instruction(&mut self) -> StepResult {
+        if self.jammed {
+            return Err(CpuError::Jammed { address: self.pc });
+        }
+
+        if self.cycle_state.is_some() {
+            let mut cycles = 1;
+            while !self.tick()? {
+                cycles += 1;
+            }
+            return Ok(cycles);
+        }
+
+        if let Some(interrupt) = self.pending_interrupt.take() {
+            return self.interrupt(interrupt);
+        }
+
+        let irq_disabled_before = self.get_status_flag(StatusFlag::I) == 1;
+        self.page_crossed = false;
+        self.extra_cycles = 0;
+        let instruction_hex = self.bus.read(self.pc);
+        let instruction = CPU_OPCODES.get(&instruction_hex)
+            .ok_or(CpuError::UnknownOpcode { opcode: instruction_hex, address: self.pc })?;
+        self.trace(instruction);
+        self.pc = self.pc.wrapping_add(1);
 
+        // Instructions without an operand still read the byte after the opcode
+        // while they're being decoded
+        if instruction.bytes == 1 && !matches!(instruction.opcode, Opcode::JAM) {
+            self.bus.read(self.pc);
+        }
+
+        self.execute_operation(instruction)?;
+
+        if self.page_crossed && instruction.opcode.has_page_cross_penalty() {
+            self.extra_cycles += 1;
+        }
+        let cycles = instruction.cycles + self.extra_cycles;
+        self.cycles += cycles as u64;
+
+        // CLI, SEI and PLP change the interrupt disable flag on their last
+        // cycle, after interrupts have already been polled. The change only
+        // takes effect once the next instruction has run.
+        let irq_disabled = match instruction.opcode {
+            Opcode::CLI | Opcode::SEI | Opcode::PLP => irq_disabled_before,
+            _ => self.get_status_flag(StatusFlag::I) == 1,
+        };
+        self.poll_interrupts(irq_disabled);
+        Ok(cycles)
+    }
+
+
+    // Carries out what an instruction does once it has been fetched and pc
+    // points past the opcode
+    fn execute_operation(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
         match instruction.opcode {
-            Opcode::ADC => self.adc(instruction),
-            Opcode::AND => self.and(instruction),
-            Opcode::ASL => self.asl(instruction),
+            Opcode::ADC => self.adc(instruction)?,
+            Opcode::AND => self.and(instruction)?,
+            Opcode::ASL => self.asl(instruction)?,
             Opcode::BCC => self.bcc(),
             Opcode::BCS => self.bcs(),
             Opcode::BEQ => self.beq(),
-            Opcode::BIT => self.bit(instruction),
+            Opcode::BIT => self.bit(instruction)?,
             Opcode::BMI => self.bmi(),
             Opcode::BNE => self.bne(),
             Opcode::BPL => self.bpl(),
-            Opcode::BRK => return,
+            Opcode::BRK => self.brk(),
             Opcode::BVC => self.bvc(),
             Opcode::BVS => self.bvs(),
             Opcode::CLC => self.clc(),
-            Opcode::CLD => {},
+            Opcode::CLD => self.cld(),
             Opcode::CLI => self.cli(),
             Opcode::CLV => self.clv(),
-            Opcode::CMP => self.cmp(instruction),
-            Opcode::CPX => self.cpx(instruction),
-            Opcode::CPY => self.cpy(instruction),
-            Opcode::DEC => self.dec(instruction),
+            Opcode::CMP => self.cmp(instruction)?,
+            Opcode::CPX => self.cpx(instruction)?,
+            Opcode::CPY => self.cpy(instruction)?,
+            Opcode::DEC => self.dec(instruction)?,
             Opcode::DEX => self.dex(),
             Opcode::DEY => self.dey(),
-            Opcode::EOR => self.eor(instruction),
-            Opcode::INC => self.inc(instruction),
+            Opcode::EOR => self.eor(instruction)?,
+            Opcode::INC => self.inc(instruction)?,
             Opcode::INX => self.inx(),
             Opcode::INY => self.iny(),
-            Opcode::JMP => self.jmp(instruction),
-            Opcode::JSR => self.jsr(instruction),
-            Opcode::LDA => self.lda(instruction),
-            Opcode::LDX => self.ldx(instruction),
-            Opcode::LDY => self.ldy(instruction),
-            Opcode::LSR => self.lsr(instruction),
-            Opcode::NOP => {},
-            Opcode::ORA => self.ora(instruction),
+            Opcode::JMP => self.jmp(instruction)?,
+            Opcode::JSR => self.jsr(),
+            Opcode::LDA => self.lda(instruction)?,
+            Opcode::LDX => self.ldx(instruction)?,
+            Opcode::LDY => self.ldy(instruction)?,
+            Opcode::LSR => self.lsr(instruction)?,
+            Opcode::NOP => self.nop(instruction)?,
+            Opcode::ORA => self.ora(instruction)?,
             Opcode::PHA => self.pha(),
             Opcode::PHP => self.php(),
             Opcode::PLA => self.pla(),
             Opcode::PLP => self.plp(),
-            Opcode::ROL => self.rol(instruction),
-            Opcode::ROR => self.ror(instruction),
+            Opcode::ROL => self.rol(instruction)?,
+            Opcode::ROR => self.ror(instruction)?,
             Opcode::RTI => self.rti(),
             Opcode::RTS => self.rts(),
-            Opcode::SBC => self.sbc(instruction),
+            Opcode::SBC => self.sbc(instruction)?,
             Opcode::SEC => self.sec(),
             Opcode::SED => self.sed(),
             Opcode::SEI => self.sei(),
-            Opcode::STA => self.sta(instruction),
-            Opcode::STX => self.stx(instruction),
-            Opcode::STY => self.sty(instruction),
+            Opcode::STA => self.sta(instruction)?,
+            Opcode::STX => self.stx(instruction)?,
+            Opcode::STY => self.sty(instruction)?,
             Opcode::TAX => self.tax(),
             Opcode::TAY => self.tay(),
             Opcode::TSX => self.tsx(),
             Opcode::TXA => self.txa(),
             Opcode::TXS => self.txs(),
             Opcode::TYA => self.tya(),
+            Opcode::ALR => self.alr(instruction)?,
+            Opcode::ANC => self.anc(instruction)?,
+            Opcode::ANE => self.ane(instruction)?,
+            Opcode::ARR => self.arr(instruction)?,
+            Opcode::DCP => self.dcp(instruction)?,
+            Opcode::ISB => self.isb(instruction)?,
+            Opcode::JAM => {
+                self.jam();
+                return Err(CpuError::Jammed { address: self.pc });
+            },
+            Opcode::LAS => self.las(instruction)?,
+            Opcode::LAX => self.lax(instruction)?,
+            Opcode::LXA => self.lxa(instruction)?,
+            Opcode::RLA => self.rla(instruction)?,
+            Opcode::RRA => self.rra(instruction)?,
+            Opcode::SAX => self.sax(instruction)?,
+            Opcode::SBX => self.sbx(instruction)?,
+            Opcode::SHA => self.sha(instruction)?,
+            Opcode::SHX => self.shx(instruction)?,
+            Opcode::SHY => self.shy(instruction)?,
+            Opcode::SLO => self.slo(instruction)?,
+            Opcode::SRE => self.sre(instruction)?,
+            Opcode::TAS => self.tas(instruction)?,
         }
-        self.pc += (instruction.bytes - 1) as u16;
+        Ok(())
     }
 
-
-    pub fn run<F: Fn()>(&mut self, callback: F) {
+    // Runs until the CPU hits an error
+    pub fn run<F: Fn()>(&mut self, callback: F) -> Result<(), CpuError> {
         loop {
-            self.execute_instruction();
+            self.execute_instruction()?;
             callback();
         }
     }
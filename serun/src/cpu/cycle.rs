@@ -0,0 +1,391 @@
+use crate::bus::Bus;
+use crate::cpu::{CPU, CpuError, Interrupt, MemoryAccess, StatusFlag, STATUS_BREAK, STATUS_UNUSED};
+use crate::opcodes::{CPU_OPCODES, AddressingMode, Instruction, Opcode};
+
+// The cycle-stepped core. Each instruction is broken down into the steps the
+// real CPU takes after fetching the opcode, and every step that touches the
+// bus takes exactly one cycle. Steps that don't need the bus (like adding an
+// index that doesn't cross a page on a read) are skipped over, and the next
+// step runs on the same cycle.
+//
+// The last step of most instructions is `Execute`, which hands the address
+// worked out so far to the same code `execute_instruction` uses, so the two
+// paths can't disagree about what an instruction does.
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Index {
+    X,
+    Y,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Step {
+    // Reads the byte at pc and throws it away
+    DummyRead,
+    // Reads the byte at pc and throws it away, moving past it
+    SkipByte,
+    // The operand is the byte at pc. Doesn't need a cycle of its own.
+    ImmediateAddress,
+    FetchAddressLo,
+    FetchAddressHi,
+    // Reads from the zero page address before the index gets added to it
+    IndexZeroPage(Index),
+    FetchPointer,
+    FetchPointerHi,
+    // Reads from the zero page pointer before X gets added to it
+    IndexPointer,
+    ReadAddressLo,
+    // Reads the high byte of the address from the byte after the pointer,
+    // without carrying into the pointer's high byte
+    ReadAddressHi,
+    // Only takes a cycle when a page is crossed or the instruction isn't a
+    // plain read
+    IndexAddress(Index),
+    ReadData,
+    DummyWrite,
+    // Runs the instruction, which makes the final access to its operand
+    Execute,
+    // Single byte instructions run on the cycle after the opcode fetch
+    Implied,
+    DummyStackRead,
+    PushPcHi,
+    PushPcLo,
+    PushStatus,
+    PullStatus,
+    PullAccumulator,
+    PullPcLo,
+    PullPcHi,
+    JumpAbsolute,
+    JumpIndirect,
+    Branch,
+    BranchTaken,
+    BranchFixup,
+}
+
+use Step::*;
+
+const IMPLIED_STEPS: [Step; 1] = [Implied];
+const PUSH_STEPS: [Step; 2] = [DummyRead, Execute];
+const PLA_STEPS: [Step; 3] = [DummyRead, DummyStackRead, PullAccumulator];
+const PLP_STEPS: [Step; 3] = [DummyRead, DummyStackRead, PullStatus];
+const JSR_STEPS: [Step; 5] = [FetchAddressLo, DummyStackRead, PushPcHi, PushPcLo, JumpAbsolute];
+const RTS_STEPS: [Step; 5] = [DummyRead, DummyStackRead, PullPcLo, PullPcHi, SkipByte];
+const RTI_STEPS: [Step; 5] = [DummyRead, DummyStackRead, PullStatus, PullPcLo, PullPcHi];
+const JMP_ABSOLUTE_STEPS: [Step; 2] = [FetchAddressLo, JumpAbsolute];
+const JMP_INDIRECT_STEPS: [Step; 4] = [FetchPointer, FetchPointerHi, ReadAddressLo, JumpIndirect];
+const BRANCH_STEPS: [Step; 3] = [Branch, BranchTaken, BranchFixup];
+const BRK_STEPS: [Step; 6] = [SkipByte, PushPcHi, PushPcLo, PushStatus, ReadAddressLo, JumpIndirect];
+// Interrupts run BRK, except that the opcode fetch and padding byte are both
+// dummy reads that don't move pc
+const INTERRUPT_STEPS: [Step; 7] = [DummyRead, DummyRead, PushPcHi, PushPcLo, PushStatus, ReadAddressLo, JumpIndirect];
+const JAM_STEPS: [Step; 1] = [Execute];
+
+const READ_STEPS: [Step; 1] = [Execute];
+const READ_MODIFY_WRITE_STEPS: [Step; 3] = [ReadData, DummyWrite, Execute];
+
+const BRK_OPCODE: u8 = 0x00;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum StepOutcome {
+    // The step didn't need the bus, so the next one runs on the same cycle
+    Skipped,
+    Cycle,
+    // Like `Cycle`, but without polling for interrupts at the end of it
+    CycleWithoutPoll,
+    // The step took the last cycle of the instruction
+    Finished,
+}
+
+// Where `tick` is in the instruction or interrupt sequence it is running
+pub(super) struct CycleState {
+    instruction: &'static Instruction,
+    // Set when running an interrupt sequence rather than an instruction
+    interrupt: Option<Interrupt>,
+    // Addressing steps followed by the steps accessing the operand
+    addressing_steps: &'static [Step],
+    access_steps: &'static [Step],
+    next_step: usize,
+    // Values the CPU holds on to between cycles
+    address: u16,
+    pointer: u16,
+    data: u8,
+    // Interrupt polled at the end of the previous cycle
+    polled_interrupt: Option<Interrupt>,
+}
+
+impl CycleState {
+    fn new(instruction: &'static Instruction, addressing_steps: &'static [Step], access_steps: &'static [Step]) -> Self {
+        CycleState {
+            instruction,
+            interrupt: None,
+            addressing_steps,
+            access_steps,
+            next_step: 0,
+            address: 0,
+            pointer: 0,
+            data: 0,
+            polled_interrupt: None,
+        }
+    }
+
+    fn next_step(&mut self) -> Option<Step> {
+        let step = match self.addressing_steps.get(self.next_step) {
+            Some(step) => Some(*step),
+            None => self.access_steps.get(self.next_step - self.addressing_steps.len()).copied(),
+        };
+        self.next_step += 1;
+        step
+    }
+
+    fn is_last_step(&self) -> bool {
+        self.next_step >= self.addressing_steps.len() + self.access_steps.len()
+    }
+}
+
+fn memory_access(opcode: &Opcode) -> MemoryAccess {
+    match opcode {
+        Opcode::STA | Opcode::STX | Opcode::STY | Opcode::SAX
+            | Opcode::SHA | Opcode::SHX | Opcode::SHY | Opcode::TAS => MemoryAccess::Write,
+        Opcode::ASL | Opcode::LSR | Opcode::ROL | Opcode::ROR | Opcode::INC | Opcode::DEC
+            | Opcode::SLO | Opcode::SRE | Opcode::RLA | Opcode::RRA | Opcode::DCP | Opcode::ISB => MemoryAccess::ReadModifyWrite,
+        _ => MemoryAccess::Read,
+    }
+}
+
+fn addressing_steps(mode: &AddressingMode) -> Option<&'static [Step]> {
+    let steps: &'static [Step] = match mode {
+        AddressingMode::Immediate => &[ImmediateAddress],
+        AddressingMode::ZeroPage => &[FetchAddressLo],
+        AddressingMode::ZeroPage_X => &[FetchAddressLo, IndexZeroPage(Index::X)],
+        AddressingMode::ZeroPage_Y => &[FetchAddressLo, IndexZeroPage(Index::Y)],
+        AddressingMode::Absolute => &[FetchAddressLo, FetchAddressHi],
+        AddressingMode::Absolute_X => &[FetchAddressLo, FetchAddressHi, IndexAddress(Index::X)],
+        AddressingMode::Absolute_Y => &[FetchAddressLo, FetchAddressHi, IndexAddress(Index::Y)],
+        AddressingMode::Indirect_X => &[FetchPointer, IndexPointer, ReadAddressLo, ReadAddressHi],
+        AddressingMode::Indirect_Y => &[FetchPointer, ReadAddressLo, ReadAddressHi, IndexAddress(Index::Y)],
+        _ => return None,
+    };
+    Some(steps)
+}
+
+impl<B: Bus> CPU<B> {
+    // Runs the CPU for a single cycle, making the same bus access the real CPU
+    // makes on that cycle. Returns whether the cycle finished an instruction
+    // (or interrupt sequence). This can be mixed freely with
+    // `execute_instruction`.
+    pub fn tick(&mut self) -> Result<bool, CpuError> {
+        if self.jammed {
+            return Err(CpuError::Jammed { address: self.pc });
+        }
+
+        let mut state = match self.cycle_state.take() {
+            Some(state) => state,
+            None => match self.pending_interrupt.take() {
+                Some(interrupt) => {
+                    let mut state = CycleState::new(&CPU_OPCODES[&BRK_OPCODE], &INTERRUPT_STEPS, &[]);
+                    state.interrupt = Some(interrupt);
+                    state
+                }
+                None => {
+                    let state = self.fetch_opcode()?;
+                    return Ok(self.end_cycle(state, StepOutcome::Cycle));
+                }
+            },
+        };
+
+        let mut outcome = StepOutcome::Finished;
+        while let Some(step) = state.next_step() {
+            outcome = self.run_step(&mut state, step)?;
+            if outcome != StepOutcome::Skipped {
+                break;
+            }
+        }
+        Ok(self.end_cycle(state, outcome))
+    }
+
+    fn fetch_opcode(&mut self) -> Result<CycleState, CpuError> {
+        self.page_crossed = false;
+        self.extra_cycles = 0;
+        let instruction_hex = self.bus.read(self.pc);
+        let instruction = CPU_OPCODES.get(&instruction_hex)
+            .ok_or(CpuError::UnknownOpcode { opcode: instruction_hex, address: self.pc })?;
+        self.trace(instruction);
+        self.pc = self.pc.wrapping_add(1);
+
+        let steps: (&'static [Step], &'static [Step]) = match &instruction.opcode {
+            Opcode::BRK => (&BRK_STEPS, &[]),
+            Opcode::JSR => (&JSR_STEPS, &[]),
+            Opcode::RTS => (&RTS_STEPS, &[]),
+            Opcode::RTI => (&RTI_STEPS, &[]),
+            Opcode::PHA | Opcode::PHP => (&PUSH_STEPS, &[]),
+            Opcode::PLA => (&PLA_STEPS, &[]),
+            Opcode::PLP => (&PLP_STEPS, &[]),
+            Opcode::JAM => (&JAM_STEPS, &[]),
+            Opcode::JMP if instruction.addressing_mode == AddressingMode::Indirect => (&JMP_INDIRECT_STEPS, &[]),
+            Opcode::JMP => (&JMP_ABSOLUTE_STEPS, &[]),
+            opcode if opcode.is_branch() => (&BRANCH_STEPS, &[]),
+            _ if instruction.bytes == 1 => (&IMPLIED_STEPS, &[]),
+            opcode => {
+                let addressing = addressing_steps(&instruction.addressing_mode)
+                    .ok_or_else(|| self.unsupported_addressing_mode(&instruction.addressing_mode))?;
+                let access: &'static [Step] = match memory_access(opcode) {
+                    MemoryAccess::ReadModifyWrite => &READ_MODIFY_WRITE_STEPS,
+                    _ => &READ_STEPS,
+                };
+                (addressing, access)
+            }
+        };
+        Ok(CycleState::new(instruction, steps.0, steps.1))
+    }
+
+    // Interrupts are polled at the end of every cycle, but only the poll on the
+    // second to last cycle of an instruction counts
+    fn end_cycle(&mut self, mut state: CycleState, outcome: StepOutcome) -> bool {
+        self.cycles += 1;
+        if outcome == StepOutcome::Finished || state.is_last_step() {
+            if state.interrupt.is_none() {
+                self.latch_interrupt(state.polled_interrupt);
+            }
+            return true;
+        }
+
+        if outcome != StepOutcome::CycleWithoutPoll {
+            let irq_disabled = self.get_status_flag(StatusFlag::I) == 1;
+            state.polled_interrupt = self.poll_interrupt_lines(irq_disabled);
+        }
+        self.cycle_state = Some(state);
+        false
+    }
+
+    fn index_register(&self, index: Index) -> u8 {
+        match index {
+            Index::X => self.register_x,
+            Index::Y => self.register_y,
+        }
+    }
+
+    fn branch_condition_met(&mut self, opcode: &Opcode) -> bool {
+        match opcode {
+            Opcode::BCC => self.is_branch_taken(StatusFlag::C, false),
+            Opcode::BCS => self.is_branch_taken(StatusFlag::C, true),
+            Opcode::BEQ => self.is_branch_taken(StatusFlag::Z, true),
+            Opcode::BMI => self.is_branch_taken(StatusFlag::N, true),
+            Opcode::BNE => self.is_branch_taken(StatusFlag::Z, false),
+            Opcode::BPL => self.is_branch_taken(StatusFlag::N, false),
+            Opcode::BVC => self.is_branch_taken(StatusFlag::V, false),
+            Opcode::BVS => self.is_branch_taken(StatusFlag::V, true),
+            _ => false,
+        }
+    }
+
+    fn run_step(&mut self, state: &mut CycleState, step: Step) -> Result<StepOutcome, CpuError> {
+        match step {
+            DummyRead => {
+                self.bus.read(self.pc);
+            }
+            SkipByte => {
+                self.fetch_byte();
+            }
+            ImmediateAddress => {
+                state.address = self.pc;
+                self.pc = self.pc.wrapping_add(1);
+                return Ok(StepOutcome::Skipped);
+            }
+            FetchAddressLo => state.address = self.fetch_byte() as u16,
+            FetchAddressHi => state.address |= (self.fetch_byte() as u16) << 8,
+            IndexZeroPage(index) => {
+                self.bus.read(state.address);
+                state.address = (state.address as u8).wrapping_add(self.index_register(index)) as u16;
+            }
+            FetchPointer => state.pointer = self.fetch_byte() as u16,
+            FetchPointerHi => state.pointer |= (self.fetch_byte() as u16) << 8,
+            IndexPointer => {
+                self.bus.read(state.pointer);
+                state.pointer = (state.pointer as u8).wrapping_add(self.register_x) as u16;
+            }
+            ReadAddressLo => state.address = self.bus.read(state.pointer) as u16,
+            ReadAddressHi => state.address |= (self.read_pointer_hi(state.pointer) as u16) << 8,
+            IndexAddress(index) => {
+                let access = memory_access(&state.instruction.opcode);
+                state.address = self.add_index(state.address, self.index_register(index), access);
+                if !self.page_crossed && access == MemoryAccess::Read {
+                    return Ok(StepOutcome::Skipped);
+                }
+            }
+            ReadData => state.data = self.bus.read(state.address),
+            DummyWrite => self.bus.write(state.address, state.data),
+            Execute => {
+                self.latched_address = Some(state.address);
+                if memory_access(&state.instruction.opcode) == MemoryAccess::ReadModifyWrite {
+                    self.latched_data = Some(state.data);
+                }
+                let result = self.execute_operation(state.instruction);
+                self.latched_address = None;
+                self.latched_data = None;
+                result?;
+            }
+            Implied => {
+                self.bus.read(self.pc);
+                self.execute_operation(state.instruction)?;
+            }
+            DummyStackRead => self.dummy_stack_read(),
+            PushPcHi => self.push_stack((self.pc >> 8) as u8),
+            PushPcLo => self.push_stack((self.pc & 0xFF) as u8),
+            PushStatus => {
+                let (status, vector) = match state.interrupt {
+                    Some(interrupt) => ((self.status & !STATUS_BREAK) | STATUS_UNUSED, interrupt.vector()),
+                    None => (self.status | STATUS_BREAK | STATUS_UNUSED, self.brk_vector()),
+                };
+                self.push_stack(status);
+                self.set_status_flag(StatusFlag::I);
+                state.pointer = vector;
+            }
+            PullStatus => self.pull_status(),
+            PullAccumulator => self.pull_accumulator(),
+            PullPcLo => state.address = self.pop_stack() as u16,
+            PullPcHi => {
+                state.address |= (self.pop_stack() as u16) << 8;
+                self.pc = state.address;
+            }
+            JumpAbsolute => {
+                state.address |= (self.fetch_byte() as u16) << 8;
+                self.pc = state.address;
+            }
+            JumpIndirect => {
+                state.address |= (self.read_pointer_hi(state.pointer) as u16) << 8;
+                self.pc = state.address;
+            }
+            Branch => {
+                let displacement = self.fetch_byte() as i8;
+                if !self.branch_condition_met(&state.instruction.opcode) {
+                    return Ok(StepOutcome::Finished);
+                }
+                state.address = self.pc.wrapping_add(displacement as u16);
+                // A taken branch that stays on the same page doesn't poll for
+                // interrupts on its last two cycles, so an interrupt that
+                // comes in during it waits for the next instruction to finish
+                if !Self::is_page_crossed(self.pc, state.address) {
+                    return Ok(StepOutcome::CycleWithoutPoll);
+                }
+            }
+            BranchTaken => {
+                self.bus.read(self.pc);
+                if !Self::is_page_crossed(self.pc, state.address) {
+                    self.pc = state.address;
+                    return Ok(StepOutcome::Finished);
+                }
+            }
+            BranchFixup => {
+                self.bus.read((self.pc & 0xFF00) | (state.address & 0x00FF));
+                self.pc = state.address;
+            }
+        }
+        Ok(StepOutcome::Cycle)
+    }
+
+    // The 6502 never carries into the high byte of the pointer, so the high
+    // byte comes from the start of the page when the pointer is at its end
+    fn read_pointer_hi(&mut self, pointer: u16) -> u8 {
+        self.bus.read((pointer & 0xFF00) | (pointer.wrapping_add(1) & 0x00FF))
+    }
+}
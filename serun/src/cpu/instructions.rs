@@ -1,7 +1,12 @@
-use crate::cpu::{CPU, StatusFlag};
+use crate::bus::Bus;
+use crate::cpu::{CPU, CpuError, MemoryAccess, StatusFlag, IRQ_VECTOR, NMI_VECTOR, STATUS_BREAK, STATUS_UNUSED};
 use crate::opcodes::{AddressingMode, Instruction};
 
-impl CPU {
+// The "magic" constant ANE and LXA OR into the accumulator varies between
+// chips and even with temperature. $EE is what most NES CPUs produce.
+const UNSTABLE_MAGIC: u8 = 0xEE;
+
+impl<B: Bus> CPU<B> {
     fn modify_accumulator(&mut self, new_accumulator_value: u16, operand: u8) {
         if new_accumulator_value > 0xFF {
             self.set_status_flag(StatusFlag::C);
@@ -20,51 +25,64 @@ impl CPU {
         self.update_zero_and_negative_flags(self.register_a);
     }
 
-    // TODO: Create wrapping add for new accumulator values
-    pub fn adc (&mut self, instruction: &Instruction) {
-        let operand = self.get_operand(instruction);
+    fn add_with_carry(&mut self, operand: u8) {
         let new_accumulator_value = self.register_a as u16 + operand as u16 + self.get_status_flag(StatusFlag::C) as u16;
         self.modify_accumulator(new_accumulator_value, operand);
     }
 
-    pub fn sbc (&mut self, instruction: &Instruction) {
-        let operand = self.get_operand(instruction);
-        let new_accumulator_value = self.register_a as u16 - operand as u16 - (1 - self.get_status_flag(StatusFlag::C) as u16);
-        self.modify_accumulator(new_accumulator_value, operand);
+    pub fn adc (&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let operand = self.get_operand(instruction)?;
+        self.add_with_carry(operand);
+        Ok(())
+    }
+
+    // A - M - (1 - C) is the same as A + !M + C, which also gets the carry and
+    // overflow flags right
+    pub fn sbc (&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let operand = self.get_operand(instruction)?;
+        self.add_with_carry(!operand);
+        Ok(())
     }
 
-    pub fn and(&mut self, instruction: &Instruction) {
-        self.register_a &= self.get_operand(instruction);
+    pub fn and(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.register_a &= self.get_operand(instruction)?;
         self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
+    }
+
+    fn shift_left(&mut self, mut operand: u8) -> u8 {
+        let old_bit_seven = (operand & 0b1000_0000) >> 7;
+        operand <<= 1;
+        self.clear_status_flag(StatusFlag::C);
+        self.status |= old_bit_seven;
+        operand
     }
 
-    pub fn asl(&mut self, instruction: &Instruction) {
+    pub fn asl(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
         match &instruction.addressing_mode {
             AddressingMode::Accumulator => {
-                let old_bit_seven = (self.register_a & 0b1000_0000) >> 7;
-                self.register_a <<= 1;
-                self.clear_status_flag(StatusFlag::C);
-                self.status |= old_bit_seven;
+                self.register_a = self.shift_left(self.register_a);
                 self.update_zero_and_negative_flags(self.register_a);
             },
             AddressingMode::ZeroPage | AddressingMode::ZeroPage_X | AddressingMode::Absolute | AddressingMode::Absolute_X => {
-                let operand_address = self.get_operand_address(&instruction.addressing_mode);
-                let mut operand = self.get_operand(instruction);
-                let old_bit_seven = (operand & 0b1000_0000) >> 7;
-                operand <<= 1;
-                self.clear_status_flag(StatusFlag::C);
-                self.status |= old_bit_seven;
-                self.memory.write(operand_address, operand);
-                self.update_zero_and_negative_flags(operand);
+                let shifted_operand = self.modify_memory(instruction, Self::shift_left)?;
+                self.update_zero_and_negative_flags(shifted_operand);
             },
-            _ => {}
+            _ => {
+                return Err(self.unsupported_addressing_mode(&instruction.addressing_mode));
+            }
         }
+        Ok(())
     }
 
     pub fn clc(&mut self) {
         self.clear_status_flag(StatusFlag::C);
     }
 
+    pub fn cld(&mut self) {
+        self.clear_status_flag(StatusFlag::D);
+    }
+
     pub fn cli(&mut self) {
         self.clear_status_flag(StatusFlag::I);
     }
@@ -97,27 +115,28 @@ impl CPU {
         }
     }
 
-    pub fn cmp(&mut self, instruction: &Instruction) {
-        let value = self.get_operand(instruction);
+    pub fn cmp(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let value = self.get_operand(instruction)?;
         self.compare_register(self.register_a, value);
+        Ok(())
     }
 
-    pub fn cpx(&mut self, instruction: &Instruction) {
-        let value = self.get_operand(instruction);
+    pub fn cpx(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let value = self.get_operand(instruction)?;
         self.compare_register(self.register_x, value);
+        Ok(())
     }
 
-    pub fn cpy(&mut self, instruction: &Instruction) {
-        let value = self.get_operand(instruction);
+    pub fn cpy(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let value = self.get_operand(instruction)?;
         self.compare_register(self.register_y, value);
+        Ok(())
     }
 
-    pub fn dec(&mut self, instruction: &Instruction) {
-        let address = self.get_operand_address(&instruction.addressing_mode);
-        let mem_value = self.memory.read(address);
-        let result = mem_value.wrapping_sub(1);
-        self.memory.write(address, result);
+    pub fn dec(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let result = self.modify_memory(instruction, |_, value| value.wrapping_sub(1))?;
         self.update_zero_and_negative_flags(result);
+        Ok(())
     }
 
     pub fn dex(&mut self) {
@@ -130,17 +149,16 @@ impl CPU {
         self.update_zero_and_negative_flags(self.register_y);
     }
 
-    pub fn eor(&mut self, instruction: &Instruction) {
-        self.register_a ^= self.get_operand(instruction);
+    pub fn eor(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.register_a ^= self.get_operand(instruction)?;
         self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
     }
 
-    pub fn inc(&mut self, instruction: &Instruction) {
-        let address = self.get_operand_address(&instruction.addressing_mode);
-        let mem_value = self.memory.read(address);
-        let result = mem_value.wrapping_add(1);
-        self.memory.write(address, result);
+    pub fn inc(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let result = self.modify_memory(instruction, |_, value| value.wrapping_add(1))?;
         self.update_zero_and_negative_flags(result);
+        Ok(())
     }
 
     pub fn inx(&mut self) {
@@ -153,50 +171,99 @@ impl CPU {
         self.update_zero_and_negative_flags(self.register_y);
     }
 
-    pub fn lda(&mut self, instruction: &Instruction) {
-        self.register_a = self.get_operand(instruction);
+    pub fn lda(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.register_a = self.get_operand(instruction)?;
         self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
     }
 
-    pub fn ldx(&mut self, instruction: &Instruction) {
-        self.register_x = self.get_operand(instruction);
+    pub fn ldx(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.register_x = self.get_operand(instruction)?;
         self.update_zero_and_negative_flags(self.register_x);
+        Ok(())
     }
 
-    pub fn ldy(&mut self, instruction: &Instruction) {
-        self.register_y = self.get_operand(instruction);
+    pub fn ldy(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.register_y = self.get_operand(instruction)?;
         self.update_zero_and_negative_flags(self.register_y);
+        Ok(())
+    }
+
+    // The unofficial NOPs that take an operand still read it from memory
+    pub fn nop(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        if !matches!(instruction.addressing_mode, AddressingMode::NoneAddressing) {
+            self.get_operand(instruction)?;
+        }
+        Ok(())
     }
 
-    pub fn ora(&mut self, instruction: &Instruction) {
-        self.register_a |= self.get_operand(instruction);
+    pub fn ora(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.register_a |= self.get_operand(instruction)?;
         self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
     }
 
     pub fn pha(&mut self) {
         self.push_stack(self.register_a);
     }
 
+    // The status register is always pushed with the break flag set
     pub fn php(&mut self) {
-        self.push_stack(self.status);
+        self.push_stack(self.status | STATUS_BREAK | STATUS_UNUSED);
     }
 
-    pub fn pla(&mut self) {
+    pub(super) fn pull_accumulator(&mut self) {
         self.register_a = self.pop_stack();
         self.update_zero_and_negative_flags(self.register_a);
     }
 
+    pub fn pla(&mut self) {
+        self.dummy_stack_read();
+        self.pull_accumulator();
+    }
+
+    // Pulls the status register, ignoring the break flag and unused bit
+    pub(super) fn pull_status(&mut self) {
+        self.status = (self.pop_stack() & !STATUS_BREAK) | STATUS_UNUSED;
+    }
+
     pub fn plp(&mut self) {
-        self.status = self.pop_stack();
+        self.dummy_stack_read();
+        self.pull_status();
     }
 
     pub fn rti(&mut self) {
-        self.status = self.pop_stack();
+        self.dummy_stack_read();
+        self.pull_status();
         self.pc = self.pop_stack_u16();
     }
 
+    // BRK skips over the padding byte following it, pushes the status register
+    // with the break flag set and jumps through the IRQ vector. If an NMI comes
+    // in while BRK is executing, it hijacks the vector fetch.
+    pub fn brk(&mut self) {
+        self.pc = self.pc.wrapping_add(1);
+        self.push_stack_u16(self.pc);
+        self.push_stack(self.status | STATUS_BREAK | STATUS_UNUSED);
+        self.set_status_flag(StatusFlag::I);
+        let vector = self.brk_vector();
+        self.pc = self.bus.read_u16(vector);
+    }
+
+    pub(super) fn brk_vector(&mut self) -> u16 {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            NMI_VECTOR
+        } else {
+            IRQ_VECTOR
+        }
+    }
+
+    // RTS reads from the pulled address before moving past it
     pub fn rts(&mut self) {
+        self.dummy_stack_read();
         let stack_val = self.pop_stack_u16();
+        self.bus.read(stack_val);
         self.pc = stack_val.wrapping_add(1);
     }
 
@@ -212,19 +279,22 @@ impl CPU {
         self.set_status_flag(StatusFlag::I);
     }
 
-    pub fn sta(&mut self, instruction: &Instruction) {
-        let address = self.get_operand_address(&instruction.addressing_mode);
-        self.memory.write(address, self.register_a);
+    pub fn sta(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let address = self.get_operand_address(&instruction.addressing_mode, MemoryAccess::Write)?;
+        self.bus.write(address, self.register_a);
+        Ok(())
     }
 
-    pub fn stx(&mut self, instruction: &Instruction) {
-        let address = self.get_operand_address(&instruction.addressing_mode);
-        self.memory.write(address, self.register_x);
+    pub fn stx(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let address = self.get_operand_address(&instruction.addressing_mode, MemoryAccess::Write)?;
+        self.bus.write(address, self.register_x);
+        Ok(())
     }
 
-    pub fn sty(&mut self, instruction: &Instruction) {
-        let address = self.get_operand_address(&instruction.addressing_mode);
-        self.memory.write(address, self.register_y);
+    pub fn sty(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let address = self.get_operand_address(&instruction.addressing_mode, MemoryAccess::Write)?;
+        self.bus.write(address, self.register_y);
+        Ok(())
     }
 
     pub fn tax(&mut self) {
@@ -265,23 +335,21 @@ impl CPU {
         operand
     }
 
-    pub fn rol(&mut self, instruction: &Instruction) {
+    pub fn rol(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
         match &instruction.addressing_mode {
             AddressingMode::Accumulator => {
                 self.register_a = self.rotate_left(self.register_a);
                 self.update_zero_and_negative_flags(self.register_a);
             },
             AddressingMode::ZeroPage | AddressingMode::ZeroPage_X | AddressingMode::Absolute | AddressingMode::Absolute_X => {
-                let operand_address = self.get_operand_address(&instruction.addressing_mode);
-                let operand = self.get_operand(instruction);
-                let rotated_operand = self.rotate_left(operand);
-                self.memory.write(operand_address, rotated_operand);
+                let rotated_operand = self.modify_memory(instruction, Self::rotate_left)?;
                 self.update_zero_and_negative_flags(rotated_operand);
             },
             _ => {
-                panic!("Recieved unexpected address mode while performing rol instruction");
+                return Err(self.unsupported_addressing_mode(&instruction.addressing_mode));
             }
         }
+        Ok(())
     }
 
     fn rotate_right(&mut self, mut operand: u8) -> u8 {
@@ -293,34 +361,47 @@ impl CPU {
         operand
     }
 
-    pub fn ror(&mut self, instruction: &Instruction) {
+    pub fn ror(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
         match &instruction.addressing_mode {
             AddressingMode::Accumulator => {
                 self.register_a = self.rotate_right(self.register_a);
                 self.update_zero_and_negative_flags(self.register_a);
             },
             AddressingMode::ZeroPage | AddressingMode::ZeroPage_X | AddressingMode::Absolute | AddressingMode::Absolute_X => {
-                let operand_address = self.get_operand_address(&instruction.addressing_mode);
-                let operand = self.get_operand(instruction);
-                let rotated_operand = self.rotate_right(operand);
-                self.memory.write(operand_address, rotated_operand);
+                let rotated_operand = self.modify_memory(instruction, Self::rotate_right)?;
                 self.update_zero_and_negative_flags(rotated_operand);
             },
             _ => {
-                panic!("Recieved unexpected address mode while performing rol instruction");
+                return Err(self.unsupported_addressing_mode(&instruction.addressing_mode));
             }
         }
+        Ok(())
     }
 
-    // Helper function for branch instructions
+    // Helper function for branch instructions. A taken branch costs an extra
+    // cycle, and another one if the target is on a different page. Both are
+    // spent on dummy reads while pc gets fixed up.
     fn branch(&mut self, flag: StatusFlag, require_flag_is_set: bool) {
-        let flag = self.get_status_flag(flag);
-        if (require_flag_is_set && flag == 1) || (!require_flag_is_set && flag == 0) {
-            let displacement = self.memory.read(self.pc);
-            self.pc += self.pc.wrapping_add(displacement as u16);
+        // The displacement is relative to the address of the next instruction
+        let displacement = self.fetch_byte() as i8;
+        if self.is_branch_taken(flag, require_flag_is_set) {
+            let next_instruction = self.pc;
+            let target = next_instruction.wrapping_add(displacement as u16);
+            self.bus.read(next_instruction);
+            self.extra_cycles += 1;
+            if Self::is_page_crossed(next_instruction, target) {
+                self.bus.read((next_instruction & 0xFF00) | (target & 0x00FF));
+                self.extra_cycles += 1;
+            }
+            self.pc = target;
         }
     }
 
+    pub(super) fn is_branch_taken(&mut self, flag: StatusFlag, require_flag_is_set: bool) -> bool {
+        let flag = self.get_status_flag(flag);
+        (require_flag_is_set && flag == 1) || (!require_flag_is_set && flag == 0)
+    }
+
     pub fn bcc(&mut self) {
         self.branch(StatusFlag::C, false);
     }
@@ -333,8 +414,8 @@ impl CPU {
         self.branch(StatusFlag::Z, true);
     }
 
-    pub fn bit(&mut self, instruction: &Instruction) {
-        let operand = self.get_operand(instruction);
+    pub fn bit(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let operand = self.get_operand(instruction)?;
         let bit_seven = (operand & 0b1000_0000) >> 7;
         let bit_six = (operand & 0b0100_0000) >> 6;
         let result = self.register_a & operand;
@@ -362,6 +443,7 @@ impl CPU {
         else {
             self.clear_status_flag(StatusFlag::Z);
         }
+        Ok(())
     }
 
     pub fn bmi(&mut self) {
@@ -384,38 +466,221 @@ impl CPU {
         self.branch(StatusFlag::V, true);
     }
 
-    // TODO: Need to account for 6502 bug
-    pub fn jmp(&mut self, instruction: &Instruction) {
-        self.pc = self.get_operand_address(&instruction.addressing_mode);
+    pub fn jmp(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.pc = self.get_operand_address(&instruction.addressing_mode, MemoryAccess::Read)?;
+        Ok(())
     }
 
-    pub fn jsr(&mut self, instruction: &Instruction) {
-        let operand_address = self.get_operand_address(&instruction.addressing_mode);
-        self.pc = operand_address.wrapping_sub(1);
-        self.push_stack_u16(operand_address);
+    // JSR pushes the address of its own last byte, which RTS adds one to.
+    // The high byte of the target only gets fetched after pc has been pushed.
+    pub fn jsr(&mut self) {
+        let lo = self.fetch_byte() as u16;
+        self.dummy_stack_read();
+        self.push_stack_u16(self.pc);
+        let hi = self.fetch_byte() as u16;
+        self.pc = hi << 8 | lo;
     }
 
-    // TODO: This and asl should be more generalized
-    pub fn lsr(&mut self, instruction: &Instruction) {
+    fn shift_right(&mut self, mut operand: u8) -> u8 {
+        let old_bit_zero = operand & 1;
+        operand >>= 1;
+        self.clear_status_flag(StatusFlag::C);
+        self.status |= old_bit_zero;
+        operand
+    }
+
+    pub fn lsr(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
         match &instruction.addressing_mode {
             AddressingMode::Accumulator => {
-                let old_bit_zero = self.register_a & 1;
-                self.register_a >>= 1;
-                self.clear_status_flag(StatusFlag::C);
-                self.status |= old_bit_zero;
+                self.register_a = self.shift_right(self.register_a);
                 self.update_zero_and_negative_flags(self.register_a);
             },
             AddressingMode::ZeroPage | AddressingMode::ZeroPage_X | AddressingMode::Absolute | AddressingMode::Absolute_X => {
-                let operand_address = self.get_operand_address(&instruction.addressing_mode);
-                let mut operand = self.get_operand(instruction);
-                let old_bit_zero = operand & 1;
-                operand >>= 1;
-                self.clear_status_flag(StatusFlag::C);
-                self.status |= old_bit_zero;
-                self.memory.write(operand_address, operand);
-                self.update_zero_and_negative_flags(operand);
+                let shifted_operand = self.modify_memory(instruction, Self::shift_right)?;
+                self.update_zero_and_negative_flags(shifted_operand);
             },
-            _ => {}
+            _ => {
+                return Err(self.unsupported_addressing_mode(&instruction.addressing_mode));
+            }
         }
+        Ok(())
+    }
+
+    // Unofficial opcodes
+
+    // Runs `operation` on the value at the operand address and writes the result
+    // back, like the read-modify-write instructions do. The CPU writes the
+    // unmodified value back once while it's working out the new one.
+    fn modify_memory<F: FnOnce(&mut Self, u8) -> u8>(&mut self, instruction: &Instruction, operation: F) -> Result<u8, CpuError> {
+        let address = self.get_operand_address(&instruction.addressing_mode, MemoryAccess::ReadModifyWrite)?;
+        let value = match self.latched_data.take() {
+            Some(value) => value,
+            None => {
+                let value = self.bus.read(address);
+                self.bus.write(address, value);
+                value
+            }
+        };
+        let result = operation(self, value);
+        self.bus.write(address, result);
+        Ok(result)
+    }
+
+    pub fn alr(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let operand = self.get_operand(instruction)?;
+        self.register_a = self.shift_right(self.register_a & operand);
+        self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
+    }
+
+    pub fn anc(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.register_a &= self.get_operand(instruction)?;
+        self.update_zero_and_negative_flags(self.register_a);
+        self.clear_status_flag(StatusFlag::C);
+        self.status |= self.register_a >> 7;
+        Ok(())
+    }
+
+    pub fn ane(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let operand = self.get_operand(instruction)?;
+        self.register_a = (self.register_a | UNSTABLE_MAGIC) & self.register_x & operand;
+        self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
+    }
+
+    // AND followed by ROR A, except carry comes from bit 6 of the result and
+    // overflow from bit 6 xor bit 5
+    pub fn arr(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let operand = self.get_operand(instruction)?;
+        let result = ((self.register_a & operand) >> 1) | (self.get_status_flag(StatusFlag::C) << 7);
+        self.register_a = result;
+        self.update_zero_and_negative_flags(result);
+
+        let bit_six = (result >> 6) & 1;
+        let bit_five = (result >> 5) & 1;
+        self.clear_status_flag(StatusFlag::C);
+        self.status |= bit_six;
+        if bit_six ^ bit_five == 1 {
+            self.set_status_flag(StatusFlag::V);
+        } else {
+            self.clear_status_flag(StatusFlag::V);
+        }
+        Ok(())
+    }
+
+    pub fn dcp(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let result = self.modify_memory(instruction, |_, value| value.wrapping_sub(1))?;
+        self.compare_register(self.register_a, result);
+        Ok(())
+    }
+
+    pub fn isb(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let result = self.modify_memory(instruction, |_, value| value.wrapping_add(1))?;
+        self.add_with_carry(!result);
+        Ok(())
+    }
+
+    // Locks up the CPU. Only a reset gets it going again.
+    pub fn jam(&mut self) {
+        self.pc = self.pc.wrapping_sub(1);
+        self.jammed = true;
+    }
+
+    pub fn las(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let result = self.get_operand(instruction)? & self.stack_pointer;
+        self.register_a = result;
+        self.register_x = result;
+        self.stack_pointer = result;
+        self.update_zero_and_negative_flags(result);
+        Ok(())
+    }
+
+    pub fn lax(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.register_a = self.get_operand(instruction)?;
+        self.register_x = self.register_a;
+        self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
+    }
+
+    pub fn lxa(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let operand = self.get_operand(instruction)?;
+        self.register_a = (self.register_a | UNSTABLE_MAGIC) & operand;
+        self.register_x = self.register_a;
+        self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
+    }
+
+    pub fn rla(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let result = self.modify_memory(instruction, Self::rotate_left)?;
+        self.register_a &= result;
+        self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
+    }
+
+    pub fn rra(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let result = self.modify_memory(instruction, Self::rotate_right)?;
+        self.add_with_carry(result);
+        Ok(())
+    }
+
+    pub fn sax(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let address = self.get_operand_address(&instruction.addressing_mode, MemoryAccess::Write)?;
+        self.bus.write(address, self.register_a & self.register_x);
+        Ok(())
+    }
+
+    pub fn sbx(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let operand = self.get_operand(instruction)?;
+        let value = self.register_a & self.register_x;
+        self.compare_register(value, operand);
+        self.register_x = value.wrapping_sub(operand);
+        Ok(())
+    }
+
+    // SHA, SHX, SHY and TAS store a register ANDed with the high byte of the
+    // base address plus one. When indexing crosses a page, the value being
+    // stored also replaces the high byte of the address it gets written to.
+    fn store_and_high_byte(&mut self, instruction: &Instruction, index: u8, value: u8) -> Result<(), CpuError> {
+        let address = self.get_operand_address(&instruction.addressing_mode, MemoryAccess::Write)?;
+        let base = address.wrapping_sub(index as u16);
+        let result = value & ((base >> 8) as u8).wrapping_add(1);
+        let address = if self.page_crossed {
+            ((result as u16) << 8) | (address & 0xFF)
+        } else {
+            address
+        };
+        self.bus.write(address, result);
+        Ok(())
+    }
+
+    pub fn sha(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.store_and_high_byte(instruction, self.register_y, self.register_a & self.register_x)
+    }
+
+    pub fn shx(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.store_and_high_byte(instruction, self.register_y, self.register_x)
+    }
+
+    pub fn shy(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.store_and_high_byte(instruction, self.register_x, self.register_y)
+    }
+
+    pub fn slo(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let result = self.modify_memory(instruction, Self::shift_left)?;
+        self.register_a |= result;
+        self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
+    }
+
+    pub fn sre(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        let result = self.modify_memory(instruction, Self::shift_right)?;
+        self.register_a ^= result;
+        self.update_zero_and_negative_flags(self.register_a);
+        Ok(())
+    }
+
+    pub fn tas(&mut self, instruction: &Instruction) -> Result<(), CpuError> {
+        self.stack_pointer = self.register_a & self.register_x;
+        self.store_and_high_byte(instruction, self.register_y, self.stack_pointer)
     }
 }
\ No newline at end of file
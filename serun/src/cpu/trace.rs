@@ -0,0 +1,163 @@
+use std::io::Write;
+use crate::bus::Bus;
+use crate::opcodes::{AddressingMode, Instruction, Opcode};
+
+const PPU_DOTS_PER_CPU_CYCLE: u64 = 3;
+const PPU_DOTS_PER_SCANLINE: u64 = 341;
+const PPU_SCANLINES_PER_FRAME: u64 = 262;
+
+// Snapshot of the CPU taken right before an instruction is executed
+#[derive(Clone, Debug)]
+pub struct TraceRecord {
+    pub pc: u16,
+    // Opcode followed by its operand bytes. Only the first `instruction.bytes`
+    // bytes are meaningful, use `bytes()` to get them.
+    pub opcode_bytes: [u8; 3],
+    pub instruction: &'static Instruction,
+    pub register_a: u8,
+    pub register_x: u8,
+    pub register_y: u8,
+    pub stack_pointer: u8,
+    pub status: u8,
+    pub cycles: u64,
+}
+
+impl TraceRecord {
+    pub fn bytes(&self) -> &[u8] {
+        &self.opcode_bytes[..self.instruction.bytes as usize]
+    }
+}
+
+// Receives a record for every instruction the CPU executes. The bus is handed
+// over as well so tracers can look around memory, but only through `peek` so
+// tracing never changes what the program sees.
+pub trait Tracer {
+    fn trace(&mut self, record: &TraceRecord, bus: &dyn Bus);
+}
+
+impl<F: FnMut(&TraceRecord, &dyn Bus)> Tracer for F {
+    fn trace(&mut self, record: &TraceRecord, bus: &dyn Bus) {
+        self(record, bus)
+    }
+}
+
+// Formats a record the same way as the widely used nestest.log, e.g.
+// C000  4C F5 C5  JMP $C5F5                       A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7
+//
+// TODO: The PPU position is worked out from the cycle count for now. It should
+// come from the PPU once there is one.
+pub fn format_nestest(record: &TraceRecord, bus: &dyn Bus) -> String {
+    let bytes = record.bytes()
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    // Unofficial opcodes are marked with a '*' in front of their mnemonic
+    let marker = if is_unofficial(record) { '*' } else { ' ' };
+    let disassembly = format!("{:?}{}", record.instruction.opcode, format_operand(record, bus));
+    let ppu_dots = record.cycles * PPU_DOTS_PER_CPU_CYCLE;
+    let scanline = (ppu_dots / PPU_DOTS_PER_SCANLINE) % PPU_SCANLINES_PER_FRAME;
+    let dot = ppu_dots % PPU_DOTS_PER_SCANLINE;
+
+    format!(
+        "{:04X}  {:<8} {}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X} PPU:{:>3},{:>3} CYC:{}",
+        record.pc, bytes, marker, disassembly, record.register_a, record.register_x,
+        record.register_y, record.status, record.stack_pointer, scanline, dot, record.cycles
+    )
+}
+
+// Besides the opcodes with no official name, there are unofficial encodings of
+// NOP and SBC
+fn is_unofficial(record: &TraceRecord) -> bool {
+    match record.instruction.opcode {
+        Opcode::NOP => record.opcode_bytes[0] != 0xEA,
+        Opcode::SBC => record.opcode_bytes[0] == 0xEB,
+        ref opcode => opcode.is_unofficial(),
+    }
+}
+
+// Disassembles the operand, along with the effective address and the value
+// stored there where it makes sense
+fn format_operand(record: &TraceRecord, bus: &dyn Bus) -> String {
+    let instruction = record.instruction;
+    let lo = record.opcode_bytes[1];
+    let hi = record.opcode_bytes[2];
+    let operand_u16 = (hi as u16) << 8 | (lo as u16);
+    let next_instruction = record.pc.wrapping_add(instruction.bytes as u16);
+
+    if instruction.opcode.is_branch() {
+        let target = next_instruction.wrapping_add(lo as i8 as u16);
+        return format!(" ${target:04X}");
+    }
+
+    match instruction.addressing_mode {
+        AddressingMode::NoneAddressing => String::new(),
+        AddressingMode::Accumulator => String::from(" A"),
+        AddressingMode::Immediate => format!(" #${lo:02X}"),
+        AddressingMode::ZeroPage => format!(" ${lo:02X} = {:02X}", bus.peek(lo as u16)),
+        AddressingMode::ZeroPage_X | AddressingMode::ZeroPage_Y => {
+            let (index, name) = index_register(record);
+            let address = lo.wrapping_add(index) as u16;
+            format!(" ${lo:02X},{name} @ {address:02X} = {:02X}", bus.peek(address))
+        }
+        AddressingMode::Absolute => match instruction.opcode {
+            Opcode::JMP | Opcode::JSR => format!(" ${operand_u16:04X}"),
+            _ => format!(" ${operand_u16:04X} = {:02X}", bus.peek(operand_u16)),
+        },
+        AddressingMode::Absolute_X | AddressingMode::Absolute_Y => {
+            let (index, name) = index_register(record);
+            let address = operand_u16.wrapping_add(index as u16);
+            format!(" ${operand_u16:04X},{name} @ {address:04X} = {:02X}", bus.peek(address))
+        }
+        AddressingMode::Indirect => {
+            // Same page wrapping bug as the CPU
+            let target_lo = bus.peek(operand_u16);
+            let target_hi = bus.peek((operand_u16 & 0xFF00) | (operand_u16.wrapping_add(1) & 0x00FF));
+            let target = (target_hi as u16) << 8 | (target_lo as u16);
+            format!(" (${operand_u16:04X}) = {target:04X}")
+        }
+        AddressingMode::Indirect_X => {
+            let pointer = lo.wrapping_add(record.register_x);
+            let address = peek_zero_page_u16(bus, pointer);
+            format!(" (${lo:02X},X) @ {pointer:02X} = {address:04X} = {:02X}", bus.peek(address))
+        }
+        AddressingMode::Indirect_Y => {
+            let base = peek_zero_page_u16(bus, lo);
+            let address = base.wrapping_add(record.register_y as u16);
+            format!(" (${lo:02X}),Y = {base:04X} @ {address:04X} = {:02X}", bus.peek(address))
+        }
+    }
+}
+
+fn index_register(record: &TraceRecord) -> (u8, char) {
+    match record.instruction.addressing_mode {
+        AddressingMode::ZeroPage_Y | AddressingMode::Absolute_Y => (record.register_y, 'Y'),
+        _ => (record.register_x, 'X'),
+    }
+}
+
+// Pointers in the zero page wrap around within it
+fn peek_zero_page_u16(bus: &dyn Bus, pointer: u8) -> u16 {
+    let lo = bus.peek(pointer as u16) as u16;
+    let hi = bus.peek(pointer.wrapping_add(1) as u16) as u16;
+    hi << 8 | lo
+}
+
+// Tracer that writes a nestest.log style line for every instruction
+pub struct NestestTracer<W: Write> {
+    output: W,
+}
+
+impl<W: Write> NestestTracer<W> {
+    pub fn new(output: W) -> Self {
+        NestestTracer { output }
+    }
+}
+
+impl<W: Write> Tracer for NestestTracer<W> {
+    fn trace(&mut self, record: &TraceRecord, bus: &dyn Bus) {
+        // Tracing shouldn't be able to bring down the emulator, so a broken
+        // output just means lost trace lines
+        let _ = writeln!(self.output, "{}", format_nestest(record, bus));
+    }
+}
@@ -1,3 +1,4 @@
+pub mod bus;
 pub mod cpu;
 pub mod memory;
 pub mod opcodes;
@@ -5,8 +6,40 @@ pub mod cartridge;
 
 #[cfg(test)]
 mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use crate::bus::{Bus, BusAccess, BusAccessKind, NesBus, RecordingBus};
+    use crate::cartridge::{Cartidge, CartidgeError};
+    use crate::cartridge::database::{self, GameEntry, HeaderCorrection};
+    use crate::cartridge::hash::{Crc32, Sha1};
+    use crate::cartridge::patch::{self, PatchError};
+    use crate::cartridge::header::{CartridgeHeader, ConsoleType, HeaderFormat, Mirroring, TvSystem, VsHardware, VsPpu, VsSystemType};
+    use crate::cpu::trace::{format_nestest, TraceRecord};
+    use crate::memory::Memory;
     use crate::opcodes::{Instruction, Opcode, AddressingMode};
-    use super::cpu::CPU;
+    use super::cpu::{CPU, CpuError, StatusFlag};
+
+    // Wraps flat memory and keeps track of every write the CPU makes
+    #[derive(Default)]
+    struct WriteLoggingBus {
+        memory: Memory,
+        writes: Vec<(u16, u8)>,
+    }
+
+    impl Bus for WriteLoggingBus {
+        fn read(&mut self, addr: u16) -> u8 {
+            self.memory.read(addr)
+        }
+
+        fn write(&mut self, addr: u16, data: u8) {
+            self.writes.push((addr, data));
+            self.memory.write(addr, data);
+        }
+
+        fn peek(&self, addr: u16) -> u8 {
+            self.memory.peek(addr)
+        }
+    }
 
     // #[test]
     // fn test_0xa9_lda_immediate_load_data() {
@@ -67,6 +100,1561 @@ mod test {
         assert_eq!(cpu.stack_pointer, 0xFD);
     }
 
+    #[test]
+    fn test_cpu_with_custom_bus() {
+        let mut cpu = CPU::new(WriteLoggingBus::default());
+        cpu.reset();
+        cpu.push_stack(0xAB);
+        assert_eq!(cpu.bus.writes, vec![(0x01FD, 0xAB)]);
+        assert_eq!(cpu.pop_stack(), 0xAB);
+    }
+
+    #[test]
+    fn test_page_cross_penalty() {
+        let mut cpu: CPU = Default::default();
+        // LDX #$01; LDA $80FF,X; LDA $8000,X; STA $80FF,X
+        cpu.load_program(vec![0xA2, 0x01, 0xBD, 0xFF, 0x80, 0xBD, 0x00, 0x80, 0x9D, 0xFF, 0x80]);
+        assert_eq!(cpu.execute_instruction(), Ok(2));
+        assert_eq!(cpu.execute_instruction(), Ok(5));
+        assert_eq!(cpu.execute_instruction(), Ok(4));
+        assert_eq!(cpu.execute_instruction(), Ok(5));
+        // The reset sequence accounts for the first 7 cycles
+        assert_eq!(cpu.cycles, 7 + 16);
+    }
+
+    fn recording_cpu(program: Vec<u8>) -> CPU<RecordingBus<Memory>> {
+        let mut memory = Memory::default();
+        memory.load(program);
+        let mut cpu = CPU::new(RecordingBus::new(memory));
+        cpu.reset();
+        cpu.bus.accesses.clear();
+        cpu
+    }
+
+    fn read(addr: u16, data: u8) -> BusAccess {
+        BusAccess { addr, data, kind: BusAccessKind::Read }
+    }
+
+    fn write(addr: u16, data: u8) -> BusAccess {
+        BusAccess { addr, data, kind: BusAccessKind::Write }
+    }
+
+    #[test]
+    fn test_indexed_dummy_reads() {
+        // LDX #$01; LDA $80FF,X; STA $8000,X
+        let mut cpu = recording_cpu(vec![0xA2, 0x01, 0xBD, 0xFF, 0x80, 0x9D, 0x00, 0x80]);
+        cpu.execute_instruction().unwrap();
+        cpu.bus.accesses.clear();
+
+        // Crossing a page costs a read from the address before the fix up
+        assert_eq!(cpu.execute_instruction(), Ok(5));
+        assert_eq!(cpu.bus.accesses, vec![
+            read(0x8002, 0xBD), read(0x8003, 0xFF), read(0x8004, 0x80), read(0x8000, 0xA2), read(0x8100, 0x00),
+        ]);
+        cpu.bus.accesses.clear();
+
+        // Stores always take the dummy read
+        assert_eq!(cpu.execute_instruction(), Ok(5));
+        assert_eq!(cpu.bus.accesses, vec![
+            read(0x8005, 0x9D), read(0x8006, 0x00), read(0x8007, 0x80), read(0x8001, 0x01), write(0x8001, 0x00),
+        ]);
+    }
+
+    #[test]
+    fn test_read_modify_write_double_write() {
+        // INC $10
+        let mut cpu = recording_cpu(vec![0xE6, 0x10]);
+        cpu.bus.inner.write(0x10, 0x41);
+        assert_eq!(cpu.execute_instruction(), Ok(5));
+        assert_eq!(cpu.bus.accesses, vec![
+            read(0x8000, 0xE6), read(0x8001, 0x10), read(0x0010, 0x41), write(0x0010, 0x41), write(0x0010, 0x42),
+        ]);
+    }
+
+    #[test]
+    fn test_branch_cycles() {
+        let mut cpu: CPU = Default::default();
+        // BEQ +$02 (not taken); LDX #$00; BEQ +$02 (taken, same page)
+        cpu.load_program(vec![0xF0, 0x02, 0xA2, 0x00, 0xF0, 0x02]);
+        assert_eq!(cpu.execute_instruction(), Ok(2));
+        assert_eq!(cpu.execute_instruction(), Ok(2));
+        assert_eq!(cpu.execute_instruction(), Ok(3));
+        assert_eq!(cpu.pc, 0x8008);
+
+        // BEQ +$7F from $80F0 lands on the next page
+        cpu.bus.write(0x80F0, 0xF0);
+        cpu.bus.write(0x80F1, 0x7F);
+        cpu.pc = 0x80F0;
+        assert_eq!(cpu.execute_instruction(), Ok(4));
+        assert_eq!(cpu.pc, 0x8171);
+
+        // Backwards branches use a signed displacement
+        cpu.bus.write(0x8171, 0xF0);
+        cpu.bus.write(0x8172, 0xFC);
+        assert_eq!(cpu.execute_instruction(), Ok(3));
+        assert_eq!(cpu.pc, 0x816F);
+    }
+
+    #[test]
+    fn test_brk() {
+        let mut cpu: CPU = Default::default();
+        cpu.bus.write_u16(0xFFFE, 0x9000);
+        cpu.load_program(vec![0x00, 0xFF]);
+        assert_eq!(cpu.execute_instruction(), Ok(7));
+        assert_eq!(cpu.pc, 0x9000);
+        assert_eq!(cpu.pop_stack(), 0b0011_0100);
+        assert_eq!(cpu.pop_stack_u16(), 0x8002);
+    }
+
+    #[test]
+    fn test_nmi() {
+        let mut cpu: CPU = Default::default();
+        cpu.bus.write_u16(0xFFFA, 0x9000);
+        cpu.load_program(vec![0xEA, 0xEA]);
+        cpu.trigger_nmi();
+        assert_eq!(cpu.execute_instruction(), Ok(2));
+        assert_eq!(cpu.execute_instruction(), Ok(7));
+        assert_eq!(cpu.pc, 0x9000);
+        // NMI and IRQ push the status register with the break flag clear
+        assert_eq!(cpu.pop_stack(), 0b0010_0100);
+        assert_eq!(cpu.pop_stack_u16(), 0x8001);
+    }
+
+    #[test]
+    fn test_irq_delayed_after_cli() {
+        let mut cpu: CPU = Default::default();
+        cpu.bus.write_u16(0xFFFE, 0x9000);
+        // CLI; NOP; NOP
+        cpu.load_program(vec![0x58, 0xEA, 0xEA]);
+        cpu.set_irq_line(true);
+        cpu.execute_instruction().unwrap();
+        cpu.execute_instruction().unwrap();
+        assert_eq!(cpu.pc, 0x8002);
+        assert_eq!(cpu.execute_instruction(), Ok(7));
+        assert_eq!(cpu.pc, 0x9000);
+
+        // The I flag set on entry masks the still asserted line
+        cpu.bus.write(0x9000, 0xEA);
+        cpu.execute_instruction().unwrap();
+        assert_eq!(cpu.pc, 0x9001);
+    }
+
+    #[test]
+    fn test_tick_matches_execute_instruction() {
+        // LDX #$02; LDA ($10,X); STA $0300,Y; INC $0300; JSR $8010; BRK
+        // at $8010: PHA; PLA; ROL $20,X; RTS
+        let mut program = vec![0xA2, 0x02, 0xA1, 0x10, 0x99, 0x00, 0x03, 0xEE, 0x00, 0x03, 0x20, 0x10, 0x80, 0x00, 0x00, 0x00];
+        program.extend([0x48, 0x68, 0x36, 0x20, 0x60]);
+
+        let mut stepped = recording_cpu(program.clone());
+        let mut ticked = recording_cpu(program);
+        for cpu in [&mut stepped, &mut ticked] {
+            cpu.bus.inner.write_u16(0x0012, 0x0400);
+            cpu.bus.inner.write(0x0400, 0x81);
+        }
+
+        for _ in 0..10 {
+            let cycles = stepped.execute_instruction().unwrap();
+            let mut ticks = 1;
+            while !ticked.tick().unwrap() {
+                ticks += 1;
+            }
+            assert_eq!(ticks, cycles);
+            assert_eq!(ticked.pc, stepped.pc);
+            assert_eq!(ticked.status, stepped.status);
+            assert_eq!(ticked.bus.accesses, stepped.bus.accesses);
+        }
+        assert_eq!(ticked.cycles, stepped.cycles);
+    }
+
+    #[test]
+    fn test_tick_irq_delayed_by_taken_branch() {
+        let mut memory = Memory::default();
+        // CLI; BNE +0; NOP
+        memory.load(vec![0x58, 0xD0, 0x00, 0xEA]);
+        memory.write_u16(0xFFFE, 0x9000);
+        let mut cpu = CPU::new(memory);
+        cpu.reset();
+        assert_eq!(cpu.tick(), Ok(false));
+        assert_eq!(cpu.tick(), Ok(true));
+
+        // Asserted after the branch's opcode fetch, which is the last time a
+        // taken branch that stays on its page polls
+        assert_eq!(cpu.tick(), Ok(false));
+        cpu.set_irq_line(true);
+        assert_eq!(cpu.tick(), Ok(false));
+        assert_eq!(cpu.tick(), Ok(true));
+        assert_eq!(cpu.pc, 0x8003);
+
+        // So the NOP runs before the IRQ is taken
+        assert_eq!(cpu.tick(), Ok(false));
+        assert_eq!(cpu.tick(), Ok(true));
+        assert_eq!(cpu.pc, 0x8004);
+        assert_eq!(cpu.execute_instruction(), Ok(7));
+        assert_eq!(cpu.pc, 0x9000);
+    }
+
+    #[test]
+    fn test_unofficial_load_and_store() {
+        let mut cpu: CPU = Default::default();
+        cpu.bus.write(0x0010, 0x8F);
+        // LAX $10; SAX $11; NOP $1234,X
+        cpu.load_program(vec![0xA7, 0x10, 0x87, 0x11, 0x1C, 0xFF, 0x12]);
+        cpu.execute_instruction().unwrap();
+        assert_eq!(cpu.register_a, 0x8F);
+        assert_eq!(cpu.register_x, 0x8F);
+        assert_eq!(cpu.get_status_flag(StatusFlag::N), 1);
+        cpu.execute_instruction().unwrap();
+        assert_eq!(cpu.bus.read(0x0011), 0x8F);
+        assert_eq!(cpu.execute_instruction(), Ok(5));
+        assert_eq!(cpu.pc, 0x8007);
+    }
+
+    #[test]
+    fn test_unofficial_read_modify_write() {
+        let mut cpu: CPU = Default::default();
+        cpu.bus.write(0x0010, 0x81);
+        cpu.bus.write(0x0011, 0x05);
+        cpu.bus.write(0x0012, 0x0F);
+        // LDA #$01; SLO $10; DCP $11; SEC; ISB $12
+        cpu.load_program(vec![0xA9, 0x01, 0x07, 0x10, 0xC7, 0x11, 0x38, 0xE7, 0x12]);
+        cpu.execute_instruction().unwrap();
+        assert_eq!(cpu.execute_instruction(), Ok(5));
+        assert_eq!(cpu.bus.read(0x0010), 0x02);
+        assert_eq!(cpu.register_a, 0x03);
+        assert_eq!(cpu.get_status_flag(StatusFlag::C), 1);
+
+        cpu.execute_instruction().unwrap();
+        assert_eq!(cpu.bus.read(0x0011), 0x04);
+        assert_eq!(cpu.get_status_flag(StatusFlag::C), 0);
+
+        cpu.execute_instruction().unwrap();
+        cpu.execute_instruction().unwrap();
+        assert_eq!(cpu.bus.read(0x0012), 0x10);
+        assert_eq!(cpu.register_a, 0xF3);
+        assert_eq!(cpu.get_status_flag(StatusFlag::C), 0);
+    }
+
+    #[test]
+    fn test_jam() {
+        let mut cpu: CPU = Default::default();
+        cpu.load_program(vec![0x02, 0xEA]);
+        assert_eq!(cpu.execute_instruction(), Err(CpuError::Jammed { address: 0x8000 }));
+        assert!(cpu.is_jammed());
+        assert_eq!(cpu.execute_instruction(), Err(CpuError::Jammed { address: 0x8000 }));
+        assert_eq!(cpu.pc, 0x8000);
+        cpu.reset();
+        assert!(!cpu.is_jammed());
+    }
+
+    #[test]
+    fn test_tracer() {
+        let records = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu: CPU = Default::default();
+        let trace_records = Rc::clone(&records);
+        cpu.set_tracer(Box::new(move |record: &TraceRecord, bus: &dyn Bus| {
+            trace_records.borrow_mut().push((record.pc, record.bytes().to_vec(), record.register_a, bus.peek(0x0010)));
+        }));
+        // LDA #$42; STA $10
+        cpu.load_program(vec![0xA9, 0x42, 0x85, 0x10]);
+        cpu.execute_instruction().unwrap();
+        cpu.execute_instruction().unwrap();
+        assert_eq!(*records.borrow(), vec![
+            (0x8000, vec![0xA9, 0x42], 0x00, 0x00),
+            (0x8002, vec![0x85, 0x10], 0x42, 0x00),
+        ]);
+
+        assert!(cpu.clear_tracer().is_some());
+        cpu.pc = 0x8000;
+        cpu.execute_instruction().unwrap();
+        assert_eq!(records.borrow().len(), 2);
+    }
+
+    #[test]
+    fn test_nestest_format() {
+        let lines = Rc::new(RefCell::new(Vec::new()));
+        let mut cpu: CPU = Default::default();
+        let trace_lines = Rc::clone(&lines);
+        cpu.set_tracer(Box::new(move |record: &TraceRecord, bus: &dyn Bus| {
+            trace_lines.borrow_mut().push(format_nestest(record, bus));
+        }));
+        cpu.bus.write(0x0089, 0x00);
+        cpu.bus.write(0x008A, 0x03);
+        cpu.bus.write(0x0301, 0x89);
+        // LDY #$01; LDA ($89),Y; NOP $A9; LSR A
+        cpu.load_program(vec![0xA0, 0x01, 0xB1, 0x89, 0x04, 0xA9, 0x4A]);
+        for _ in 0..4 {
+            cpu.execute_instruction().unwrap();
+        }
+        assert_eq!(*lines.borrow(), vec![
+            "8000  A0 01     LDY #$01                        A:00 X:00 Y:00 P:24 SP:FD PPU:  0, 21 CYC:7",
+            "8002  B1 89     LDA ($89),Y = 0300 @ 0301 = 89  A:00 X:00 Y:01 P:24 SP:FD PPU:  0, 27 CYC:9",
+            "8004  04 A9    *NOP $A9 = 00                    A:89 X:00 Y:01 P:A4 SP:FD PPU:  0, 42 CYC:14",
+            "8006  4A        LSR A                           A:89 X:00 Y:01 P:A4 SP:FD PPU:  0, 51 CYC:17",
+        ]);
+    }
+
+    #[test]
+    fn test_nes_bus_ram_mirroring() {
+        let mut bus = NesBus::default();
+        bus.write(0x0002, 0x42);
+        assert_eq!(bus.read(0x0802), 0x42);
+        assert_eq!(bus.read(0x1002), 0x42);
+        bus.write(0x1FFF, 0x24);
+        assert_eq!(bus.read(0x07FF), 0x24);
+    }
+
+    #[test]
+    fn test_nes_bus_ppu_register_mirroring() {
+        let mut bus = NesBus::default();
+        bus.write(0x3FFE, 0x10);
+        assert_eq!(bus.peek(0x2006), 0x10);
+        assert_eq!(bus.peek(0x200E), 0x10);
+    }
+
+    #[test]
+    fn test_cartridge_header() {
+        // 2 PRG banks, 1 CHR bank, mapper 0x42, vertical mirroring, battery,
+        // PlayChoice-10, 2 banks of PRG RAM, PAL
+        let header = [0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x23, 0x42, 0x02, 0x01, 0, 0, 0, 0, 0, 0];
+        let header = CartridgeHeader::parse(&header);
+        assert_eq!(header.format, HeaderFormat::INes);
+        assert_eq!(header.mapper, 0x42);
+        assert_eq!(header.mirroring, Mirroring::Vertical);
+        assert!(header.battery);
+        assert!(!header.trainer);
+        assert_eq!(header.prg_rom_size, 0x8000);
+        assert_eq!(header.chr_rom_size, 0x2000);
+        assert_eq!(header.prg_ram_size, 0);
+        assert_eq!(header.prg_nvram_size, 0x4000);
+        assert_eq!(header.chr_ram_size, 0);
+        assert_eq!(header.tv_system, TvSystem::Pal);
+        assert_eq!(header.console_type, ConsoleType::PlayChoice10);
+
+        // Four-screen wins over the mirroring bit, and no PRG RAM size means 8 KiB
+        let header = CartridgeHeader::parse(&[0x4E, 0x45, 0x53, 0x1A, 0x01, 0x00, 0x09, 0x00, 0, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(header.mirroring, Mirroring::FourScreen);
+        assert_eq!(header.prg_ram_size, 0x2000);
+        assert_eq!(header.chr_ram_size, 0x2000);
+        assert_eq!(header.console_type, ConsoleType::Nes);
+    }
+
+    #[test]
+    fn test_archaic_ines_header() {
+        // "DiskDude!" smeared over bytes 7-15
+        let mut header = vec![0x4E, 0x45, 0x53, 0x1A, 0x02, 0x01, 0x41];
+        header.extend(b"DiskDude!");
+        let header = CartridgeHeader::parse(&header);
+        assert_eq!(header.format, HeaderFormat::ArchaicINes);
+        assert_eq!(header.mapper, 4);
+        assert_eq!(header.console_type, ConsoleType::Nes);
+        assert_eq!(header.tv_system, TvSystem::Ntsc);
+    }
+
+    #[test]
+    fn test_nes_2_0_header() {
+        let header = [
+            0x4E, 0x45, 0x53, 0x1A,
+            0x02, // PRG ROM LSB
+            0x07, // CHR ROM as exponent-multiplier: 2^1 * (3 * 2 + 1)
+            0x12, // Mapper low nibble 1, battery
+            0x49, // Mapper middle nibble 4, NES 2.0, Vs. System
+            0x35, // Submapper 3, mapper high nibble 5
+            0xF1, // CHR ROM uses exponent notation, PRG ROM MSB 1
+            0x97, // PRG NVRAM 64 << 9, PRG RAM 64 << 7
+            0x07, // No CHR NVRAM, CHR RAM 64 << 7
+            0x03, // Dendy
+            0x51, // Dual System, RP2C03G
+            0x01, // One miscellaneous ROM
+            0x2A, // Expansion device $2A
+        ];
+        let header = CartridgeHeader::parse(&header);
+        assert_eq!(header.format, HeaderFormat::Nes20);
+        assert_eq!(header.mapper, 0x541);
+        assert_eq!(header.submapper, 3);
+        assert!(header.battery);
+        assert_eq!(header.prg_rom_size, 0x102 * 0x4000);
+        assert_eq!(header.chr_rom_size, 14);
+        assert_eq!(header.prg_ram_size, 0x2000);
+        assert_eq!(header.prg_nvram_size, 0x8000);
+        assert_eq!(header.chr_ram_size, 0x2000);
+        assert_eq!(header.chr_nvram_size, 0);
+        assert_eq!(header.tv_system, TvSystem::Dendy);
+        assert_eq!(header.console_type, ConsoleType::VsSystem);
+        assert_eq!(header.vs_system, Some(VsSystemType { ppu: VsPpu::Rp2c03g, hardware: VsHardware::DualSystem }));
+        assert_eq!(header.misc_rom_count, 1);
+        assert_eq!(header.expansion_device, 0x2A);
+    }
+
+    fn nes_file(header: [u8; 12], rom_size: usize) -> Vec<u8> {
+        let mut file = vec![0x4E, 0x45, 0x53, 0x1A];
+        file.extend(header);
+        file.resize(16 + rom_size, 0xEA);
+        file
+    }
+
+    #[test]
+    fn test_cartridge_from_bytes() {
+        let cartridge = Cartidge::from_bytes(&nes_file([1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x6000)).unwrap();
+        assert_eq!(cartridge.header.prg_rom_size, 0x4000);
+        assert_eq!(cartridge.header.chr_rom_size, 0x2000);
+
+        let error = |file: Vec<u8>| Cartidge::from_bytes(&file).unwrap_err();
+        assert_eq!(error(b"NES".to_vec()), CartidgeError::TooShort);
+        assert_eq!(error(b"NES\x1A\x01".to_vec()), CartidgeError::TooShort);
+        assert_eq!(error(vec![0; 0x4010]), CartidgeError::MissingHeaderPrefix);
+        assert_eq!(error(nes_file([0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x2000)), CartidgeError::ZeroPrgBanks);
+        assert_eq!(error(nes_file([1, 0, 0xF0, 0xF0, 0, 0, 0, 0, 0, 0, 0, 0], 0x4000)), CartidgeError::UnsupportedMapper(0xFF));
+        assert_eq!(
+            error(nes_file([2, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x4000)),
+            CartidgeError::TruncatedPrgRom { expected: 0x8000, actual: 0x4000 }
+        );
+        // The trainer comes out of the space left for PRG ROM
+        assert_eq!(
+            error(nes_file([1, 0, 0x04, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x4000)),
+            CartidgeError::TruncatedPrgRom { expected: 0x4000, actual: 0x3E00 }
+        );
+        assert_eq!(
+            error(nes_file([1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x5000)),
+            CartidgeError::TruncatedChrRom { expected: 0x2000, actual: 0x1000 }
+        );
+    }
+
+    fn unif_file(chunks: &[(&[u8], &[u8])]) -> Vec<u8> {
+        let mut file = b"UNIF".to_vec();
+        file.extend(7u32.to_le_bytes());
+        file.resize(32, 0);
+        for (id, data) in chunks {
+            file.extend(*id);
+            file.extend((data.len() as u32).to_le_bytes());
+            file.extend(*data);
+        }
+        file
+    }
+
+    #[test]
+    fn test_unif() {
+        // PRG chunks go together by number, not by where they are in the file
+        let cartridge = Cartidge::from_bytes(&unif_file(&[
+            (b"NAME", b"Test\0"),
+            (b"PRG1", &[0x11; 0x4000]),
+            (b"MAPR", b"NES-UNROM\0"),
+            (b"PRG0", &[0x00; 0x4000]),
+            (b"MIRR", &[1]),
+        ]))
+        .unwrap();
+        assert_eq!(cartridge.header.format, HeaderFormat::Unif);
+        assert_eq!(cartridge.header.mapper, 2);
+        assert_eq!(cartridge.header.prg_rom_size, 0x8000);
+        assert_eq!(cartridge.header.chr_rom_size, 0);
+        assert_eq!(cartridge.header.chr_ram_size, 0x2000);
+        assert_eq!(cartridge.mirroring(), Mirroring::Vertical);
+        assert!(!cartridge.header.battery);
+        assert_eq!(cartridge.cpu_peek(0x8000), Some(0x00));
+        assert_eq!(cartridge.cpu_peek(0xC000), Some(0x11));
+
+        let mut cartridge = Cartidge::from_bytes(&unif_file(&[
+            (b"MAPR", b"NES-SKROM"),
+            (b"PRG0", &[0xEA; 0x8000]),
+            (b"CHR0", &[0x01; 0x2000]),
+            (b"CHR1", &[0x02; 0x2000]),
+            (b"BATR", &[0]),
+            (b"TVCI", &[1]),
+        ]))
+        .unwrap();
+        assert_eq!(cartridge.header.mapper, 1);
+        assert_eq!(cartridge.header.chr_rom_size, 0x4000);
+        assert!(cartridge.header.battery);
+        assert_eq!(cartridge.header.prg_nvram_size, 0x2000);
+        assert_eq!(cartridge.header.tv_system, TvSystem::Pal);
+        assert_eq!(cartridge.ppu_peek(0x0000), 0x01);
+        cartridge.cpu_write(0x6000, 0x42);
+        assert_eq!(cartridge.cpu_peek(0x6000), Some(0x42));
+
+        let error = |file: Vec<u8>| Cartidge::from_bytes(&file).unwrap_err();
+        assert_eq!(error(b"UNIF".to_vec()), CartidgeError::TooShort);
+        assert_eq!(error(unif_file(&[(b"PRG0", &[0; 0x4000])])), CartidgeError::MissingBoardName);
+        assert_eq!(
+            error(unif_file(&[(b"MAPR", b"NES-ZZROM\0"), (b"PRG0", &[0; 0x4000])])),
+            CartidgeError::UnsupportedBoard("NES-ZZROM".to_string())
+        );
+        assert_eq!(error(unif_file(&[(b"MAPR", b"NES-NROM-128\0")])), CartidgeError::ZeroPrgBanks);
+        let mut truncated = unif_file(&[(b"MAPR", b"NES-NROM-128\0"), (b"PRG0", &[0; 0x4000])]);
+        truncated.pop();
+        assert_eq!(error(truncated), CartidgeError::TruncatedUnifChunk);
+    }
+
+    #[test]
+    fn test_nrom() {
+        // NROM-128 with CHR RAM and vertical mirroring. The reset vector at
+        // the end of the bank points at $C123.
+        let mut file = nes_file([1, 0, 0x01, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x4000);
+        file[16] = 0xAA;
+        file[16 + 0x3FFC] = 0x23;
+        file[16 + 0x3FFD] = 0xC1;
+        let mut cartridge = Cartidge::from_bytes(&file).unwrap();
+        assert_eq!(cartridge.mirroring(), Mirroring::Vertical);
+        assert_eq!(cartridge.cpu_peek(0x8000), Some(0xAA));
+        assert_eq!(cartridge.cpu_peek(0xC000), Some(0xAA));
+        cartridge.cpu_write(0x8000, 0x55);
+        assert_eq!(cartridge.cpu_peek(0x8000), Some(0xAA));
+
+        // 8 KiB of PRG RAM at $6000 and CHR RAM, since the header asks for no CHR ROM
+        cartridge.cpu_write(0x6001, 0x12);
+        assert_eq!(cartridge.cpu_peek(0x6001), Some(0x12));
+        assert_eq!(cartridge.cpu_peek(0x5000), None);
+        cartridge.ppu_write(0x1FFF, 0x34);
+        assert_eq!(cartridge.ppu_read(0x1FFF), 0x34);
+
+        let mut cpu = CPU::new(NesBus::new(cartridge));
+        cpu.reset();
+        assert_eq!(cpu.pc, 0xC123);
+    }
+
+    #[test]
+    fn test_mmc1() {
+        // 256 KiB of PRG ROM with each 16 KiB bank filled with its number,
+        // 8 KiB of CHR RAM and 8 KiB of PRG RAM
+        let mut file = nes_file([16, 0, 0x10, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x40000);
+        for (bank, data) in file[16..].chunks_mut(0x4000).enumerate() {
+            data.fill(bank as u8);
+        }
+        let mut cartridge = Cartidge::from_bytes(&file).unwrap();
+        let write_register = |cartridge: &mut Cartidge, addr: u16, value: u8| {
+            for bit in 0..5 {
+                cartridge.cpu_write(addr, value >> bit & 1);
+                cartridge.cpu_clock();
+                cartridge.cpu_clock();
+            }
+        };
+
+        // Powers up with the last bank fixed at $C000
+        assert_eq!(cartridge.cpu_peek(0x8000), Some(0));
+        assert_eq!(cartridge.cpu_peek(0xC000), Some(15));
+        write_register(&mut cartridge, 0xE000, 3);
+        assert_eq!(cartridge.cpu_peek(0x8000), Some(3));
+        assert_eq!(cartridge.cpu_peek(0xFFFF), Some(15));
+
+        // First bank fixed at $8000
+        write_register(&mut cartridge, 0x8000, 0b0_1010);
+        assert_eq!(cartridge.mirroring(), Mirroring::Vertical);
+        assert_eq!(cartridge.cpu_peek(0x8000), Some(0));
+        assert_eq!(cartridge.cpu_peek(0xC000), Some(3));
+
+        // 32 KiB mode ignores the low bit of the bank number
+        write_register(&mut cartridge, 0x8000, 0b0_0001);
+        assert_eq!(cartridge.mirroring(), Mirroring::SingleScreenUpper);
+        assert_eq!(cartridge.cpu_peek(0x8000), Some(2));
+        assert_eq!(cartridge.cpu_peek(0xC000), Some(3));
+
+        // Writing with bit 7 set resets the shift register and the PRG mode
+        cartridge.cpu_write(0xE000, 1);
+        cartridge.cpu_clock();
+        cartridge.cpu_clock();
+        cartridge.cpu_write(0x8000, 0x80);
+        cartridge.cpu_clock();
+        cartridge.cpu_clock();
+        assert_eq!(cartridge.cpu_peek(0x8000), Some(3));
+        assert_eq!(cartridge.cpu_peek(0xC000), Some(15));
+        write_register(&mut cartridge, 0xE000, 4);
+        assert_eq!(cartridge.cpu_peek(0x8000), Some(4));
+
+        // A write on the cycle right after another one is ignored, like the
+        // second write of a read-modify-write instruction
+        for _ in 0..5 {
+            cartridge.cpu_write(0xE000, 1);
+            cartridge.cpu_clock();
+            cartridge.cpu_write(0xE000, 0);
+            cartridge.cpu_clock();
+            cartridge.cpu_clock();
+        }
+        assert_eq!(cartridge.cpu_peek(0x8000), Some(15));
+
+        // PRG RAM is disabled by bit 4 of the PRG bank, which the ignored
+        // writes just set
+        assert_eq!(cartridge.cpu_peek(0x6000), None);
+        write_register(&mut cartridge, 0xE000, 0);
+        cartridge.cpu_write(0x6000, 0x42);
+        assert_eq!(cartridge.cpu_peek(0x6000), Some(0x42));
+        write_register(&mut cartridge, 0xE000, 0b1_0000);
+        assert_eq!(cartridge.cpu_peek(0x6000), None);
+        cartridge.cpu_write(0x6000, 0x24);
+        write_register(&mut cartridge, 0xE000, 0);
+        assert_eq!(cartridge.cpu_peek(0x6000), Some(0x42));
+
+        // SNROM also disables it through the CHR bank
+        write_register(&mut cartridge, 0xA000, 0b1_0000);
+        assert_eq!(cartridge.cpu_peek(0x6000), None);
+        write_register(&mut cartridge, 0xA000, 0);
+
+        // Two 4 KiB CHR banks, mirrored over the 8 KiB of CHR RAM
+        cartridge.ppu_write(0x0000, 0x11);
+        cartridge.ppu_write(0x1000, 0x22);
+        write_register(&mut cartridge, 0x8000, 0b1_0000);
+        write_register(&mut cartridge, 0xA000, 1);
+        write_register(&mut cartridge, 0xC000, 2);
+        assert_eq!(cartridge.ppu_read(0x0000), 0x22);
+        assert_eq!(cartridge.ppu_read(0x1000), 0x11);
+    }
+
+    #[test]
+    fn test_discrete_mappers() {
+        // Fills each bank of PRG ROM with its number, except for a last byte
+        // of $FF that can take any write without bus conflicts, and each bank
+        // of CHR ROM with its number
+        let cartridge = |header: [u8; 12], prg_bank_size: usize| {
+            let prg_rom_size = header[0] as usize * 0x4000;
+            let mut file = nes_file(header, prg_rom_size + header[1] as usize * 0x2000);
+            let (prg_rom, chr_rom) = file[16..].split_at_mut(prg_rom_size);
+            for (bank, data) in prg_rom.chunks_mut(prg_bank_size).enumerate() {
+                data.fill(bank as u8);
+                data[prg_bank_size - 1] = 0xFF;
+            }
+            for (bank, data) in chr_rom.chunks_mut(0x2000).enumerate() {
+                data.fill(bank as u8);
+            }
+            Cartidge::from_bytes(&file).unwrap()
+        };
+
+        // UxROM. The bank number written gets ANDed with the ROM byte at the
+        // same address, unless an NES 2.0 header rules bus conflicts out.
+        let mut uxrom = cartridge([8, 0, 0x20, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x4000);
+        assert_eq!(uxrom.cpu_peek(0x8000), Some(0));
+        assert_eq!(uxrom.cpu_peek(0xC000), Some(7));
+        uxrom.cpu_write(0xC000, 5);
+        assert_eq!(uxrom.cpu_peek(0x8000), Some(5));
+        uxrom.cpu_write(0xC000, 2);
+        assert_eq!(uxrom.cpu_peek(0x8000), Some(2));
+        uxrom.cpu_write(0xFFFF, 6);
+        assert_eq!(uxrom.cpu_peek(0x8000), Some(6));
+        assert_eq!(uxrom.cpu_peek(0xC000), Some(7));
+        let mut uxrom = cartridge([8, 0, 0x20, 0x08, 0x10, 0, 0, 0, 0, 0, 0, 0], 0x4000);
+        uxrom.cpu_write(0x8000, 5);
+        assert_eq!(uxrom.cpu_peek(0x8000), Some(5));
+        // 8 KiB of PRG ROM, which NES 2.0 sizes allow, fills both halves of
+        // the fixed bank
+        let mut file = nes_file([0x34, 0, 0x20, 0x08, 0, 0x0F, 0, 0, 0, 0, 0, 0], 0x2000);
+        file[16..].iter_mut().enumerate().for_each(|(i, byte)| *byte = (i >> 8) as u8);
+        let uxrom = Cartidge::from_bytes(&file).unwrap();
+        assert_eq!(uxrom.header.prg_rom_size, 0x2000);
+        for addr in (0xC000..=0xFFFF).step_by(0x100) {
+            assert_eq!(uxrom.cpu_peek(addr), Some(((addr >> 8) & 0x1F) as u8));
+        }
+
+        // CNROM
+        let mut cnrom = cartridge([1, 4, 0x31, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x4000);
+        assert_eq!(cnrom.mirroring(), Mirroring::Vertical);
+        cnrom.cpu_write(0xFFFF, 2);
+        assert_eq!(cnrom.ppu_read(0x0000), 2);
+        assert_eq!(cnrom.ppu_read(0x1FFF), 2);
+        cnrom.cpu_write(0x8000, 3);
+        assert_eq!(cnrom.ppu_read(0x0000), 0);
+
+        // AxROM switches 32 KiB at a time and has no bus conflicts by default
+        let mut axrom = cartridge([8, 0, 0x70, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x8000);
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenLower);
+        axrom.cpu_write(0x8000, 0x12);
+        assert_eq!(axrom.cpu_peek(0x8000), Some(2));
+        assert_eq!(axrom.cpu_peek(0xC000), Some(2));
+        assert_eq!(axrom.mirroring(), Mirroring::SingleScreenUpper);
+        axrom.ppu_write(0x0123, 0x45);
+        assert_eq!(axrom.ppu_read(0x0123), 0x45);
+
+        // GxROM
+        let mut gxrom = cartridge([8, 4, 0x21, 0x40, 0, 0, 0, 0, 0, 0, 0, 0], 0x8000);
+        assert_eq!(gxrom.mirroring(), Mirroring::Vertical);
+        gxrom.cpu_write(0xFFFF, 0x31);
+        assert_eq!(gxrom.cpu_peek(0x8000), Some(3));
+        assert_eq!(gxrom.ppu_read(0x1000), 1);
+    }
+
+    #[test]
+    fn test_mmc3() {
+        // 128 KiB of PRG ROM and CHR ROM, with each 8 KiB PRG bank and each
+        // 1 KiB CHR bank filled with its number
+        let mut file = nes_file([8, 16, 0x40, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x40000);
+        let (prg_rom, chr_rom) = file[16..].split_at_mut(0x20000);
+        for (bank, data) in prg_rom.chunks_mut(0x2000).enumerate() {
+            data.fill(bank as u8);
+        }
+        for (bank, data) in chr_rom.chunks_mut(0x0400).enumerate() {
+            data.fill(bank as u8);
+        }
+        let mut cartridge = Cartidge::from_bytes(&file).unwrap();
+
+        for (register, bank) in [3, 9, 40, 41, 42, 43, 5, 6].into_iter().enumerate() {
+            cartridge.cpu_write(0x8000, register as u8);
+            cartridge.cpu_write(0x8001, bank);
+        }
+        let prg_banks = |cartridge: &Cartidge| [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| cartridge.cpu_peek(addr).unwrap());
+        let chr_banks = |cartridge: &mut Cartidge| [0x0000, 0x0400, 0x0800, 0x0C00, 0x1000, 0x1400, 0x1800, 0x1C00].map(|addr| cartridge.ppu_read(addr));
+        assert_eq!(prg_banks(&cartridge), [5, 6, 14, 15]);
+        assert_eq!(chr_banks(&mut cartridge), [2, 3, 8, 9, 40, 41, 42, 43]);
+        cartridge.cpu_write(0x8000, 0xC0);
+        assert_eq!(prg_banks(&cartridge), [14, 6, 5, 15]);
+        assert_eq!(chr_banks(&mut cartridge), [40, 41, 42, 43, 2, 3, 8, 9]);
+
+        cartridge.cpu_write(0xA000, 1);
+        assert_eq!(cartridge.mirroring(), Mirroring::Horizontal);
+        cartridge.cpu_write(0xA000, 0);
+        assert_eq!(cartridge.mirroring(), Mirroring::Vertical);
+
+        // PRG RAM can be write protected, or disabled altogether
+        cartridge.cpu_write(0x6000, 0x12);
+        cartridge.cpu_write(0xA001, 0xC0);
+        cartridge.cpu_write(0x6000, 0x34);
+        assert_eq!(cartridge.cpu_peek(0x6000), Some(0x12));
+        cartridge.cpu_write(0xA001, 0x00);
+        assert_eq!(cartridge.cpu_peek(0x6000), None);
+    }
+
+    #[test]
+    fn test_mmc3_irq() {
+        // A rise of A12 only clocks the counter once it has been low for a
+        // few CPU cycles, like it is for most of every scanline
+        fn scanline(cartridge: &mut Cartidge) {
+            cartridge.ppu_read(0x0000);
+            for _ in 0..4 {
+                cartridge.cpu_clock();
+            }
+            cartridge.ppu_read(0x1000);
+            cartridge.ppu_read(0x0000);
+            cartridge.ppu_read(0x1000);
+        }
+        // NES 2.0 submapper 4 is the MMC3A
+        let mmc3 = |submapper: u8| Cartidge::from_bytes(&nes_file([2, 1, 0x40, 0x08, submapper << 4, 0, 0, 0, 0, 0, 0, 0], 0xA000)).unwrap();
+
+        let mut cartridge = mmc3(0);
+        cartridge.cpu_write(0xC000, 2);
+        cartridge.cpu_write(0xC001, 0);
+        cartridge.cpu_write(0xE001, 0);
+        scanline(&mut cartridge);
+        scanline(&mut cartridge);
+        assert!(!cartridge.irq());
+        scanline(&mut cartridge);
+        assert!(cartridge.irq());
+        cartridge.cpu_write(0xE000, 0);
+        assert!(!cartridge.irq());
+        cartridge.cpu_write(0xE001, 0);
+
+        // With a latch of 0, the Sharp MMC3 keeps firing on every scanline
+        // while the NEC one only fires once after a reload
+        for (submapper, fires) in [(0, [true, true]), (4, [true, false])] {
+            let mut cartridge = mmc3(submapper);
+            cartridge.cpu_write(0xC001, 0);
+            cartridge.cpu_write(0xE001, 0);
+            for fires in fires {
+                scanline(&mut cartridge);
+                assert_eq!(cartridge.irq(), fires);
+                cartridge.cpu_write(0xE000, 0);
+                cartridge.cpu_write(0xE001, 0);
+            }
+        }
+
+        // The mapper drives the CPU's IRQ line through the bus. Without a
+        // program, the vectors point into the NOPs filling PRG ROM.
+        for _ in 0..3 {
+            scanline(&mut cartridge);
+        }
+        assert!(cartridge.irq());
+        let mut cpu = CPU::new(NesBus::new(cartridge));
+        cpu.reset();
+        cpu.clear_status_flag(StatusFlag::I);
+        cpu.execute_instruction().unwrap();
+        assert_eq!(cpu.execute_instruction(), Ok(7));
+        assert_eq!(cpu.pc, 0xEAEA);
+        assert_eq!(cpu.get_status_flag(StatusFlag::I), 1);
+    }
+
+    #[test]
+    fn test_mmc2_mmc4_latches() {
+        // 128 KiB of PRG ROM and 64 KiB of CHR ROM with each 4 KiB CHR bank
+        // filled with its number
+        let cartridge = |mapper: u8| {
+            let mut file = nes_file([8, 8, mapper << 4, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x30000);
+            for (bank, data) in file[16 + 0x20000..].chunks_mut(0x1000).enumerate() {
+                data.fill(bank as u8);
+            }
+            let mut cartridge = Cartidge::from_bytes(&file).unwrap();
+            for (addr, bank) in [(0xB000, 1), (0xC000, 2), (0xD000, 3), (0xE000, 4)] {
+                cartridge.cpu_write(addr, bank);
+            }
+            cartridge
+        };
+
+        for mapper in [9, 10] {
+            let mut cartridge = cartridge(mapper);
+            assert_eq!(cartridge.ppu_read(0x0000), 2);
+            assert_eq!(cartridge.ppu_read(0x1000), 4);
+            // The fetch that flips the latch still comes from the old bank
+            assert_eq!(cartridge.ppu_read(0x0FD8), 2);
+            assert_eq!(cartridge.ppu_read(0x0000), 1);
+            assert_eq!(cartridge.ppu_read(0x1FDB), 4);
+            assert_eq!(cartridge.ppu_read(0x1000), 3);
+            cartridge.ppu_read(0x1FE8);
+            assert_eq!(cartridge.ppu_read(0x1000), 4);
+            assert_eq!(cartridge.ppu_read(0x0000), 1);
+            // Fetching the low bit plane of the tile does nothing
+            cartridge.ppu_read(0x1FE0);
+            cartridge.ppu_read(0x0FE0);
+            assert_eq!(cartridge.ppu_read(0x0000), 1);
+            assert_eq!(cartridge.ppu_peek(0x1000), 4);
+        }
+
+        // Only the MMC4 reacts to the whole plane in the first pattern table
+        let mut mmc2 = cartridge(9);
+        let mut mmc4 = cartridge(10);
+        mmc2.ppu_read(0x0FD9);
+        mmc4.ppu_read(0x0FD9);
+        assert_eq!(mmc2.ppu_read(0x0000), 2);
+        assert_eq!(mmc4.ppu_read(0x0000), 1);
+
+        // The MMC2 switches 8 KiB of PRG ROM and the MMC4 16 KiB. Each 8 KiB
+        // bank is filled with its number.
+        let mut file = nes_file([8, 0, 0x90, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x20000);
+        for (bank, data) in file[16..].chunks_mut(0x2000).enumerate() {
+            data.fill(bank as u8);
+        }
+        let mut mmc2 = Cartidge::from_bytes(&file).unwrap();
+        mmc2.cpu_write(0xA000, 3);
+        assert_eq!([0x8000, 0xA000, 0xC000, 0xE000].map(|addr| mmc2.cpu_peek(addr).unwrap()), [3, 13, 14, 15]);
+        file[6] = 0xA0;
+        let mut mmc4 = Cartidge::from_bytes(&file).unwrap();
+        mmc4.cpu_write(0xA000, 3);
+        assert_eq!([0x8000, 0xA000, 0xC000, 0xE000].map(|addr| mmc4.cpu_peek(addr).unwrap()), [6, 7, 14, 15]);
+        mmc4.cpu_write(0xF000, 1);
+        assert_eq!(mmc4.mirroring(), Mirroring::Horizontal);
+    }
+
+    #[test]
+    fn test_vrc_wiring() {
+        // 128 KiB of PRG ROM and CHR ROM, with each 8 KiB PRG bank and each
+        // 1 KiB CHR bank filled with its number
+        let cartridge = |mapper: u8, submapper: u8| {
+            let header = [8, 16, mapper << 4, 0x08 | (mapper & 0xF0), submapper << 4, 0, 0, 0, 0, 0, 0, 0];
+            let mut file = nes_file(header, 0x40000);
+            let (prg_rom, chr_rom) = file[16..].split_at_mut(0x20000);
+            for (bank, data) in prg_rom.chunks_mut(0x2000).enumerate() {
+                data.fill(bank as u8);
+            }
+            for (bank, data) in chr_rom.chunks_mut(0x0400).enumerate() {
+                data.fill(bank as u8);
+            }
+            Cartidge::from_bytes(&file).unwrap()
+        };
+
+        // Each variant gets the low and high bits of CHR bank 1 at the chip's
+        // $B002 and $B003, through whichever lines it has for A0 and A1
+        for (mapper, submapper, low, high) in [
+            (21, 1, 0xB004, 0xB006),
+            (21, 2, 0xB080, 0xB0C0),
+            (21, 0, 0xB080, 0xB006),
+            (23, 1, 0xB002, 0xB003),
+            (23, 2, 0xB008, 0xB00C),
+            (25, 1, 0xB001, 0xB003),
+            (25, 2, 0xB004, 0xB00C),
+            (25, 0, 0xB001, 0xB009),
+        ] {
+            let mut vrc4 = cartridge(mapper, submapper);
+            vrc4.cpu_write(low, 0x0D);
+            vrc4.cpu_write(high, 0x01);
+            assert_eq!(vrc4.ppu_read(0x0400), 0x1D, "mapper {mapper} submapper {submapper}");
+        }
+
+        // VRC2a drops the low bit of CHR bank numbers
+        let mut vrc2 = cartridge(22, 0);
+        vrc2.cpu_write(0xB001, 0x0D);
+        assert_eq!(vrc2.ppu_read(0x0400), 0x06);
+
+        // VRC4 PRG banking, in both modes
+        let mut vrc4 = cartridge(21, 1);
+        vrc4.cpu_write(0x8000, 3);
+        vrc4.cpu_write(0xA000, 4);
+        let prg_banks = |cartridge: &Cartidge| [0x8000, 0xA000, 0xC000, 0xE000].map(|addr| cartridge.cpu_peek(addr).unwrap());
+        assert_eq!(prg_banks(&vrc4), [3, 4, 14, 15]);
+        vrc4.cpu_write(0x9004, 0b10);
+        assert_eq!(prg_banks(&vrc4), [14, 4, 3, 15]);
+        vrc4.cpu_write(0x9000, 3);
+        assert_eq!(vrc4.mirroring(), Mirroring::SingleScreenUpper);
+
+        // VRC6b swaps A0 and A1, and VRC7a selects its registers with A4
+        let mut vrc6 = cartridge(26, 0);
+        vrc6.cpu_write(0x8000, 2);
+        vrc6.cpu_write(0xC000, 7);
+        vrc6.cpu_write(0xD002, 9);
+        assert_eq!(prg_banks(&vrc6), [4, 5, 7, 15]);
+        assert_eq!(vrc6.ppu_read(0x0400), 9);
+        vrc6.cpu_write(0xB003, 0x84);
+        assert_eq!(vrc6.mirroring(), Mirroring::Horizontal);
+        let mut vrc7 = cartridge(85, 2);
+        vrc7.cpu_write(0x8010, 6);
+        vrc7.cpu_write(0xA010, 3);
+        assert_eq!(prg_banks(&vrc7), [0, 6, 0, 15]);
+        assert_eq!(vrc7.ppu_read(0x0400), 3);
+        // FM audio isn't emulated, the VRC7 stays silent whatever gets written
+        vrc7.cpu_write(0x9010, 0x30);
+        vrc7.cpu_write(0x9030, 0x0F);
+        vrc7.cpu_clock();
+        assert_eq!(vrc7.expansion_audio(), 0.0);
+    }
+
+    #[test]
+    fn test_vrc_irq() {
+        // VRC4a, with the chip's A0 and A1 on the CPU's A1 and A2
+        let mut vrc4 = Cartidge::from_bytes(&nes_file([8, 0, 0x50, 0x18, 0x10, 0, 0, 0, 0, 0, 0, 0], 0x20000)).unwrap();
+        let clocks_until_irq = |cartridge: &mut Cartidge| {
+            let mut clocks = 0;
+            while !cartridge.irq() {
+                cartridge.cpu_clock();
+                clocks += 1;
+            }
+            clocks
+        };
+
+        // In cycle mode the counter goes up every CPU cycle from the latch,
+        // and fires on the clock after it reaches $FF
+        vrc4.cpu_write(0xF000, 0x0C);
+        vrc4.cpu_write(0xF002, 0x0F);
+        vrc4.cpu_write(0xF004, 0b111);
+        assert_eq!(clocks_until_irq(&mut vrc4), 4);
+        vrc4.cpu_write(0xF006, 0);
+        assert!(!vrc4.irq());
+        // The counter starts over from the latch, and acknowledging keeps it
+        // enabled because of the enable after acknowledge bit
+        assert_eq!(clocks_until_irq(&mut vrc4), 4);
+
+        // In scanline mode the prescaler clocks the counter every 113 or 114
+        // CPU cycles. Only the low bits of the latch change, making it $FD.
+        vrc4.cpu_write(0xF000, 0x0D);
+        vrc4.cpu_write(0xF004, 0b010);
+        assert_eq!(clocks_until_irq(&mut vrc4), 341);
+        // Acknowledging without the enable after acknowledge bit disables
+        // the counter
+        vrc4.cpu_write(0xF006, 0);
+        for _ in 0..1000 {
+            vrc4.cpu_clock();
+        }
+        assert!(!vrc4.irq());
+    }
+
+    #[test]
+    fn test_vrc6_audio() {
+        let mut vrc6 = Cartidge::from_bytes(&nes_file([8, 0, 0x80, 0x10, 0, 0, 0, 0, 0, 0, 0, 0], 0x20000)).unwrap();
+        assert_eq!(vrc6.expansion_audio(), 0.0);
+
+        // Pulse 1 with the duty cycle ignored plays at its volume constantly
+        vrc6.cpu_write(0x9000, 0x8F);
+        vrc6.cpu_write(0x9002, 0x80);
+        assert_eq!(vrc6.expansion_audio(), 15.0 / 61.0);
+
+        // Pulse 2 at volume 6 with a duty of 2/16 and a period of 2 cycles
+        vrc6.cpu_write(0x9002, 0x00);
+        vrc6.cpu_write(0xA000, 0x16);
+        vrc6.cpu_write(0xA001, 0x01);
+        vrc6.cpu_write(0xA002, 0x80);
+        let mut samples = Vec::new();
+        for _ in 0..32 {
+            samples.push(vrc6.expansion_audio());
+            vrc6.cpu_clock();
+        }
+        let high = samples.iter().filter(|&&sample| sample == 6.0 / 61.0).count();
+        assert_eq!(high, 4);
+        assert_eq!(high + samples.iter().filter(|&&sample| sample == 0.0).count(), 32);
+
+        // The sawtooth adds its rate to the accumulator on every other step of
+        // its divider, and outputs the top 5 bits
+        vrc6.cpu_write(0xA002, 0x00);
+        vrc6.cpu_write(0xB000, 0x20);
+        vrc6.cpu_write(0xB002, 0x80);
+        let mut peak: f32 = 0.0;
+        for _ in 0..14 {
+            vrc6.cpu_clock();
+            peak = peak.max(vrc6.expansion_audio());
+        }
+        assert_eq!(peak, 24.0 / 61.0);
+
+        // Halting stops every channel where it is
+        vrc6.cpu_write(0x9003, 0x01);
+        let level = vrc6.expansion_audio();
+        vrc6.cpu_clock();
+        vrc6.cpu_clock();
+        assert_eq!(vrc6.expansion_audio(), level);
+    }
+
+    #[test]
+    fn test_battery_save() {
+        let dir = std::env::temp_dir().join(format!("serun_test_battery_save_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let rom_path = dir.join("game.nes");
+        let save_path = dir.join("game.sav");
+        // NROM with battery backed PRG RAM
+        std::fs::write(&rom_path, nes_file([1, 1, 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x6000)).unwrap();
+
+        // Nothing gets written until the game writes to PRG RAM
+        let mut cartridge = Cartidge::from_path(rom_path.clone()).unwrap();
+        assert_eq!(cartridge.save_path(), Some(save_path.as_path()));
+        cartridge.flush_save().unwrap();
+        assert!(!save_path.exists());
+
+        // Saved periodically while running, and on drop
+        cartridge.cpu_write(0x6000, 0x12);
+        for _ in 0..5 * 1_789_773 {
+            cartridge.cpu_clock();
+        }
+        assert_eq!(std::fs::read(&save_path).unwrap()[0], 0x12);
+        cartridge.cpu_write(0x7FFF, 0x34);
+        drop(cartridge);
+        let save = std::fs::read(&save_path).unwrap();
+        assert_eq!(save.len(), 0x2000);
+        assert_eq!((save[0], save[0x1FFF]), (0x12, 0x34));
+
+        let cartridge = Cartidge::from_path(rom_path.clone()).unwrap();
+        assert_eq!(cartridge.cpu_peek(0x6000), Some(0x12));
+        assert_eq!(cartridge.cpu_peek(0x7FFF), Some(0x34));
+        drop(cartridge);
+
+        // Without a battery, PRG RAM starts out empty every time
+        std::fs::write(&rom_path, nes_file([1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x6000)).unwrap();
+        let cartridge = Cartidge::from_path(rom_path).unwrap();
+        assert_eq!(cartridge.save_path(), None);
+        assert_eq!(cartridge.cpu_peek(0x6000), Some(0));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_rom_hashes() {
+        let mut crc32 = Crc32::default();
+        crc32.update(b"1234");
+        crc32.update(b"56789");
+        assert_eq!(crc32.finish(), 0xCBF4_3926);
+
+        let sha1 = |data: &[u8]| {
+            let mut sha1 = Sha1::default();
+            sha1.update(data);
+            sha1.finish().iter().map(|byte| format!("{byte:02x}")).collect::<String>()
+        };
+        assert_eq!(sha1(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(sha1(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        // Padding spills over into a second block
+        assert_eq!(sha1(&[b'a'; 56]), "c2db330f6083854c99d4b5bfb6e8f29f201be699");
+        assert_eq!(sha1(&[b'a'; 1000]), "291e9a6c66994949b57ba5e650361e98fc36b1ba");
+    }
+
+    #[test]
+    fn test_game_database() {
+        let nestest = std::fs::read(concat!(env!("CARGO_MANIFEST_DIR"), "/../debug/tests/nestest.nes")).unwrap();
+        let cartridge = Cartidge::from_bytes(&nestest).unwrap();
+        assert_eq!(cartridge.crc32, 0x158B_0388);
+        // Test ROMs aren't in the real database, only in the one for tests
+        let entry = database::lookup_in(&database::TEST_GAMES, cartridge.crc32, &cartridge.sha1).unwrap();
+        assert_eq!(entry.name, "nestest");
+        assert_eq!(database::correct_header(entry, &mut CartridgeHeader::parse(&nestest)), []);
+
+        // A header claiming an unsupported mapper, vertical mirroring and a
+        // PAL console gets put right
+        let mut bad_header = nestest.clone();
+        bad_header[6] = 0xF1;
+        bad_header[9] = 0x01;
+        let mut header = CartridgeHeader::parse(&bad_header);
+        let corrections = database::correct_header(entry, &mut header);
+        assert_eq!(header.mapper, 0);
+        assert_eq!(header.mirroring, Mirroring::Horizontal);
+        assert_eq!(header.tv_system, TvSystem::Ntsc);
+        assert_eq!(corrections, [
+            HeaderCorrection::Mapper { header: 15, database: 0 },
+            HeaderCorrection::Mirroring { header: Mirroring::Vertical, database: Mirroring::Horizontal },
+            HeaderCorrection::TvSystem { header: TvSystem::Pal, database: TvSystem::Ntsc },
+        ]);
+        assert_eq!(corrections[0].to_string(), "Mapper 15 corrected to 0");
+
+        // Unknown dumps are taken at their header's word
+        let mut unknown = nestest;
+        unknown[16] ^= 0xFF;
+        let cartridge = Cartidge::from_bytes(&unknown).unwrap();
+        assert_eq!(cartridge.database_entry, None);
+        assert_eq!(cartridge.header_corrections, []);
+
+        // Mirroring the mapper controls is left however the header has it
+        let entry = GameEntry {
+            name: "MMC1",
+            sha1: [0; 20],
+            mapper: 1,
+            submapper: 0,
+            mirroring: None,
+            prg_ram_size: 0x2000,
+            prg_nvram_size: 0,
+            chr_ram_size: 0,
+            chr_nvram_size: 0,
+            tv_system: TvSystem::Ntsc,
+        };
+        let mut header = CartridgeHeader::parse(&nes_file([1, 1, 0x11, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0));
+        assert_eq!(database::correct_header(&entry, &mut header), []);
+        assert_eq!(header.mirroring, Mirroring::Vertical);
+    }
+
+    // Encodes a number the way UPS and BPS patches do
+    fn patch_number(mut number: usize) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        loop {
+            let bits = (number & 0x7F) as u8;
+            number >>= 7;
+            if number == 0 {
+                bytes.push(0x80 | bits);
+                return bytes;
+            }
+            bytes.push(bits);
+            number -= 1;
+        }
+    }
+
+    // Finishes a UPS or BPS patch with its checksums
+    fn with_patch_checksums(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        let crc32 = |data: &[u8]| {
+            let mut crc32 = Crc32::default();
+            crc32.update(data);
+            crc32.finish()
+        };
+        patch.extend(crc32(source).to_le_bytes());
+        patch.extend(crc32(target).to_le_bytes());
+        patch.extend(crc32(&patch).to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn test_patches() {
+        let rom = nes_file([1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0], 0x6000);
+        let mut target = rom.clone();
+        target[6] = 0x01;
+        target[16..20].copy_from_slice(&[1, 2, 3, 4]);
+        target[0x2000..0x2100].fill(0x55);
+        let check = |cartridge: Cartidge| {
+            assert_eq!(cartridge.mirroring(), Mirroring::Vertical);
+            assert_eq!([0x8000, 0x8003, 0x9FF0, 0xA0EF, 0xA0F0].map(|addr| cartridge.cpu_peek(addr).unwrap()), [1, 4, 0x55, 0x55, 0xEA]);
+        };
+
+        // IPS, with a run of repeated bytes
+        let mut ips = b"PATCH".to_vec();
+        ips.extend([0, 0, 6, 0, 1, 0x01]);
+        ips.extend([0, 0, 16, 0, 4, 1, 2, 3, 4]);
+        ips.extend([0, 0x20, 0, 0, 0, 1, 0, 0x55]);
+        ips.extend(b"EOF");
+        check(Cartidge::from_bytes_with_patch(&rom, &ips).unwrap());
+        assert_eq!(Cartidge::from_bytes_with_patch(&rom, &ips[..ips.len() - 2]).unwrap_err(), CartidgeError::Patch(PatchError::Truncated));
+
+        // UPS, XORing in the differences
+        let mut ups = b"UPS1".to_vec();
+        ups.extend(patch_number(rom.len()));
+        ups.extend(patch_number(target.len()));
+        ups.extend(patch_number(6));
+        ups.extend([0x01, 0]);
+        ups.extend(patch_number(16 - 8));
+        ups.extend([1 ^ 0xEA, 2 ^ 0xEA, 3 ^ 0xEA, 4 ^ 0xEA, 0]);
+        ups.extend(patch_number(0x2000 - 21));
+        ups.extend([0x55 ^ 0xEA; 0x100]);
+        ups.push(0);
+        let ups = with_patch_checksums(ups, &rom, &target);
+        check(Cartidge::from_bytes_with_patch(&rom, &ups).unwrap());
+
+        // BPS, copying from the source and from the target built so far
+        let mut bps = b"BPS1".to_vec();
+        bps.extend(patch_number(rom.len()));
+        bps.extend(patch_number(target.len()));
+        bps.extend(patch_number(0));
+        let command = |action: usize, len: usize| patch_number(((len - 1) << 2) | action);
+        bps.extend(command(0, 6));
+        bps.extend(command(1, 1));
+        bps.push(0x01);
+        bps.extend(command(0, 9));
+        bps.extend(command(1, 4));
+        bps.extend([1, 2, 3, 4]);
+        // Source copy from 20, the offset relative to where the last one (at 0)
+        // left off
+        bps.extend(command(2, 0x2000 - 20));
+        bps.extend(patch_number(20 << 1));
+        bps.extend(command(1, 1));
+        bps.push(0x55);
+        // Target copy repeating the byte just written
+        bps.extend(command(3, 0xFF));
+        bps.extend(patch_number(0x2000 << 1));
+        bps.extend(command(2, target.len() - 0x2100));
+        bps.extend(patch_number((0x2100 - 0x2000) << 1));
+        let bps = with_patch_checksums(bps, &rom, &target);
+        check(Cartidge::from_bytes_with_patch(&rom, &bps).unwrap());
+
+        // The checksums catch patches for other ROMs and corrupt patches
+        let mut other_rom = rom.clone();
+        other_rom[0x100] = 0;
+        assert!(matches!(
+            Cartidge::from_bytes_with_patch(&other_rom, &bps).unwrap_err(),
+            CartidgeError::Patch(PatchError::SourceChecksum { .. })
+        ));
+        let mut corrupt = ups.clone();
+        corrupt[10] ^= 1;
+        assert!(matches!(
+            Cartidge::from_bytes_with_patch(&rom, &corrupt).unwrap_err(),
+            CartidgeError::Patch(PatchError::PatchChecksum { .. })
+        ));
+        assert_eq!(Cartidge::from_bytes_with_patch(&rom, b"nope").unwrap_err(), CartidgeError::Patch(PatchError::UnknownFormat));
+
+        // Mangled patches are errors, never panics. Random UPS and BPS bodies
+        // also get valid checksums so that they make it past them, and are
+        // applied to a small source so that there's no time wasted on
+        // checksumming ROMs.
+        let mut state: u32 = 0x8765_4321;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        for patch in [&ips, &ups, &bps] {
+            for len in 0..patch.len() {
+                let _ = patch::apply(&patch[..len], &rom);
+            }
+        }
+        let source = [0xEA; 64];
+        for _ in 0..500 {
+            let mut body: Vec<u8> = (0..next() % 64).map(|_| next() as u8).collect();
+            for magic in [&b"PATCH"[..], b"UPS1", b"BPS1"] {
+                let mut patch = magic.to_vec();
+                patch.append(&mut body.clone());
+                // IPS offsets go up to 16 MiB, growing the file that far takes
+                // a while in debug builds
+                if magic == b"PATCH" && patch.len() > 5 {
+                    patch[5] = 0;
+                }
+                let _ = patch::apply(&patch, &source);
+                if magic != b"PATCH" {
+                    let _ = patch::apply(&with_patch_checksums(patch, &source, &[]), &source);
+                }
+            }
+            body.clear();
+        }
+        // A UPS hunk ending at the very end of the address space
+        let mut overflowing = b"UPS1".to_vec();
+        overflowing.extend(patch_number(0));
+        overflowing.extend(patch_number(0));
+        overflowing.extend(patch_number(0));
+        overflowing.push(0);
+        overflowing.extend(patch_number(usize::MAX - 1));
+        overflowing.push(0);
+        let overflowing = with_patch_checksums(overflowing, &[], &[]);
+        assert_eq!(patch::apply(&overflowing, &[]), Err(PatchError::OutOfBounds));
+
+        // A patch with the same name as the ROM gets picked up automatically
+        let dir = std::env::temp_dir().join(format!("serun_test_patches_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("game.nes"), &rom).unwrap();
+        std::fs::write(dir.join("game.bps"), &bps).unwrap();
+        check(Cartidge::from_path(dir.join("game.nes")).unwrap());
+        std::fs::write(dir.join("hack.ips"), &ips).unwrap();
+        check(Cartidge::from_path_with_patch(dir.join("game.nes"), dir.join("hack.ips")).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // A disk side holding one 4 byte file, padded out to the full 65500 bytes
+    fn fds_side() -> Vec<u8> {
+        let mut side = b"\x01*NINTENDO-HVC*".to_vec();
+        side.resize(56, 0);
+        side.extend([0x02, 0x01]);
+        side.extend([0x03, 0x00, 0x00]);
+        side.extend(b"TESTFILE");
+        side.extend([0x00, 0x60, 0x04, 0x00, 0x00]);
+        side.extend([0x04, 0xAA, 0xBB, 0xCC, 0xDD]);
+        side.resize(65500, 0);
+        side
+    }
+
+    #[test]
+    fn test_fds() {
+        let mut bios = vec![0xEA; 0x2000];
+        bios[0x1FFC] = 0x24;
+        bios[0x1FFD] = 0xE0;
+        let mut image = b"FDS\x1A\x02".to_vec();
+        image.resize(16, 0);
+        image.extend(fds_side());
+        image.extend(fds_side());
+
+        assert_eq!(Cartidge::from_bytes(&image).unwrap_err(), CartidgeError::MissingFdsBios);
+        assert_eq!(Cartidge::from_bytes(&fds_side()).unwrap_err(), CartidgeError::MissingFdsBios);
+        assert_eq!(Cartidge::from_fds_bytes(&image, &bios[..0x1000]).unwrap_err(), CartidgeError::FdsBiosSize(0x1000));
+        assert_eq!(
+            Cartidge::from_fds_bytes(&image[..image.len() - 1], &bios).unwrap_err(),
+            CartidgeError::TruncatedDiskImage { expected: 131000, actual: 130999 }
+        );
+        // Without the fwNES header, the sides are counted from the size
+        let headerless = Cartidge::from_fds_bytes(&image[16..], &bios).unwrap();
+        let mut fds = Cartidge::from_fds_bytes(&image, &bios).unwrap();
+        assert_eq!(fds.crc32, headerless.crc32);
+        assert_eq!(fds.disk_side_count(), 2);
+        assert_eq!(fds.disk_side(), Some(0));
+        assert_eq!(fds.header.mapper, 20);
+
+        // BIOS, PRG RAM and CHR RAM
+        assert_eq!(fds.cpu_peek(0xFFFC), Some(0x24));
+        fds.cpu_write(0xE000, 0x00);
+        assert_eq!(fds.cpu_peek(0xE000), Some(0xEA));
+        fds.cpu_write(0x6000, 0x12);
+        fds.cpu_write(0xDFFF, 0x34);
+        assert_eq!(fds.cpu_peek(0x6000), Some(0x12));
+        assert_eq!(fds.cpu_peek(0xDFFF), Some(0x34));
+        fds.ppu_write(0x1FFF, 0x56);
+        assert_eq!(fds.ppu_read(0x1FFF), 0x56);
+
+        // Disk registers do nothing until enabled
+        fds.cpu_write(0x4025, 0x2E);
+        assert_eq!(fds.mirroring(), Mirroring::Vertical);
+        assert_eq!(fds.cpu_read(0x4032), None);
+        fds.cpu_write(0x4023, 0x83);
+        fds.cpu_write(0x4025, 0x2E);
+        assert_eq!(fds.mirroring(), Mirroring::Horizontal);
+
+        // One-shot timer IRQ, after the reload value + 1 cycles
+        fds.cpu_write(0x4020, 10);
+        fds.cpu_write(0x4021, 0);
+        fds.cpu_write(0x4022, 0x02);
+        for _ in 0..10 {
+            fds.cpu_clock();
+        }
+        assert!(!fds.irq());
+        fds.cpu_clock();
+        assert!(fds.irq());
+        assert_eq!(fds.cpu_read(0x4030).unwrap() & 0x01, 0x01);
+        assert!(!fds.irq());
+        for _ in 0..100 {
+            fds.cpu_clock();
+        }
+        assert!(!fds.irq());
+
+        // Read the start of the disk with transfer IRQs. The byte marking the
+        // end of the gap comes without one.
+        fds.cpu_write(0x4025, 0xE5);
+        let mut read = Vec::new();
+        let mut cycles = 0;
+        while read.len() < 15 && cycles < 1_000_000 {
+            fds.cpu_clock();
+            cycles += 1;
+            if fds.irq() {
+                assert_eq!(fds.cpu_peek(0x4030).unwrap() & 0x02, 0x02);
+                read.push(fds.cpu_read(0x4031).unwrap());
+                assert!(!fds.irq());
+            }
+        }
+        assert_eq!(read, b"\x01*NINTENDO-HVC*");
+        assert_eq!(fds.cpu_read(0x4032).unwrap() & 0x03, 0x00);
+
+        // Flipping the disk leaves the drive empty for a while
+        assert_eq!(fds.insert_disk(2).unwrap_err(), CartidgeError::NoSuchDiskSide { side: 2, count: 2 });
+        fds.insert_disk(1).unwrap();
+        assert_eq!(fds.disk_side(), Some(1));
+        assert_eq!(fds.cpu_read(0x4032).unwrap() & 0x07, 0x07);
+        for _ in 0..1_000_000 {
+            fds.cpu_clock();
+        }
+        assert_eq!(fds.cpu_read(0x4032).unwrap() & 0x05, 0x00);
+        fds.eject_disk();
+        assert_eq!(fds.disk_side(), None);
+        assert_eq!(fds.cpu_read(0x4032).unwrap() & 0x07, 0x07);
+    }
+
+    #[test]
+    fn test_fds_disk_save() {
+        let dir = std::env::temp_dir().join(format!("serun_test_fds_disk_save_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let image_path = dir.join("game.fds");
+        let bios_path = dir.join("disksys.rom");
+        let save_path = dir.join("game.sav");
+        std::fs::write(&image_path, fds_side()).unwrap();
+        std::fs::write(&bios_path, [0; 0x2000]).unwrap();
+
+        // Writes `data` over the start of the disk, a byte every 151 cycles
+        // once the head is back at the start
+        let write_disk = |data: u8, len: usize| {
+            let mut fds = Cartidge::from_fds_path(image_path.clone(), bios_path.clone()).unwrap();
+            assert_eq!(fds.save_path(), Some(save_path.as_path()));
+            fds.cpu_write(0x4023, 0x01);
+            fds.cpu_write(0x4024, data);
+            fds.cpu_write(0x4025, 0x41);
+            for _ in 0..50_001 + 151 * (len - 1) + 1 {
+                fds.cpu_clock();
+            }
+            fds.cpu_write(0x4025, 0x04);
+        };
+
+        // PRG RAM doesn't get saved, it's lost when the power goes
+        let mut fds = Cartidge::from_fds_path(image_path.clone(), bios_path.clone()).unwrap();
+        fds.cpu_write(0x6000, 0x12);
+        fds.flush_save().unwrap();
+        assert!(!save_path.exists());
+        drop(fds);
+
+        // The disk is, starting from what was saved last time
+        write_disk(0x5A, 10);
+        write_disk(0x33, 3);
+        let save = std::fs::read(&save_path).unwrap();
+        assert_eq!(&save[..11], [0x33, 0x33, 0x33, 0x5A, 0x5A, 0x5A, 0x5A, 0x5A, 0x5A, 0x5A, 0x00]);
+        // The image itself is left alone
+        assert_eq!(std::fs::read(&image_path).unwrap(), fds_side());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_fds_audio() {
+        let mut fds = Cartidge::from_fds_bytes(&fds_side(), &[0; 0x2000]).unwrap();
+        fds.cpu_write(0x4023, 0x83);
+        // A rising ramp, at full volume
+        fds.cpu_write(0x4089, 0x80);
+        for i in 0..64 {
+            fds.cpu_write(0x4040 + i, i as u8);
+        }
+        assert_eq!(fds.cpu_peek(0x407F), Some(63));
+        fds.cpu_write(0x4089, 0x00);
+        fds.cpu_write(0x4080, 0x80 | 0x20);
+        assert_eq!(fds.cpu_peek(0x4090), Some(0x20));
+        fds.cpu_write(0x4087, 0x80);
+        fds.cpu_write(0x4082, 0x00);
+        fds.cpu_write(0x4083, 0x04);
+        // 0x400 per cycle is 64 cycles per step, which gets to the end of the
+        // wave after 4095
+        let mut samples = Vec::new();
+        for _ in 0..4095 {
+            fds.cpu_clock();
+            samples.push(fds.expansion_audio());
+        }
+        assert_eq!(samples[0], 0.0);
+        assert_eq!(samples[63], 1.0 / 63.0);
+        assert_eq!(samples[4094], 1.0);
+        assert!(samples.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        // Master volume scales it down to 2/5. A frequency of 0 holds the
+        // wave where it is.
+        fds.cpu_write(0x4083, 0x00);
+        fds.cpu_write(0x4089, 0x03);
+        fds.cpu_clock();
+        assert_eq!(fds.expansion_audio(), 0.4);
+
+        // Halting the wave sends it back to the start
+        fds.cpu_write(0x4083, 0x80);
+        fds.cpu_clock();
+        assert_eq!(fds.expansion_audio(), 0.0);
+
+        // The volume envelope fades out, once every 8 * $E8 * 1 cycles
+        fds.cpu_write(0x4083, 0x04);
+        fds.cpu_write(0x4080, 0x00);
+        for _ in 0..8 * 0xE8 * 4 {
+            fds.cpu_clock();
+        }
+        assert_eq!(fds.cpu_peek(0x4090), Some(0x20 - 4));
+
+        // Sound registers are ignored while disabled, reads included
+        fds.cpu_write(0x4023, 0x01);
+        fds.cpu_write(0x4080, 0x80 | 0x3F);
+        assert_eq!(fds.cpu_peek(0x4090), None);
+        assert_eq!(fds.cpu_peek(0x4092), None);
+        fds.cpu_write(0x4023, 0x03);
+        assert_eq!(fds.cpu_peek(0x4090), Some(0x20 - 4));
+    }
+
+    #[test]
+    fn test_malformed_cartridges_never_panic() {
+        let mut corpus = vec![
+            vec![],
+            b"NES\x1A".to_vec(),
+            nes_file([0; 12], 0),
+            nes_file([0xFF; 12], 0),
+            nes_file([0xFF; 12], 0x10000),
+            // NES 2.0 with the biggest exponent-multiplier sizes there are
+            nes_file([0xFF, 0xFF, 0, 0x08, 0, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF], 0x100),
+            nes_file([0xFF, 0xFF, 0x04, 0x08, 0, 0xFF, 0, 0, 0, 0, 0, 0], 0x100),
+        ];
+        // Random headers with random amounts of data behind them, from a fixed
+        // seed so failures can be reproduced
+        let mut state: u32 = 0x1234_5678;
+        let mut next = || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+        let mappers = [0, 1, 2, 3, 4, 7, 9, 10, 21, 22, 23, 24, 25, 26, 66, 85];
+        for _ in 0..2000 {
+            let mut header = [0; 12];
+            header.iter_mut().for_each(|byte| *byte = next() as u8);
+            // Keep the mapper supported most of the time, so loading gets past
+            // the header checks
+            if next() % 4 != 0 {
+                let mapper = mappers[next() as usize % mappers.len()];
+                header[2] = (header[2] & 0x0F) | (mapper << 4);
+                header[3] = (header[3] & 0x0F) | (mapper & 0xF0);
+                header[4] = 0;
+                header[0] %= 4;
+                header[1] %= 4;
+                // NES 2.0 exponent sizes down to 256 bytes of PRG ROM, smaller
+                // than any bank
+                if next() % 4 == 0 {
+                    header[3] = (header[3] & 0xF3) | 0x08;
+                    header[5] |= 0x0F;
+                    header[0] = ((8 + next() % 9) << 2) as u8 | (header[0] & 0b11);
+                }
+            }
+            let size = next() as usize % 0x14000;
+            corpus.push(nes_file(header, size));
+        }
+
+        // UNIF files with random chunks, some of them sized past the end
+        let ids: [&[u8]; 6] = [b"MAPR", b"PRG0", b"CHR0", b"MIRR", b"BATR", b"XXXX"];
+        for _ in 0..500 {
+            let mut chunks = Vec::new();
+            for _ in 0..next() % 6 {
+                let data: Vec<u8> = (0..next() % 0x100).map(|_| next() as u8).collect();
+                chunks.push((ids[next() as usize % ids.len()], data));
+            }
+            if next() % 2 == 0 {
+                chunks.push((b"MAPR", b"NES-SLROM".to_vec()));
+            }
+            let chunks: Vec<(&[u8], &[u8])> = chunks.iter().map(|(id, data)| (*id, &data[..])).collect();
+            let mut file = unif_file(&chunks);
+            if next() % 4 == 0 {
+                let len = next() as usize % (file.len() + 1);
+                file.truncate(len);
+            }
+            corpus.push(file);
+        }
+
+        // Disk images with random blocks, which mustn't throw off laying them
+        // out with gaps
+        for _ in 0..50 {
+            let mut side = fds_side();
+            for _ in 0..next() % 64 {
+                let index = next() as usize % 200;
+                side[index] = next() as u8 % 6;
+            }
+            let _ = Cartidge::from_fds_bytes(&side, &[0; 0x2000]);
+            side.truncate(next() as usize % side.len());
+            let _ = Cartidge::from_fds_bytes(&side, &[0; 0x2000]);
+        }
+
+        // Whatever loads gets poked at a little too, since odd sizes can
+        // throw off bank arithmetic
+        for file in corpus {
+            let Ok(mut cartridge) = Cartidge::from_bytes(&file) else {
+                continue;
+            };
+            for addr in [0x6000, 0x8000, 0xA000, 0xC000, 0xE000, 0xFFFC, 0xFFFF] {
+                cartridge.cpu_read(addr);
+                cartridge.cpu_write(addr, next() as u8);
+                cartridge.cpu_peek(addr);
+            }
+            for addr in [0x0000, 0x0FFF, 0x1000, 0x1FFF] {
+                cartridge.ppu_read(addr);
+                cartridge.ppu_write(addr, next() as u8);
+            }
+            cartridge.cpu_clock();
+        }
+    }
+
     #[test]
     fn test_rol() {
         let mut cpu: CPU = Default::default();
@@ -74,8 +1662,20 @@ mod test {
         cpu.reset();
         cpu.status = 0b1010_0111;
         cpu.register_a = 0b0011_1100;
-        cpu.rol(&instruction);
+        cpu.rol(&instruction).unwrap();
         assert_eq!(cpu.register_a, 0b0111_1001);
         assert_eq!(cpu.status, 0b0010_0100);
     }
+
+    #[test]
+    fn test_unsupported_addressing_mode() {
+        let mut cpu: CPU = Default::default();
+        let instruction = Instruction { opcode: Opcode::ROL, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate };
+        cpu.reset();
+        cpu.pc = 0x0201;
+        assert_eq!(
+            cpu.rol(&instruction),
+            Err(CpuError::UnsupportedAddressingMode { mode: AddressingMode::Immediate, address: 0x0200 })
+        );
+    }
 }
@@ -1,3 +1,5 @@
+use crate::bus::Bus;
+
 #[derive(Clone, Debug)]
 pub struct Memory {
     pub raw_memory: Vec<u8>,
@@ -11,21 +13,21 @@ impl Default for Memory {
     }
 }
 
-impl Memory {
-    pub fn read(&self, addr: u16) -> u8 {
+impl Bus for Memory {
+    fn read(&mut self, addr: u16) -> u8 {
         self.raw_memory[addr as usize]
     }
 
-    pub fn write(&mut self, addr: u16, data: u8) {
+    fn write(&mut self, addr: u16, data: u8) {
         self.raw_memory[addr as usize] = data;
     }
 
-    pub fn read_u16(&mut self, pos: u16) -> u16 {
-        let lo = self.read(pos) as u16;
-        let hi = self.read(pos.wrapping_add(1)) as u16;
-        (hi << 8) | lo
+    fn peek(&self, addr: u16) -> u8 {
+        self.raw_memory[addr as usize]
     }
+}
 
+impl Memory {
     pub fn write_u16(&mut self, pos: u16, data: u16) {
         let hi = (data >> 8) as u8;
         let lo = (data & 0xFF) as u8;
@@ -37,4 +39,4 @@ impl Memory {
         self.raw_memory[0x8000..(0x8000 + program.len())].copy_from_slice(&program[..]);
         self.write_u16(0xFFFC, 0x8000);
     }
-}
\ No newline at end of file
+}
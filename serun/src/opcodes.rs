@@ -1,6 +1,6 @@
 use phf::phf_map;
 
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
 #[allow(non_camel_case_types)]
 pub enum AddressingMode {
     Immediate,
@@ -74,7 +74,62 @@ pub enum Opcode {
     TSX,
     TXA,
     TXS,
-    TYA
+    TYA,
+
+    // Unofficial opcodes
+    ALR,
+    ANC,
+    ANE,
+    ARR,
+    DCP,
+    ISB,
+    JAM,
+    LAS,
+    LAX,
+    LXA,
+    RLA,
+    RRA,
+    SAX,
+    SBX,
+    SHA,
+    SHX,
+    SHY,
+    SLO,
+    SRE,
+    TAS
+}
+
+impl Opcode {
+    pub fn is_unofficial(&self) -> bool {
+        matches!(
+            self,
+            Opcode::ALR | Opcode::ANC | Opcode::ANE | Opcode::ARR | Opcode::DCP |
+            Opcode::ISB | Opcode::JAM | Opcode::LAS | Opcode::LAX | Opcode::LXA |
+            Opcode::RLA | Opcode::RRA | Opcode::SAX | Opcode::SBX | Opcode::SHA |
+            Opcode::SHX | Opcode::SHY | Opcode::SLO | Opcode::SRE | Opcode::TAS
+        )
+    }
+
+    pub fn is_branch(&self) -> bool {
+        matches!(
+            self,
+            Opcode::BCC | Opcode::BCS | Opcode::BEQ | Opcode::BMI |
+            Opcode::BNE | Opcode::BPL | Opcode::BVC | Opcode::BVS
+        )
+    }
+
+    // Read instructions using indexed addressing take an extra cycle when the
+    // index pushes the effective address into the next page. Stores and
+    // read-modify-write instructions always spend that cycle, so it is already
+    // included in their base cycle count.
+    pub fn has_page_cross_penalty(&self) -> bool {
+        matches!(
+            self,
+            Opcode::ADC | Opcode::AND | Opcode::CMP | Opcode::EOR | Opcode::LDA |
+            Opcode::LDX | Opcode::LDY | Opcode::ORA | Opcode::SBC | Opcode::LAX |
+            Opcode::LAS | Opcode::NOP
+        )
+    }
 }
 
 #[derive(Debug)]
@@ -108,7 +163,7 @@ pub static CPU_OPCODES: phf::Map<u8, Instruction> = phf_map! {
         0x31u8 => Instruction { opcode: Opcode::AND, bytes: 2, cycles: 5, addressing_mode: AddressingMode::Indirect_Y },         // (+1 if page crossed)
 
         // ASL
-        0x0Au8 => Instruction { opcode: Opcode::ASL, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x0Au8 => Instruction { opcode: Opcode::ASL, bytes: 1, cycles: 2, addressing_mode: AddressingMode::Accumulator },
         0x06u8 => Instruction { opcode: Opcode::ASL, bytes: 2, cycles: 5, addressing_mode: AddressingMode::ZeroPage },
         0x16u8 => Instruction { opcode: Opcode::ASL, bytes: 2, cycles: 6, addressing_mode: AddressingMode::ZeroPage_X },
         0x0Eu8 => Instruction { opcode: Opcode::ASL, bytes: 3, cycles: 6, addressing_mode: AddressingMode::Absolute },
@@ -238,12 +293,12 @@ pub static CPU_OPCODES: phf::Map<u8, Instruction> = phf_map! {
         // LDY
         0xA0u8 => Instruction { opcode: Opcode::LDY, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
         0xA4u8 => Instruction { opcode: Opcode::LDY, bytes: 2, cycles: 3, addressing_mode: AddressingMode::ZeroPage },
-        0xB4u8 => Instruction { opcode: Opcode::LDY, bytes: 2, cycles: 4, addressing_mode: AddressingMode::ZeroPage_Y },
+        0xB4u8 => Instruction { opcode: Opcode::LDY, bytes: 2, cycles: 4, addressing_mode: AddressingMode::ZeroPage_X },
         0xACu8 => Instruction { opcode: Opcode::LDY, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute },
-        0xBCu8 => Instruction { opcode: Opcode::LDY, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute_Y },          // (+1 if page crossed)
+        0xBCu8 => Instruction { opcode: Opcode::LDY, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute_X },          // (+1 if page crossed)
 
         // LSR
-        0x4Au8 => Instruction { opcode: Opcode::LSR, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x4Au8 => Instruction { opcode: Opcode::LSR, bytes: 1, cycles: 2, addressing_mode: AddressingMode::Accumulator },
         0x46u8 => Instruction { opcode: Opcode::LSR, bytes: 2, cycles: 5, addressing_mode: AddressingMode::ZeroPage },
         0x56u8 => Instruction { opcode: Opcode::LSR, bytes: 2, cycles: 6, addressing_mode: AddressingMode::ZeroPage_X },
         0x4Eu8 => Instruction { opcode: Opcode::LSR, bytes: 3, cycles: 6, addressing_mode: AddressingMode::Absolute },
@@ -282,7 +337,7 @@ pub static CPU_OPCODES: phf::Map<u8, Instruction> = phf_map! {
         0x3Eu8 => Instruction { opcode: Opcode::ROL, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_X },
 
         // ROR
-        0x6Au8 => Instruction { opcode: Opcode::ROR, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x6Au8 => Instruction { opcode: Opcode::ROR, bytes: 1, cycles: 2, addressing_mode: AddressingMode::Accumulator },
         0x66u8 => Instruction { opcode: Opcode::ROR, bytes: 2, cycles: 5, addressing_mode: AddressingMode::ZeroPage },
         0x76u8 => Instruction { opcode: Opcode::ROR, bytes: 2, cycles: 6, addressing_mode: AddressingMode::ZeroPage_X },
         0x6Eu8 => Instruction { opcode: Opcode::ROR, bytes: 3, cycles: 6, addressing_mode: AddressingMode::Absolute },
@@ -329,7 +384,7 @@ pub static CPU_OPCODES: phf::Map<u8, Instruction> = phf_map! {
 
         // STY
         0x84u8 => Instruction { opcode: Opcode::STY, bytes: 2, cycles: 3, addressing_mode: AddressingMode::ZeroPage },
-        0x94u8 => Instruction { opcode: Opcode::STY, bytes: 2, cycles: 4, addressing_mode: AddressingMode::ZeroPage_Y },
+        0x94u8 => Instruction { opcode: Opcode::STY, bytes: 2, cycles: 4, addressing_mode: AddressingMode::ZeroPage_X },
         0x8Cu8 => Instruction { opcode: Opcode::STY, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute },
 
         // TAX
@@ -345,8 +400,160 @@ pub static CPU_OPCODES: phf::Map<u8, Instruction> = phf_map! {
         0x8Au8 => Instruction { opcode: Opcode::TXA, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
 
         // TXS
-        0x9Au8 => Instruction { opcode: Opcode::TSX, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x9Au8 => Instruction { opcode: Opcode::TXS, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
 
         // TYA
         0x98u8 => Instruction { opcode: Opcode::TYA, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+
+        // Unofficial opcodes. These aren't documented, but they are stable enough
+        // on the NES's 6502 that some games rely on them.
+
+        // ALR
+        0x4Bu8 => Instruction { opcode: Opcode::ALR, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+
+        // ANC
+        0x0Bu8 => Instruction { opcode: Opcode::ANC, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+        0x2Bu8 => Instruction { opcode: Opcode::ANC, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+
+        // ANE (unstable)
+        0x8Bu8 => Instruction { opcode: Opcode::ANE, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+
+        // ARR
+        0x6Bu8 => Instruction { opcode: Opcode::ARR, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+
+        // DCP
+        0xC7u8 => Instruction { opcode: Opcode::DCP, bytes: 2, cycles: 5, addressing_mode: AddressingMode::ZeroPage },
+        0xD7u8 => Instruction { opcode: Opcode::DCP, bytes: 2, cycles: 6, addressing_mode: AddressingMode::ZeroPage_X },
+        0xCFu8 => Instruction { opcode: Opcode::DCP, bytes: 3, cycles: 6, addressing_mode: AddressingMode::Absolute },
+        0xDFu8 => Instruction { opcode: Opcode::DCP, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_X },
+        0xDBu8 => Instruction { opcode: Opcode::DCP, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_Y },
+        0xC3u8 => Instruction { opcode: Opcode::DCP, bytes: 2, cycles: 8, addressing_mode: AddressingMode::Indirect_X },
+        0xD3u8 => Instruction { opcode: Opcode::DCP, bytes: 2, cycles: 8, addressing_mode: AddressingMode::Indirect_Y },
+
+        // ISB
+        0xE7u8 => Instruction { opcode: Opcode::ISB, bytes: 2, cycles: 5, addressing_mode: AddressingMode::ZeroPage },
+        0xF7u8 => Instruction { opcode: Opcode::ISB, bytes: 2, cycles: 6, addressing_mode: AddressingMode::ZeroPage_X },
+        0xEFu8 => Instruction { opcode: Opcode::ISB, bytes: 3, cycles: 6, addressing_mode: AddressingMode::Absolute },
+        0xFFu8 => Instruction { opcode: Opcode::ISB, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_X },
+        0xFBu8 => Instruction { opcode: Opcode::ISB, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_Y },
+        0xE3u8 => Instruction { opcode: Opcode::ISB, bytes: 2, cycles: 8, addressing_mode: AddressingMode::Indirect_X },
+        0xF3u8 => Instruction { opcode: Opcode::ISB, bytes: 2, cycles: 8, addressing_mode: AddressingMode::Indirect_Y },
+
+        // JAM (halts the CPU until it is reset)
+        0x02u8 => Instruction { opcode: Opcode::JAM, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x12u8 => Instruction { opcode: Opcode::JAM, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x22u8 => Instruction { opcode: Opcode::JAM, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x32u8 => Instruction { opcode: Opcode::JAM, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x42u8 => Instruction { opcode: Opcode::JAM, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x52u8 => Instruction { opcode: Opcode::JAM, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x62u8 => Instruction { opcode: Opcode::JAM, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x72u8 => Instruction { opcode: Opcode::JAM, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x92u8 => Instruction { opcode: Opcode::JAM, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0xB2u8 => Instruction { opcode: Opcode::JAM, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0xD2u8 => Instruction { opcode: Opcode::JAM, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0xF2u8 => Instruction { opcode: Opcode::JAM, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+
+        // LAS
+        0xBBu8 => Instruction { opcode: Opcode::LAS, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute_Y },             // (+1 if page crossed)
+
+        // LAX
+        0xA7u8 => Instruction { opcode: Opcode::LAX, bytes: 2, cycles: 3, addressing_mode: AddressingMode::ZeroPage },
+        0xB7u8 => Instruction { opcode: Opcode::LAX, bytes: 2, cycles: 4, addressing_mode: AddressingMode::ZeroPage_Y },
+        0xAFu8 => Instruction { opcode: Opcode::LAX, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute },
+        0xBFu8 => Instruction { opcode: Opcode::LAX, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute_Y },             // (+1 if page crossed)
+        0xA3u8 => Instruction { opcode: Opcode::LAX, bytes: 2, cycles: 6, addressing_mode: AddressingMode::Indirect_X },
+        0xB3u8 => Instruction { opcode: Opcode::LAX, bytes: 2, cycles: 5, addressing_mode: AddressingMode::Indirect_Y },             // (+1 if page crossed)
+
+        // LXA (unstable)
+        0xABu8 => Instruction { opcode: Opcode::LXA, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+
+        // NOP
+        0x1Au8 => Instruction { opcode: Opcode::NOP, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x3Au8 => Instruction { opcode: Opcode::NOP, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x5Au8 => Instruction { opcode: Opcode::NOP, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x7Au8 => Instruction { opcode: Opcode::NOP, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0xDAu8 => Instruction { opcode: Opcode::NOP, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0xFAu8 => Instruction { opcode: Opcode::NOP, bytes: 1, cycles: 2, addressing_mode: AddressingMode::NoneAddressing },
+        0x80u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+        0x82u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+        0x89u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+        0xC2u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+        0xE2u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+        0x04u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 3, addressing_mode: AddressingMode::ZeroPage },
+        0x44u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 3, addressing_mode: AddressingMode::ZeroPage },
+        0x64u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 3, addressing_mode: AddressingMode::ZeroPage },
+        0x14u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 4, addressing_mode: AddressingMode::ZeroPage_X },
+        0x34u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 4, addressing_mode: AddressingMode::ZeroPage_X },
+        0x54u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 4, addressing_mode: AddressingMode::ZeroPage_X },
+        0x74u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 4, addressing_mode: AddressingMode::ZeroPage_X },
+        0xD4u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 4, addressing_mode: AddressingMode::ZeroPage_X },
+        0xF4u8 => Instruction { opcode: Opcode::NOP, bytes: 2, cycles: 4, addressing_mode: AddressingMode::ZeroPage_X },
+        0x0Cu8 => Instruction { opcode: Opcode::NOP, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute },
+        0x1Cu8 => Instruction { opcode: Opcode::NOP, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute_X },             // (+1 if page crossed)
+        0x3Cu8 => Instruction { opcode: Opcode::NOP, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute_X },             // (+1 if page crossed)
+        0x5Cu8 => Instruction { opcode: Opcode::NOP, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute_X },             // (+1 if page crossed)
+        0x7Cu8 => Instruction { opcode: Opcode::NOP, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute_X },             // (+1 if page crossed)
+        0xDCu8 => Instruction { opcode: Opcode::NOP, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute_X },             // (+1 if page crossed)
+        0xFCu8 => Instruction { opcode: Opcode::NOP, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute_X },             // (+1 if page crossed)
+
+        // RLA
+        0x27u8 => Instruction { opcode: Opcode::RLA, bytes: 2, cycles: 5, addressing_mode: AddressingMode::ZeroPage },
+        0x37u8 => Instruction { opcode: Opcode::RLA, bytes: 2, cycles: 6, addressing_mode: AddressingMode::ZeroPage_X },
+        0x2Fu8 => Instruction { opcode: Opcode::RLA, bytes: 3, cycles: 6, addressing_mode: AddressingMode::Absolute },
+        0x3Fu8 => Instruction { opcode: Opcode::RLA, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_X },
+        0x3Bu8 => Instruction { opcode: Opcode::RLA, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_Y },
+        0x23u8 => Instruction { opcode: Opcode::RLA, bytes: 2, cycles: 8, addressing_mode: AddressingMode::Indirect_X },
+        0x33u8 => Instruction { opcode: Opcode::RLA, bytes: 2, cycles: 8, addressing_mode: AddressingMode::Indirect_Y },
+
+        // RRA
+        0x67u8 => Instruction { opcode: Opcode::RRA, bytes: 2, cycles: 5, addressing_mode: AddressingMode::ZeroPage },
+        0x77u8 => Instruction { opcode: Opcode::RRA, bytes: 2, cycles: 6, addressing_mode: AddressingMode::ZeroPage_X },
+        0x6Fu8 => Instruction { opcode: Opcode::RRA, bytes: 3, cycles: 6, addressing_mode: AddressingMode::Absolute },
+        0x7Fu8 => Instruction { opcode: Opcode::RRA, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_X },
+        0x7Bu8 => Instruction { opcode: Opcode::RRA, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_Y },
+        0x63u8 => Instruction { opcode: Opcode::RRA, bytes: 2, cycles: 8, addressing_mode: AddressingMode::Indirect_X },
+        0x73u8 => Instruction { opcode: Opcode::RRA, bytes: 2, cycles: 8, addressing_mode: AddressingMode::Indirect_Y },
+
+        // SAX
+        0x87u8 => Instruction { opcode: Opcode::SAX, bytes: 2, cycles: 3, addressing_mode: AddressingMode::ZeroPage },
+        0x97u8 => Instruction { opcode: Opcode::SAX, bytes: 2, cycles: 4, addressing_mode: AddressingMode::ZeroPage_Y },
+        0x8Fu8 => Instruction { opcode: Opcode::SAX, bytes: 3, cycles: 4, addressing_mode: AddressingMode::Absolute },
+        0x83u8 => Instruction { opcode: Opcode::SAX, bytes: 2, cycles: 6, addressing_mode: AddressingMode::Indirect_X },
+
+        // SBC
+        0xEBu8 => Instruction { opcode: Opcode::SBC, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+
+        // SBX
+        0xCBu8 => Instruction { opcode: Opcode::SBX, bytes: 2, cycles: 2, addressing_mode: AddressingMode::Immediate },
+
+        // SHA (unstable)
+        0x9Fu8 => Instruction { opcode: Opcode::SHA, bytes: 3, cycles: 5, addressing_mode: AddressingMode::Absolute_Y },
+        0x93u8 => Instruction { opcode: Opcode::SHA, bytes: 2, cycles: 6, addressing_mode: AddressingMode::Indirect_Y },
+
+        // SHX (unstable)
+        0x9Eu8 => Instruction { opcode: Opcode::SHX, bytes: 3, cycles: 5, addressing_mode: AddressingMode::Absolute_Y },
+
+        // SHY (unstable)
+        0x9Cu8 => Instruction { opcode: Opcode::SHY, bytes: 3, cycles: 5, addressing_mode: AddressingMode::Absolute_X },
+
+        // SLO
+        0x07u8 => Instruction { opcode: Opcode::SLO, bytes: 2, cycles: 5, addressing_mode: AddressingMode::ZeroPage },
+        0x17u8 => Instruction { opcode: Opcode::SLO, bytes: 2, cycles: 6, addressing_mode: AddressingMode::ZeroPage_X },
+        0x0Fu8 => Instruction { opcode: Opcode::SLO, bytes: 3, cycles: 6, addressing_mode: AddressingMode::Absolute },
+        0x1Fu8 => Instruction { opcode: Opcode::SLO, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_X },
+        0x1Bu8 => Instruction { opcode: Opcode::SLO, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_Y },
+        0x03u8 => Instruction { opcode: Opcode::SLO, bytes: 2, cycles: 8, addressing_mode: AddressingMode::Indirect_X },
+        0x13u8 => Instruction { opcode: Opcode::SLO, bytes: 2, cycles: 8, addressing_mode: AddressingMode::Indirect_Y },
+
+        // SRE
+        0x47u8 => Instruction { opcode: Opcode::SRE, bytes: 2, cycles: 5, addressing_mode: AddressingMode::ZeroPage },
+        0x57u8 => Instruction { opcode: Opcode::SRE, bytes: 2, cycles: 6, addressing_mode: AddressingMode::ZeroPage_X },
+        0x4Fu8 => Instruction { opcode: Opcode::SRE, bytes: 3, cycles: 6, addressing_mode: AddressingMode::Absolute },
+        0x5Fu8 => Instruction { opcode: Opcode::SRE, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_X },
+        0x5Bu8 => Instruction { opcode: Opcode::SRE, bytes: 3, cycles: 7, addressing_mode: AddressingMode::Absolute_Y },
+        0x43u8 => Instruction { opcode: Opcode::SRE, bytes: 2, cycles: 8, addressing_mode: AddressingMode::Indirect_X },
+        0x53u8 => Instruction { opcode: Opcode::SRE, bytes: 2, cycles: 8, addressing_mode: AddressingMode::Indirect_Y },
+
+        // TAS (unstable)
+        0x9Bu8 => Instruction { opcode: Opcode::TAS, bytes: 3, cycles: 5, addressing_mode: AddressingMode::Absolute_Y },
     };
\ No newline at end of file